@@ -0,0 +1,39 @@
+use crate::error::BrowserError;
+use crate::url::URL;
+
+/// The HTML for an about: page, which is made up in-process rather than
+/// loaded from anywhere
+pub(crate) fn about_page(url: &URL) -> Result<String, BrowserError> {
+    match url.path.as_str() {
+        "blank" => Ok(String::new()),
+        "version" => Ok(format!(
+            "<h1>{}</h1><p>Version {}</p><p>Built for {}-{}, {}</p>",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::ARCH,
+            std::env::consts::OS,
+            if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+        )),
+        page => Err(BrowserError::Url(format!("Unknown page about:{}", page))),
+    }
+}
+
+/// The HTML for the page shown in place of one that couldn't be loaded
+pub(crate) fn error_page(address: &str, err: &BrowserError) -> String {
+    format!(
+        "<p><b>Couldn't load {}</b></p><p>{}</p>",
+        escape(address),
+        escape(&err.to_string())
+    )
+}
+
+/// Escapes `text` so that it shows as it is in HTML
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::about::escape;
+use crate::http::Response;
+use crate::intercept::RequestInterceptor;
+use crate::url::URL;
+
+/// Hosts and URLs that are never requested, like those of ads and trackers.
+/// Requests for them are answered with a placeholder instead, so a blocked
+/// page says so and a blocked image shows its alt text.
+pub struct Blocklist {
+    // Entries without a "/", which block a host along with its subdomains
+    hosts: Vec<String>,
+    // Entries with one, which block any URL whose host and path contain them
+    patterns: Vec<String>,
+    blocked: AtomicUsize,
+    // Each request blocked is logged to stderr from 1
+    pub verbosity: u8,
+}
+
+impl Blocklist {
+    /// Reads a blocklist from `text`, with one host or URL part per line.
+    /// Empty lines and ones starting with "#" are skipped.
+    pub fn parse(text: &str) -> Self {
+        let (hosts, patterns) = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_ascii_lowercase)
+            .partition(|entry| !entry.contains('/'));
+        Self {
+            hosts,
+            patterns,
+            blocked: AtomicUsize::new(0),
+            verbosity: 0,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Whether requests for `url` are blocked
+    pub fn blocks(&self, url: &URL) -> bool {
+        let host = url.host.to_ascii_lowercase();
+        let in_domain = |entry: &String| {
+            host.strip_suffix(entry.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+        };
+        if self.hosts.iter().any(in_domain) {
+            return true;
+        }
+        let address = format!("{}{}", host, url.path.to_ascii_lowercase());
+        self.patterns
+            .iter()
+            .any(|pattern| address.contains(pattern.as_str()))
+    }
+
+    /// How many requests have been blocked so far
+    pub fn blocked(&self) -> usize {
+        self.blocked.load(Ordering::Relaxed)
+    }
+}
+
+impl RequestInterceptor for Blocklist {
+    fn intercept(
+        &self,
+        _method: &str,
+        url: &mut URL,
+        _headers: &mut Vec<(String, String)>,
+    ) -> Option<Response> {
+        if !self.blocks(url) {
+            return None;
+        }
+        let blocked = self.blocked.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.verbosity >= 1 {
+            eprintln!("* Blocked {} ({} blocked so far)", url, blocked);
+        }
+        let headers = HashMap::from([("content-type".to_owned(), vec!["text/html".to_owned()])]);
+        let body = format!(
+            "<p><b>Blocked</b> {} is on the blocklist",
+            escape(&url.to_string())
+        )
+        .into_bytes();
+        Some(Response::new(403, headers, body))
+    }
+}
@@ -0,0 +1,1680 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use ab_glyph::{Font, ScaleFont};
+use rustybuzz::{UnicodeBuffer, shape};
+
+use image::RgbaImage;
+
+use crate::about::{about_page, error_page, escape};
+use crate::charset::decode;
+use crate::dom::{Node, descendants, elements, head, parse, section};
+use crate::error::BrowserError;
+use crate::font::{FontManager, FontProperties, FontSize, FontStyle, FontWeight};
+use crate::form::{FORM_CONTENT_TYPE, Form, INPUT_PADDING, InputItem};
+use crate::glyph::GlyphCache;
+use crate::http::{BodyKind, HttpClient, Progress, RequestBody, Response};
+use crate::images::{DEFAULT_IMAGE_CACHE_SIZE, ImageCache};
+use crate::json::pretty_print;
+use crate::layout::{
+    DisplayItem, ImageItem, LINE_HEIGHT, LINK_COLOR, Layout, TEXT_COLOR, TextItem,
+    VISITED_LINK_COLOR,
+};
+use crate::lexer::{Token, is_word_break, lex, preformatted, words};
+use crate::loading::{LOAD_POLL_INTERVAL, Loading};
+use crate::reader::{READER_FONT_SIZE, READER_WIDTH, main_content};
+use crate::url::URL;
+use crate::vertical::Vertical;
+use crate::zoom::DEFAULT_ZOOM;
+use crate::{HSTEP, VSTEP};
+
+// How quickly smooth scrolling closes the gap to its target, per second
+pub(crate) const SCROLL_EASING: f32 = 15.0;
+pub(crate) const FIND_BAR_HEIGHT: u32 = 30;
+pub(crate) const FIND_BAR_COLOR: [u8; 3] = [230, 230, 230];
+pub(crate) const FIND_MATCH_COLOR: [u8; 3] = [255, 240, 100];
+pub(crate) const FIND_CURRENT_COLOR: [u8; 3] = [255, 160, 50];
+pub(crate) const SELECTION_COLOR: [u8; 3] = [180, 210, 255];
+pub(crate) const TOOLTIP_DELAY: Duration = Duration::from_millis(500);
+// How long the window has to stay the same size before the page is laid out
+// again for it, so dragging its edge doesn't lay it out at every step
+pub(crate) const RESIZE_SETTLE: Duration = Duration::from_millis(100);
+pub(crate) const TOOLTIP_COLOR: [u8; 3] = [255, 255, 225];
+pub(crate) const TOOLTIP_BORDER_COLOR: [u8; 3] = [120, 120, 120];
+pub(crate) const TOOLTIP_PADDING: u32 = 4;
+pub(crate) const PLACEHOLDER_BORDER_COLOR: [u8; 3] = [160, 160, 160];
+pub(crate) const FOCUS_BORDER_COLOR: [u8; 3] = [60, 120, 220];
+pub(crate) const BUTTON_COLOR: [u8; 3] = [225, 225, 225];
+pub(crate) const STATUS_BAR_HEIGHT: u32 = 20;
+pub(crate) const STATUS_BAR_COLOR: [u8; 3] = [240, 240, 240];
+// The scrollbar along the bottom of pages wider than the window
+pub(crate) const SCROLLBAR_HEIGHT: u32 = 8;
+pub(crate) const SCROLLBAR_COLOR: [u8; 3] = [235, 235, 235];
+pub(crate) const SCROLLBAR_THUMB_COLOR: [u8; 3] = [170, 170, 170];
+pub(crate) const SCROLLBAR_MIN_THUMB: u32 = 20;
+// How far each press of an arrow key, or line of the mouse wheel, scrolls
+// unless set otherwise
+pub(crate) const SCROLL_STEP: u32 = 20;
+// How far synthesized oblique text leans right, per pixel above the baseline
+pub(crate) const OBLIQUE_SKEW: f32 = 0.2;
+// How far below the baseline underlines go, as a fraction of the px size
+pub(crate) const UNDERLINE_OFFSET: f32 = 0.1;
+// Text decorations are the px size divided by this thick, but at least 1px
+pub(crate) const DECORATION_THICKNESS: f32 = 16.0;
+// Glyph coverage is blended as it is unless set otherwise
+pub(crate) const DEFAULT_GAMMA: f32 = 1.0;
+// Without anti-aliasing, pixels a glyph covers at least this much of are
+// drawn in full and the rest not at all
+pub(crate) const COVERAGE_THRESHOLD: f32 = 0.5;
+
+pub struct Browser {
+    pub(crate) scroll: u32,
+    // Smooth scrolling eases `scroll_position` towards `scroll_target`, and
+    // `scroll` follows it rounded to whole pixels
+    pub smooth_scroll: bool,
+    // How far each press of an arrow key, or line of the mouse wheel, scrolls
+    pub scroll_step: u32,
+    // Scrolls the page along with the mouse wheel ("natural" scrolling)
+    // rather than against it
+    pub reverse_scroll: bool,
+    // Glyph coverage is raised to 1 / gamma before blending, so above 1 text
+    // comes out heavier and below 1 lighter
+    pub gamma: f32,
+    // Without it glyphs are drawn with hard edges, each pixel either in the
+    // text's color or left as it was
+    pub antialias: bool,
+    // Caps the width of the text column on wide windows, centering it
+    pub max_content_width: Option<u32>,
+    // How tall lines are, as a multiple of the font's height
+    pub line_height: f32,
+    // The smallest font size text is shown at, in pt
+    pub min_font_size: Option<u32>,
+    pub(crate) scroll_target: u32,
+    pub(crate) scroll_position: f32,
+    // How far right the page is scrolled, for content wider than the window.
+    // This isn't smoothed.
+    pub(crate) scroll_x: u32,
+    pub(crate) scroll_velocity: f32,
+    pub(crate) last_tick: Option<Instant>,
+    // The page currently shown, set once it has loaded successfully
+    pub(crate) current_url: Option<URL>,
+    // What relative URLs on the page resolve against: the page's first
+    // <base href>, or else the page itself
+    pub(crate) base: Option<URL>,
+    // The page as a tree of elements and text
+    pub(crate) document: Vec<Node>,
+    // The text of linked stylesheets, keyed by their href as written
+    pub(crate) stylesheets: HashMap<String, String>,
+    // Decoded <img> sources, keyed by their src attribute as written
+    pub(crate) images: HashMap<String, Rc<RgbaImage>>,
+    // Images decoded for this page and earlier ones, by URL
+    pub(crate) image_cache: RefCell<ImageCache>,
+    pub(crate) display_list: Vec<DisplayItem>,
+    // Where each heading on the page starts, top to bottom
+    pub(crate) headings: Vec<u32>,
+    // Only the page's main content is laid out in reader mode
+    pub(crate) reader_mode: bool,
+    pub(crate) font_manager: FontManager,
+    // Filled in while drawing, which only borrows the browser
+    pub(crate) glyph_cache: RefCell<GlyphCache>,
+    // The family used for text that doesn't ask for another
+    pub(crate) font_family: String,
+    pub(crate) http_client: HttpClient,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    // Set whenever the frame needs to be repainted (scroll, resize or new
+    // content) and cleared once it has been drawn
+    pub dirty: bool,
+    pub(crate) find: Option<FindState>,
+    // Where a mouse selection started and where it currently ends, as a
+    // display item index and a byte offset into its source text
+    pub(crate) selection: Option<((usize, usize), (usize, usize))>,
+    pub(crate) tooltip: Option<Tooltip>,
+    // The target of the link under the mouse cursor, shown in a strip along
+    // the bottom of the window
+    pub(crate) status: Option<TextItem>,
+    pub(crate) forms: Vec<Form>,
+    // What has been typed into the page's inputs, by input index, and the
+    // input being typed into
+    pub(crate) input_values: HashMap<usize, String>,
+    pub(crate) focus: Option<usize>,
+    // Every page loaded so far, so links to them can be told apart
+    pub(crate) visited: HashSet<String>,
+    // The colors of links in the browser's own stylesheet, which the page's
+    // can override
+    pub link_color: [u8; 3],
+    pub visited_link_color: [u8; 3],
+    // Experimental: lays text out in columns running top to bottom, placed
+    // right to left, as Japanese and Chinese can be written
+    pub vertical_text: bool,
+    // The page loading in the background, if any, which takes the place of
+    // this one once it starts to arrive
+    pub(crate) loading: Option<Loading>,
+    // When the page's <meta http-equiv="refresh"> is due, and where it goes
+    pub(crate) refresh: Option<(Instant, URL)>,
+    // The page's icon, once it's been fetched
+    pub(crate) favicon: Option<Option<Rc<RgbaImage>>>,
+    // Where the icon arrives while it's being fetched in the background
+    pub(crate) favicon_loading: Option<Receiver<Progress>>,
+    // How far the page is zoomed, in percent, and the zoom chosen for each
+    // host, which tabs share
+    pub(crate) zoom: u32,
+    pub(crate) site_zoom: Rc<RefCell<HashMap<String, u32>>>,
+    // When the window was last resized, while the page is still laid out
+    // for its old size
+    pub(crate) resized: Option<Instant>,
+}
+
+/// A title under the mouse cursor, which is shown once the mouse has rested
+/// on it for `TOOLTIP_DELAY`
+pub(crate) struct Tooltip {
+    pub(crate) title: Rc<str>,
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) since: Instant,
+    pub(crate) label: Option<TextItem>,
+}
+
+/// A display item index and a byte range within its source text
+pub(crate) type TextPosition = (usize, Range<usize>);
+
+/// A text match within the display list, with one range per display item it
+/// spans
+pub(crate) type FindMatch = Vec<TextPosition>;
+
+pub(crate) struct FindState {
+    pub(crate) query: String,
+    pub(crate) matches: Vec<FindMatch>,
+    pub(crate) current: usize,
+    pub(crate) bar: TextItem,
+}
+
+impl Browser {
+    pub fn new(width: u32, height: u32, http_client: HttpClient) -> Self {
+        let font_manager = FontManager::new();
+        let font_family = font_manager.default_family(None);
+        Self {
+            scroll: 0,
+            smooth_scroll: true,
+            scroll_step: SCROLL_STEP,
+            reverse_scroll: false,
+            gamma: DEFAULT_GAMMA,
+            antialias: true,
+            max_content_width: None,
+            min_font_size: None,
+            line_height: LINE_HEIGHT,
+            scroll_target: 0,
+            scroll_position: 0.0,
+            scroll_x: 0,
+            scroll_velocity: 0.0,
+            last_tick: None,
+            current_url: None,
+            base: None,
+            document: Vec::new(),
+            stylesheets: HashMap::new(),
+            images: HashMap::new(),
+            image_cache: RefCell::new(ImageCache::new(DEFAULT_IMAGE_CACHE_SIZE)),
+            display_list: Vec::new(),
+            headings: Vec::new(),
+            reader_mode: false,
+            font_manager,
+            glyph_cache: RefCell::default(),
+            font_family,
+            http_client,
+            width,
+            height,
+            dirty: true,
+            find: None,
+            selection: None,
+            tooltip: None,
+            status: None,
+            forms: Vec::new(),
+            input_values: HashMap::new(),
+            focus: None,
+            visited: HashSet::new(),
+            link_color: LINK_COLOR,
+            visited_link_color: VISITED_LINK_COLOR,
+            vertical_text: false,
+            loading: None,
+            refresh: None,
+            favicon: None,
+            favicon_loading: None,
+            zoom: DEFAULT_ZOOM,
+            site_zoom: Rc::default(),
+            resized: None,
+        }
+    }
+
+    /// Loads `url` and draws it once into a `width` by `height` RGBA buffer,
+    /// without opening a window
+    pub fn render_to_buffer(url: &str, width: u32, height: u32) -> Result<Vec<u8>, BrowserError> {
+        let mut browser = Browser::new(width, height, HttpClient::new(false));
+        browser.load(URL::new(url)?)?;
+        let mut frame = vec![255; (width * height * 4) as usize];
+        browser.draw(&mut frame);
+        Ok(frame)
+    }
+
+    /// Sets the base font family from a family list, falling back to the
+    /// defaults if none of it is installed. Without `system_fonts` all text
+    /// is drawn in the bundled font.
+    pub fn set_font(&mut self, family: Option<&str>, system_fonts: bool) {
+        self.font_manager.system_fonts = system_fonts;
+        self.font_family = self.font_manager.default_family(family);
+        self.font_manager.cached_fonts.clear();
+    }
+
+    pub fn load(&mut self, url: URL) -> Result<(), BrowserError> {
+        self.navigate(url, None)
+    }
+
+    /// Loads `url`, POSTing a urlencoded form to it when there is one
+    pub(crate) fn navigate(&mut self, url: URL, form: Option<&str>) -> Result<(), BrowserError> {
+        self.loading = None;
+        self.refresh = None;
+        let (body, kind) = match url.scheme.as_ref() {
+            "http" | "https" => {
+                let form = form.map(|form| RequestBody {
+                    content_type: FORM_CONTENT_TYPE,
+                    data: form.as_bytes(),
+                });
+                page_body(url.request_page(&mut self.http_client, form.as_ref()))?
+            }
+            "file" => (url.load_file()?, url.file_kind()),
+            "about" => (about_page(&url)?, BodyKind::Html),
+            scheme => {
+                return Err(BrowserError::Url(format!(
+                    "Unsupported URL scheme {}:",
+                    scheme
+                )));
+            }
+        };
+
+        self.show_body(url, body, kind);
+        Ok(())
+    }
+
+    /// Shows the body of the page at `url` as the kind of document it is
+    pub(crate) fn show_body(&mut self, url: URL, body: String, kind: BodyKind) {
+        self.visited.insert(url.to_string());
+        // Anything but HTML is shown as it is, rather than lexed
+        let tokens = match kind {
+            BodyKind::Html => self.lex(body),
+            BodyKind::PlainText => preformatted(&body),
+            BodyKind::Json => preformatted(&pretty_print(&body).unwrap_or(body)),
+        };
+        self.show(tokens, Some(url));
+    }
+
+    /// Shows an HTML document that doesn't come from a URL, like one piped
+    /// in on stdin. Relative links and images on it are left unresolved.
+    pub fn load_html(&mut self, html: &[u8]) {
+        self.loading = None;
+        let tokens = self.lex(decode(html, None));
+        self.show(tokens, None);
+    }
+
+    /// Shows a page saying why `address` couldn't be loaded, in place of
+    /// the page that was there
+    pub fn show_error(&mut self, address: &str, err: &BrowserError) {
+        let tokens = self.lex(error_page(address, err));
+        self.show(tokens, None);
+        self.scroll = 0;
+        self.scroll_x = 0;
+        self.reset_scroll();
+    }
+
+    /// Lays out a newly loaded page, along with its images
+    pub(crate) fn show(&mut self, tokens: Vec<Token>, url: Option<URL>) {
+        self.document = parse(tokens);
+        self.input_values.clear();
+        self.focus = None;
+        // Links are resolved against the base during layout
+        let base = url.as_ref().map(|url| self.base_url(url));
+        self.load_images(base.as_ref());
+        self.load_stylesheets(base.as_ref());
+        self.base = base;
+        self.compute_styles();
+        self.current_url = url;
+        self.favicon = None;
+        self.favicon_loading = None;
+        self.schedule_refresh();
+        self.restore_zoom();
+        self.layout();
+        self.selection = None;
+        self.status = None;
+        self.update_find();
+        self.dirty = true;
+    }
+
+    /// Fetches the current page again, along with its images. The scroll
+    /// position is kept, as far as the reloaded page is still long enough.
+    pub fn reload(&mut self) -> Result<(), BrowserError> {
+        let Some(url) = self.current_url.clone() else {
+            return Ok(());
+        };
+        // The page's images are fetched again too, rather than drawn from
+        // the cache
+        let image_cache = self.image_cache.get_mut();
+        for src in self.images.keys() {
+            let image_url = match &self.base {
+                Some(base) => base.resolve(src),
+                None => URL::new(src).ok(),
+            };
+            if let Some(image_url) = image_url {
+                image_cache.remove(&image_url.to_string());
+            }
+        }
+        self.load(url)?;
+        self.reset_scroll();
+        Ok(())
+    }
+
+    /// The URL that relative URLs on the page at `url` resolve against. Only
+    /// the first <base> with an href counts.
+    pub(crate) fn base_url(&self, url: &URL) -> URL {
+        elements(&self.document)
+            .filter(|element| element.name() == "base")
+            .find_map(|element| element.tag.attributes.get("href"))
+            .and_then(|href| url.resolve(href))
+            .unwrap_or_else(|| url.clone())
+    }
+
+    /// The page's title, from the <title> in its head, with its whitespace
+    /// collapsed
+    pub fn title(&self) -> Option<String> {
+        let title = elements(head(&self.document)).find(|element| element.name() == "title")?;
+        let words: Vec<&str> = descendants(&title.children)
+            .filter_map(|node| match node {
+                Node::Text(text) => Some(text),
+                Node::Element(_) => None,
+            })
+            .flat_map(|text| words(text).map(|(_, word)| word))
+            .collect();
+        (!words.is_empty()).then(|| words.join(" "))
+    }
+
+    /// Fetches and decodes the page's images, several at once, apart from
+    /// those still in the image cache. Any that fail are left out, so layout
+    /// falls back to their alt text placeholder. Without a base only images
+    /// with an absolute src can be loaded.
+    pub(crate) fn load_images(&mut self, base: Option<&URL>) {
+        self.images.clear();
+        let mut srcs: Vec<&String> = Vec::new();
+        let mut urls = Vec::new();
+        let image_cache = self.image_cache.get_mut();
+        for element in elements(&self.document) {
+            if element.name() != "img" {
+                continue;
+            }
+            let Some(src) = element.tag.attributes.get("src") else {
+                continue;
+            };
+            if srcs.contains(&src) {
+                continue;
+            }
+            let url = match base {
+                Some(base) => base.resolve(src),
+                None => URL::new(src).ok(),
+            };
+            let Some(url) = url else {
+                continue;
+            };
+            if let Some(image) = image_cache.get(&url.to_string()) {
+                self.images.insert(src.clone(), image);
+                continue;
+            }
+            srcs.push(src);
+            urls.push(url);
+        }
+
+        let fetched = self.http_client.fetch_all(&urls);
+        for ((src, url), response) in srcs.into_iter().zip(urls).zip(fetched) {
+            let image = response
+                .and_then(|response| {
+                    image::load_from_memory(&response.body)
+                        .map_err(|err| BrowserError::Encoding(err.to_string()))
+                })
+                .map(|image| image.to_rgba8());
+            match image {
+                Ok(image) => {
+                    let image = Rc::new(image);
+                    image_cache.insert(url.to_string(), image.clone());
+                    self.images.insert(src.clone(), image);
+                }
+                Err(err) => eprintln!("Failed to load image {}: {}", src, err),
+            }
+        }
+    }
+
+    /// Sets how many bytes of decoded images are kept for pages to use
+    /// again, dropping the least recently drawn if they no longer fit
+    pub fn set_image_cache_size(&mut self, bytes: usize) {
+        self.image_cache.get_mut().set_budget(bytes);
+    }
+
+    pub(crate) fn layout(&mut self) {
+        let mut layout = Layout::new(self.width);
+        layout.vertical = self.vertical_text.then(|| Vertical::new(self.height));
+        layout.max_content_width = self.max_content_width;
+        layout.line_height = self.line_height;
+        layout.min_font_size = self.min_font_size.map(FontSize);
+        layout.images = self.images.clone();
+        layout.font_properties.font_family = self.font_family.clone();
+        layout.input_values = self.input_values.clone();
+        layout.base = self.base.clone();
+        let mut shown = section(&self.document, "body");
+        if self.reader_mode {
+            layout.max_content_width = Some(
+                self.max_content_width
+                    .map_or(READER_WIDTH, |max| std::cmp::min(max, READER_WIDTH)),
+            );
+            layout.font_size = READER_FONT_SIZE;
+            shown = shown.map(main_content);
+        }
+        layout.font_size = FontSize((layout.font_size.0 * self.zoom / 100).max(1));
+        layout.set_font_size(layout.font_size);
+        // What's shown starts out in the style of the element around it
+        if let Some(shown) = shown {
+            layout.set_style(&shown.style);
+        }
+        let nodes = shown.map_or(&[][..], |shown| &shown.children);
+        self.display_list = layout.document(nodes, &mut self.font_manager);
+        self.forms = layout.forms;
+        // Cells in a table row are laid out side by side, so their headings
+        // can come out of order
+        self.headings = layout.headings;
+        self.headings.sort_unstable();
+    }
+
+    pub(crate) fn lex(&self, body: String) -> Vec<Token> {
+        lex(&body)
+    }
+
+    pub(crate) fn max_scroll(&self) -> u32 {
+        self.display_list
+            .iter()
+            .map(DisplayItem::bottom)
+            .max()
+            .map_or(0, |bottom| (bottom + VSTEP).saturating_sub(self.height))
+    }
+
+    pub(crate) fn max_scroll_x(&self) -> u32 {
+        self.display_list
+            .iter()
+            .map(DisplayItem::right)
+            .max()
+            .map_or(0, |right| (right + HSTEP).saturating_sub(self.width))
+    }
+
+    /// The text items in the display list, along with their indices
+    pub(crate) fn text_items(&self) -> impl Iterator<Item = (usize, &TextItem)> {
+        self.display_list
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| Some((index, item.as_text()?)))
+    }
+
+    /// Looks up an index known to refer to a text item, e.g. from a
+    /// `TextPosition`
+    pub(crate) fn text_item(&self, index: usize) -> &TextItem {
+        self.display_list[index]
+            .as_text()
+            .expect("index should refer to a text item")
+    }
+
+    /// The first word at least partly in view, by the text it came from and
+    /// where in it it starts, along with how far below the top of the view
+    /// it is. None when the page isn't scrolled, as it then stays at the top.
+    fn scroll_anchor(&self) -> Option<(Rc<str>, usize, i64)> {
+        if self.scroll == 0 {
+            return None;
+        }
+        let (_, item) = self
+            .text_items()
+            .find(|(_, item)| item.vertical_extent().1 > self.scroll as f32)?;
+        let offset = item.vertical_extent().0 as i64 - self.scroll as i64;
+        Some((item.source.clone(), item.range.start, offset))
+    }
+
+    pub(crate) fn reset_scroll(&mut self) {
+        self.scroll = std::cmp::min(self.scroll, self.max_scroll());
+
+        // Don't animate towards a target that may no longer be reachable
+        self.scroll_target = self.scroll;
+        self.scroll_position = self.scroll as f32;
+        self.scroll_velocity = 0.0;
+        self.last_tick = None;
+        self.scroll_x = std::cmp::min(self.scroll_x, self.max_scroll_x());
+    }
+
+    pub fn scrolldown(&mut self) {
+        if self.display_list.is_empty() {
+            return;
+        }
+
+        self.set_scroll(std::cmp::min(
+            self.scroll_target + self.scroll_step,
+            self.max_scroll(),
+        ));
+    }
+
+    pub fn scrollup(&mut self) {
+        self.set_scroll(self.scroll_target.saturating_sub(self.scroll_step));
+    }
+
+    /// Scrolls for the mouse wheel turning `lines` lines, positive away from
+    /// the user. That scrolls up the page, or down it with `reverse_scroll`.
+    pub fn scroll_wheel(&mut self, lines: f32) {
+        if self.display_list.is_empty() {
+            return;
+        }
+
+        let lines = if self.reverse_scroll { lines } else { -lines };
+        let distance = (lines.abs() * self.scroll_step as f32).round() as u32;
+        if lines > 0.0 {
+            self.set_scroll(std::cmp::min(
+                self.scroll_target + distance,
+                self.max_scroll(),
+            ));
+        } else {
+            self.set_scroll(self.scroll_target.saturating_sub(distance));
+        }
+    }
+
+    /// Scrolls towards the right of a page wider than the window
+    pub fn scroll_right(&mut self) {
+        self.set_scroll_x(std::cmp::min(
+            self.scroll_x + self.scroll_step,
+            self.max_scroll_x(),
+        ));
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.set_scroll_x(self.scroll_x.saturating_sub(self.scroll_step));
+    }
+
+    pub(crate) fn set_scroll_x(&mut self, scroll_x: u32) {
+        if scroll_x != self.scroll_x {
+            self.scroll_x = scroll_x;
+            self.dirty = true;
+        }
+    }
+
+    /// How far down the page is scrolled, in px
+    pub fn scroll_offset(&self) -> u32 {
+        self.scroll
+    }
+
+    /// How far right the page is scrolled, in px
+    pub fn scroll_x_offset(&self) -> u32 {
+        self.scroll_x
+    }
+
+    /// Scrolls to put the next heading below the top of the window at the top
+    pub fn next_heading(&mut self) {
+        let current = self.scroll_target;
+        if let Some(&heading) = self.headings.iter().find(|&&y| y > current) {
+            self.set_scroll(std::cmp::min(heading, self.max_scroll()));
+        }
+    }
+
+    /// Scrolls back to put the heading above the top of the window at the top
+    pub fn previous_heading(&mut self) {
+        let current = self.scroll_target;
+        if let Some(&heading) = self.headings.iter().rev().find(|&&y| y < current) {
+            self.set_scroll(heading);
+        }
+    }
+
+    pub(crate) fn set_scroll(&mut self, scroll: u32) {
+        self.scroll_target = scroll;
+        if !self.smooth_scroll && scroll != self.scroll {
+            self.scroll = scroll;
+            self.scroll_position = scroll as f32;
+            self.dirty = true;
+        }
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        self.scroll != self.scroll_target || self.scroll_velocity != 0.0
+    }
+
+    /// Advances smooth scrolling by one frame, returning whether it is still
+    /// animating and so needs another frame
+    pub fn tick(&mut self, now: Instant) -> bool {
+        self.show_tooltip(now);
+        self.poll_load();
+        self.poll_favicon();
+        self.follow_refresh(now);
+        if self
+            .resized
+            .is_some_and(|resized| now >= resized + RESIZE_SETTLE)
+        {
+            self.finish_resize();
+        }
+
+        if !self.is_scrolling() {
+            self.last_tick = None;
+            return false;
+        }
+
+        // Cap the step so a long stall doesn't overshoot the target
+        let dt = self
+            .last_tick
+            .map_or(1.0 / 60.0, |last| (now - last).as_secs_f32())
+            .min(0.05);
+        self.last_tick = Some(now);
+
+        let target = self.scroll_target as f32;
+        self.scroll_velocity = (target - self.scroll_position) * SCROLL_EASING;
+        self.scroll_position += self.scroll_velocity * dt;
+        if (target - self.scroll_position).abs() < 0.5 {
+            self.scroll_position = target;
+            self.scroll_velocity = 0.0;
+        }
+
+        let scroll = self.scroll_position.round() as u32;
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            self.dirty = true;
+        }
+
+        self.is_scrolling()
+    }
+
+    pub fn open_find(&mut self) {
+        if self.find.is_none() {
+            let bar = self.ui_text("Find: ".to_owned(), HSTEP, self.height);
+            self.find = Some(FindState {
+                query: String::new(),
+                matches: Vec::new(),
+                current: 0,
+                bar,
+            });
+            self.update_find();
+        }
+    }
+
+    pub fn is_finding(&self) -> bool {
+        self.find.is_some()
+    }
+
+    pub fn close_find(&mut self) {
+        self.find = None;
+        self.dirty = true;
+    }
+
+    pub fn find_push(&mut self, text: &str) {
+        if let Some(find) = &mut self.find {
+            find.query.push_str(text);
+            find.current = 0;
+            self.update_find();
+        }
+    }
+
+    pub fn find_pop(&mut self) {
+        if let Some(find) = &mut self.find {
+            find.query.pop();
+            find.current = 0;
+            self.update_find();
+        }
+    }
+
+    pub fn find_next(&mut self) {
+        if let Some(find) = &mut self.find
+            && !find.matches.is_empty()
+        {
+            find.current = (find.current + 1) % find.matches.len();
+            self.update_find();
+        }
+    }
+
+    /// Re-runs the search against the current display list, rebuilds the find
+    /// bar and scrolls the current match into view
+    pub(crate) fn update_find(&mut self) {
+        let Some(find) = &mut self.find else {
+            return;
+        };
+
+        find.matches = find_matches(&self.display_list, &find.query);
+        find.current = find.current.min(find.matches.len().saturating_sub(1));
+        let label = if find.matches.is_empty() {
+            format!("Find: {}", find.query)
+        } else {
+            format!(
+                "Find: {}  ({}/{})",
+                find.query,
+                find.current + 1,
+                find.matches.len()
+            )
+        };
+        let target = find
+            .matches
+            .get(find.current)
+            .and_then(|found| found.first())
+            .map(|(index, _)| *index);
+        let target = target.map(|index| self.text_item(index).y);
+
+        let bar = self.ui_text(label, HSTEP, self.height - FIND_BAR_HEIGHT / 3);
+        if let Some(find) = &mut self.find {
+            find.bar = bar;
+        }
+
+        if let Some(y) = target {
+            let scroll = std::cmp::min(y.saturating_sub(self.height / 3), self.max_scroll());
+            self.set_scroll(scroll);
+        }
+        self.dirty = true;
+    }
+
+    /// Maps a point in window coordinates to the closest display item and the
+    /// byte offset of the glyph boundary nearest to it
+    pub(crate) fn hit_test(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let x = x + self.scroll_x as f32;
+        let y = y + self.scroll as f32;
+        let (index, item) = self.text_items().min_by(|(_, a), (_, b)| {
+            let distance = |item: &TextItem| {
+                let (top, bottom) = item.vertical_extent();
+                let (left, right) = item.x_extent(&item.range);
+                let dy = (top - y).max(y - bottom).max(0.0);
+                let dx = (left - x).max(x - right).max(0.0);
+                // Prefer anything on the right line over closeness in x
+                (dy, dx)
+            };
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        })?;
+
+        if item.vertical {
+            // A single character, so the point is before or after it
+            let (top, bottom) = item.vertical_extent();
+            let offset = match y < (top + bottom) / 2.0 {
+                true => item.range.start,
+                false => item.range.end,
+            };
+            return Some((index, offset));
+        }
+
+        let scale_factor = item.scale().x / item.font.height_unscaled();
+        let mut cursor_x = item.x as f32;
+        for (info, pos) in item
+            .glyphs
+            .glyph_infos()
+            .iter()
+            .zip(item.glyphs.glyph_positions())
+        {
+            let advance = pos.x_advance as f32 * scale_factor;
+            if x < cursor_x + advance / 2.0 {
+                return Some((index, item.range.start + info.cluster as usize));
+            }
+            cursor_x += advance;
+        }
+
+        Some((index, item.range.end))
+    }
+
+    /// The display item directly under a point in window coordinates
+    pub(crate) fn item_at(&self, x: f32, y: f32) -> Option<usize> {
+        let x = x + self.scroll_x as f32;
+        let y = y + self.scroll as f32;
+        self.text_items().map(|(index, _)| index).find(|index| {
+            let item = self.text_item(*index);
+            let (top, bottom) = item.vertical_extent();
+            let (left, right) = item.x_extent(&item.range);
+            (left..right).contains(&x) && (top..bottom).contains(&y)
+        })
+    }
+
+    /// The resolved href of the link under a point in window coordinates
+    pub fn link_at(&self, x: f32, y: f32) -> Option<&str> {
+        let index = self.item_at(x, y)?;
+        self.text_item(index).href.as_deref()
+    }
+
+    /// Where the frame whose box is under a point in window coordinates
+    /// would go, if there is one
+    pub(crate) fn frame_at(&self, x: f32, y: f32) -> Option<&str> {
+        let x = x + self.scroll_x as f32;
+        let y = y + self.scroll as f32;
+        self.display_list.iter().find_map(|item| match item {
+            DisplayItem::Image(image)
+                if (image.x as f32..(image.x + image.width) as f32).contains(&x)
+                    && (image.y as f32..(image.y + image.height) as f32).contains(&y) =>
+            {
+                image.frame.as_deref()
+            }
+            _ => None,
+        })
+    }
+
+    pub fn hover(&mut self, x: f32, y: f32, now: Instant) {
+        let href = self.link_at(x, y).map(str::to_owned);
+        if href.as_deref() != self.status.as_ref().map(|status| &*status.source) {
+            // Laid out relative to the top of the status bar, which moves up
+            // while the find bar is open
+            self.status = href.map(|href| {
+                self.ui_text(href, HSTEP / 4, STATUS_BAR_HEIGHT - STATUS_BAR_HEIGHT / 3)
+            });
+            self.dirty = true;
+        }
+
+        let title = self
+            .item_at(x, y)
+            .and_then(|index| self.text_item(index).title.clone());
+        match (&mut self.tooltip, title) {
+            // Moving within the same element just moves a pending tooltip
+            (Some(tooltip), Some(title)) if tooltip.title == title => {
+                if tooltip.label.is_none() {
+                    (tooltip.x, tooltip.y) = (x, y);
+                }
+            }
+            (_, Some(title)) => {
+                self.dirty |= self.tooltip.as_ref().is_some_and(|t| t.label.is_some());
+                self.tooltip = Some(Tooltip {
+                    title,
+                    x,
+                    y,
+                    since: now,
+                    label: None,
+                });
+            }
+            (_, None) => {
+                self.dirty |= self.tooltip.as_ref().is_some_and(|t| t.label.is_some());
+                self.tooltip = None;
+            }
+        }
+    }
+
+    pub(crate) fn show_tooltip(&mut self, now: Instant) {
+        let Some(tooltip) = &self.tooltip else {
+            return;
+        };
+        if tooltip.label.is_some() || now < tooltip.since + TOOLTIP_DELAY {
+            return;
+        }
+
+        let text = tooltip.title.to_string();
+        let (x, y) = (tooltip.x as u32 + 12, tooltip.y as u32 + 32);
+        let mut label = self.ui_text(text, x, y);
+
+        // Shift the tooltip back in if it would hang off the window
+        let (left, right) = label.x_extent(&label.range);
+        let (_, bottom) = label.vertical_extent();
+        let overflow_x = (right as u32 + TOOLTIP_PADDING).saturating_sub(self.width);
+        let overflow_y = (bottom as u32 + TOOLTIP_PADDING).saturating_sub(self.height);
+        label.x -= std::cmp::min(overflow_x, left as u32 - TOOLTIP_PADDING);
+        label.y -= overflow_y;
+
+        if let Some(tooltip) = &mut self.tooltip {
+            tooltip.label = Some(label);
+        }
+        self.dirty = true;
+    }
+
+    /// When the event loop next needs to wake up by itself, i.e. to show a
+    /// pending tooltip, or more of a page that's loading or its icon
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let tooltip = self
+            .tooltip
+            .as_ref()
+            .filter(|tooltip| tooltip.label.is_none())
+            .map(|tooltip| tooltip.since + TOOLTIP_DELAY);
+        let loading = (self.loading.is_some() || self.favicon_loading.is_some())
+            .then(|| Instant::now() + LOAD_POLL_INTERVAL);
+        let refresh = self.refresh.as_ref().map(|(due, _)| *due);
+        let resized = self.resized.map(|resized| resized + RESIZE_SETTLE);
+        tooltip
+            .into_iter()
+            .chain(loading)
+            .chain(refresh)
+            .chain(resized)
+            .min()
+    }
+
+    pub fn start_selection(&mut self, x: f32, y: f32) {
+        self.selection = self.hit_test(x, y).map(|point| (point, point));
+        self.dirty = true;
+    }
+
+    pub fn extend_selection(&mut self, x: f32, y: f32) {
+        let focus = self.hit_test(x, y);
+        if let (Some((_, current)), Some(focus)) = (&mut self.selection, focus)
+            && *current != focus
+        {
+            *current = focus;
+            self.dirty = true;
+        }
+    }
+
+    /// The selection as one byte range per display item it covers
+    pub(crate) fn selected_ranges(&self) -> Vec<TextPosition> {
+        let Some((anchor, focus)) = self.selection else {
+            return Vec::new();
+        };
+        let (start, end) = if anchor <= focus {
+            (anchor, focus)
+        } else {
+            (focus, anchor)
+        };
+
+        (start.0..=end.0)
+            .filter_map(|index| {
+                let range = &self.display_list[index].as_text()?.range;
+                let from = if index == start.0 {
+                    start.1
+                } else {
+                    range.start
+                };
+                let to = if index == end.0 { end.1 } else { range.end };
+                Some((index, from..to))
+            })
+            .filter(|(_, range)| !range.is_empty())
+            .collect()
+    }
+
+    /// The selected text, with words on the same line joined by spaces and
+    /// separate lines by newlines
+    pub fn selected_text(&self) -> String {
+        let mut text = String::new();
+        let mut last_y = None;
+        for (index, range) in self.selected_ranges() {
+            let item = self.text_item(index);
+            match last_y {
+                Some(y) if y == item.y => text.push(' '),
+                Some(_) => text.push('\n'),
+                None => {}
+            }
+            text.push_str(&item.source[range]);
+            last_y = Some(item.y);
+        }
+        text
+    }
+
+    /// The text of the page in reading order, with a line for each block,
+    /// like a paragraph or heading, and single spaces between words
+    pub fn extract_text(&self) -> String {
+        let mut text = String::new();
+        let mut last: Option<&TextItem> = None;
+        for (_, item) in self.text_items() {
+            match last {
+                Some(last) if last.block != item.block => text.push('\n'),
+                Some(last) if item.continues(last) => {}
+                Some(_) => text.push(' '),
+                None => {}
+            }
+            text.extend(
+                item.source[item.range.clone()]
+                    .chars()
+                    .filter(|&c| !is_word_break(c)),
+            );
+            last = Some(item);
+        }
+        text
+    }
+
+    /// Shapes a line of browser UI text in the default font
+    pub(crate) fn ui_text(&mut self, text: String, x: u32, y: u32) -> TextItem {
+        let font_properties = FontProperties {
+            font_family: self.font_family.clone(),
+            ..FontProperties::default()
+        };
+        let (font, face) = self.font_manager.get_fonts(&font_properties);
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(&text);
+        let range = 0..text.len();
+        TextItem {
+            glyphs: shape(face, &[], buffer),
+            x,
+            y,
+            font,
+            font_properties,
+            font_size: FontSize(12),
+            underline: false,
+            strikethrough: false,
+            source: text.into(),
+            range,
+            title: None,
+            href: None,
+            color: TEXT_COLOR,
+            block: 0,
+            synthetic_oblique: false,
+            synthetic_bold: false,
+            vertical: false,
+        }
+    }
+
+    /// Paints the page in passes, each over everything in view before the
+    /// next starts: boxes and highlights, then text, then the lines drawn
+    /// over text, then the browser's own bars and tooltips on top
+    pub fn draw(&self, frame: &mut [u8]) {
+        self.draw_backgrounds(frame);
+        for (item, offset) in self.visible_text() {
+            self.draw_glyphs(frame, item, offset);
+        }
+        self.draw_decorations(frame);
+        self.draw_overlays(frame);
+    }
+
+    /// The display list items reaching into the window, however little of
+    /// them shows
+    pub(crate) fn visible_items(&self) -> impl Iterator<Item = &DisplayItem> {
+        self.display_list
+            .iter()
+            .filter(|item| item.bottom() > self.scroll && item.top() < self.scroll + self.height)
+    }
+
+    /// Every piece of text in view, with the offset to draw it at: the
+    /// page's own, and the alt text and labels inside image and input boxes
+    pub(crate) fn visible_text(&self) -> impl Iterator<Item = (&TextItem, (i32, i32))> {
+        let (x, y) = (-(self.scroll_x as i32), -(self.scroll as i32));
+        self.visible_items().filter_map(move |item| match item {
+            DisplayItem::Text(text) => Some((text, (x, y))),
+            DisplayItem::Image(image) if image.pixels.is_none() => {
+                let offset = (image.x as i32 + x, image.y as i32 + y);
+                image.alt.as_ref().map(|alt| (alt, offset))
+            }
+            DisplayItem::Image(_) => None,
+            DisplayItem::Input(input) => {
+                let offset = (input.x as i32 + x, input.y as i32 + y);
+                input.label.as_ref().map(|label| (label, offset))
+            }
+        })
+    }
+
+    /// Images and the boxes of inputs and missing images, then the find and
+    /// selection highlights
+    pub(crate) fn draw_backgrounds(&self, frame: &mut [u8]) {
+        for item in self.visible_items() {
+            match item {
+                DisplayItem::Text(_) => {}
+                DisplayItem::Image(image) => match &image.pixels {
+                    Some(pixels) => {
+                        if let Some(url) = &image.url {
+                            self.image_cache.borrow_mut().touch(url);
+                        }
+                        self.draw_image(frame, image, pixels)
+                    }
+                    None => self.draw_placeholder(frame, image),
+                },
+                DisplayItem::Input(input) => self.draw_input(frame, input),
+            }
+        }
+
+        if let Some(find) = &self.find {
+            for (index, found) in find.matches.iter().enumerate() {
+                let color = if index == find.current {
+                    FIND_CURRENT_COLOR
+                } else {
+                    FIND_MATCH_COLOR
+                };
+                for position in found {
+                    self.highlight(frame, position, color);
+                }
+            }
+        }
+
+        for position in self.selected_ranges() {
+            self.highlight(frame, &position, SELECTION_COLOR);
+        }
+    }
+
+    /// Underlines and strikethroughs, and the caret in the input being
+    /// typed into
+    pub(crate) fn draw_decorations(&self, frame: &mut [u8]) {
+        for (item, offset) in self.visible_text() {
+            self.draw_lines(frame, item, offset);
+        }
+        for item in self.visible_items() {
+            match item {
+                DisplayItem::Input(input) if self.focus == Some(input.index) => {
+                    self.draw_caret(frame, input)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The scrollbar, tooltip, status bar and find bar, over the page
+    pub(crate) fn draw_overlays(&self, frame: &mut [u8]) {
+        self.draw_scrollbar(frame);
+
+        if let Some(label) = self.tooltip.as_ref().and_then(|t| t.label.as_ref()) {
+            self.draw_tooltip(frame, label);
+        }
+
+        if let Some(status) = &self.status {
+            let top = self.page_bottom().saturating_sub(STATUS_BAR_HEIGHT) as i32;
+            self.fill_rect(
+                frame,
+                0,
+                top,
+                self.width,
+                STATUS_BAR_HEIGHT,
+                STATUS_BAR_COLOR,
+            );
+            self.draw_glyphs(frame, status, (0, top));
+        }
+
+        if let Some(find) = &self.find {
+            let top = self.height.saturating_sub(FIND_BAR_HEIGHT);
+            self.fill_rect(
+                frame,
+                0,
+                top as i32,
+                self.width,
+                FIND_BAR_HEIGHT,
+                FIND_BAR_COLOR,
+            );
+            self.draw_glyphs(frame, &find.bar, (0, 0));
+        }
+    }
+
+    pub(crate) fn draw_tooltip(&self, frame: &mut [u8], label: &TextItem) {
+        let (left, right) = label.x_extent(&label.range);
+        let (top, bottom) = label.vertical_extent();
+        let x = left as i32 - TOOLTIP_PADDING as i32;
+        let y = top as i32 - TOOLTIP_PADDING as i32;
+        let width = (right - left) as u32 + 2 * TOOLTIP_PADDING;
+        let height = (bottom - top) as u32 + 2 * TOOLTIP_PADDING;
+
+        self.fill_rect(frame, x, y, width, height, TOOLTIP_BORDER_COLOR);
+        self.fill_rect(frame, x + 1, y + 1, width - 2, height - 2, TOOLTIP_COLOR);
+        self.draw_glyphs(frame, label, (0, 0));
+    }
+
+    /// The bottom of the part of the window showing the page, which is above
+    /// the find bar while it's open
+    pub(crate) fn page_bottom(&self) -> u32 {
+        match self.find {
+            Some(_) => self.height.saturating_sub(FIND_BAR_HEIGHT),
+            None => self.height,
+        }
+    }
+
+    /// Draws a scrollbar along the bottom of a page wider than the window,
+    /// with a thumb showing which part of its width is in view
+    pub(crate) fn draw_scrollbar(&self, frame: &mut [u8]) {
+        let max_scroll_x = self.max_scroll_x();
+        if max_scroll_x == 0 {
+            return;
+        }
+        let top = self.page_bottom().saturating_sub(SCROLLBAR_HEIGHT) as i32;
+        self.fill_rect(frame, 0, top, self.width, SCROLLBAR_HEIGHT, SCROLLBAR_COLOR);
+
+        let page_width = (self.width + max_scroll_x) as u64;
+        let thumb_width = std::cmp::max(
+            (self.width as u64 * self.width as u64 / page_width) as u32,
+            SCROLLBAR_MIN_THUMB,
+        );
+        let travel = self.width.saturating_sub(thumb_width) as u64;
+        let x = travel * self.scroll_x as u64 / max_scroll_x as u64;
+        self.fill_rect(
+            frame,
+            x as i32,
+            top,
+            thumb_width,
+            SCROLLBAR_HEIGHT,
+            SCROLLBAR_THUMB_COLOR,
+        );
+    }
+
+    /// Draws the bordered box standing in for an image that didn't load
+    pub(crate) fn draw_placeholder(&self, frame: &mut [u8], image: &ImageItem) {
+        let x = image.x as i32 - self.scroll_x as i32;
+        let y = image.y as i32 - self.scroll as i32;
+        self.fill_rect(
+            frame,
+            x,
+            y,
+            image.width,
+            image.height,
+            PLACEHOLDER_BORDER_COLOR,
+        );
+        self.fill_rect(
+            frame,
+            x + 1,
+            y + 1,
+            image.width.saturating_sub(2),
+            image.height.saturating_sub(2),
+            [255, 255, 255],
+        );
+    }
+
+    /// Draws an input's box, with a brighter border when it's being typed
+    /// into
+    pub(crate) fn draw_input(&self, frame: &mut [u8], input: &InputItem) {
+        let x = input.x as i32 - self.scroll_x as i32;
+        let y = input.y as i32 - self.scroll as i32;
+        let focused = self.focus == Some(input.index);
+        let border = if focused {
+            FOCUS_BORDER_COLOR
+        } else {
+            PLACEHOLDER_BORDER_COLOR
+        };
+        let background = if input.submit {
+            BUTTON_COLOR
+        } else {
+            [255, 255, 255]
+        };
+        self.fill_rect(frame, x, y, input.width, input.height, border);
+        self.fill_rect(
+            frame,
+            x + 1,
+            y + 1,
+            input.width.saturating_sub(2),
+            input.height.saturating_sub(2),
+            background,
+        );
+    }
+
+    /// Draws the caret after the text of the input being typed into
+    pub(crate) fn draw_caret(&self, frame: &mut [u8], input: &InputItem) {
+        let x = input.x as i32 - self.scroll_x as i32;
+        let y = input.y as i32 - self.scroll as i32;
+        let text_end = input
+            .label
+            .as_ref()
+            .map_or(INPUT_PADDING as f32, |label| label.x_extent(&label.range).1);
+        self.fill_rect(
+            frame,
+            x + text_end.round() as i32,
+            y + INPUT_PADDING as i32,
+            1,
+            input.height.saturating_sub(2 * INPUT_PADDING),
+            [0, 0, 0],
+        );
+    }
+
+    /// Blends an image's pixels, already scaled to its box, over the page
+    pub(crate) fn draw_image(&self, frame: &mut [u8], image: &ImageItem, pixels: &RgbaImage) {
+        let x = image.x as i32 - self.scroll_x as i32;
+        let y = image.y as i32 - self.scroll as i32;
+        for (px, py, pixel) in pixels.enumerate_pixels() {
+            let fx = x + px as i32;
+            let fy = y + py as i32;
+            if fx < 0 || fx >= self.width as i32 || fy < 0 || fy >= self.height as i32 {
+                continue;
+            }
+
+            let idx = ((fy as u32 * self.width + fx as u32) * 4) as usize;
+            let alpha = pixel[3] as f32 / 255.0;
+            for d in 0..3 {
+                let bg = frame[idx + d] as f32;
+                let fg = pixel[d] as f32;
+                frame[idx + d] = (bg * (1.0 - alpha) + fg * alpha) as u8;
+            }
+            frame[idx + 3] = 255;
+        }
+    }
+
+    pub(crate) fn highlight(
+        &self,
+        frame: &mut [u8],
+        (index, range): &TextPosition,
+        color: [u8; 3],
+    ) {
+        let item = self.text_item(*index);
+        let (left, right) = item.x_extent(range);
+        let (top, bottom) = item.vertical_extent();
+        self.fill_rect(
+            frame,
+            left as i32 - self.scroll_x as i32,
+            top as i32 - self.scroll as i32,
+            (right - left).ceil() as u32,
+            (bottom - top).ceil() as u32,
+            color,
+        );
+    }
+
+    pub(crate) fn fill_rect(
+        &self,
+        frame: &mut [u8],
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: [u8; 3],
+    ) {
+        let left = x.max(0) as u32;
+        let top = y.max(0) as u32;
+        let right = std::cmp::min(x + width as i32, self.width as i32);
+        let bottom = std::cmp::min(y + height as i32, self.height as i32);
+        for py in top as i32..bottom {
+            for px in left as i32..right {
+                let idx = ((py as u32 * self.width + px as u32) * 4) as usize;
+                frame[idx..idx + 3].copy_from_slice(&color);
+                frame[idx + 3] = 255;
+            }
+        }
+    }
+
+    /// How much of a pixel's color to take from the text, given how much of
+    /// it the glyph covers, after gamma and anti-aliasing settings
+    fn adjust_coverage(&self, coverage: f32) -> f32 {
+        if !self.antialias {
+            return if coverage >= COVERAGE_THRESHOLD {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        if self.gamma == DEFAULT_GAMMA || coverage == 0.0 {
+            return coverage;
+        }
+        coverage.powf(1.0 / self.gamma).min(1.0)
+    }
+
+    /// Draws the glyphs of a text item, shifted by `offset`
+    pub(crate) fn draw_glyphs(&self, frame: &mut [u8], item: &TextItem, offset: (i32, i32)) {
+        let scale = item.scale();
+        // RustyBuzz offsets / advances need to be manually scaled to px values
+        let scale_factor = scale.x / item.font.height_unscaled();
+        let infos = item.glyphs.glyph_infos();
+        let positions = item.glyphs.glyph_positions();
+        // Vertical text advances down rather than across, with the offsets
+        // moving each glyph from the top middle of its box to its baseline
+        let mut cursor_x = (item.x as i32 + offset.0) as f32;
+        let mut cursor_y = (item.y as i32 + offset.1) as f32;
+        let mut glyph_cache = self.glyph_cache.borrow_mut();
+        for (info, pos) in infos.iter().zip(positions.iter()) {
+            // A glyph's index in the font rather than a code point, which
+            // OpenType limits to 16 bits
+            let gid = ab_glyph::GlyphId(info.glyph_id as u16);
+            let x = cursor_x + (pos.x_offset as f32 * scale_factor);
+            let y = cursor_y - (pos.y_offset as f32 * scale_factor);
+
+            let cached = glyph_cache.get(item.font, gid, scale, (x, y));
+            if let Some((bitmap, (origin_x, origin_y))) = cached {
+                let rows = bitmap.coverage.chunks(bitmap.width.max(1) as usize);
+                for (row, coverage) in rows.enumerate() {
+                    let gy = origin_y + bitmap.top + row as i32;
+                    for (column, &coverage) in coverage.iter().enumerate() {
+                        let coverage = self.adjust_coverage(coverage);
+                        if coverage == 0.0 {
+                            continue;
+                        }
+                        let mut gx = origin_x + bitmap.left + column as i32;
+                        if item.synthetic_oblique {
+                            gx += ((y - gy as f32) * OBLIQUE_SKEW).round() as i32;
+                        }
+                        // Synthesized bold draws every pixel again one to the
+                        // right, thickening the vertical strokes
+                        for gx in gx..=gx + item.synthetic_bold as i32 {
+                            if gx < 0
+                                || gx >= self.width as i32
+                                || gy < 0
+                                || gy >= self.height as i32
+                            {
+                                continue;
+                            }
+
+                            let idx = ((gy as u32 * self.width + gx as u32) * 4) as usize;
+                            let inv_alpha = 1.0 - coverage;
+                            for d in 0..3 {
+                                let bg = frame[idx + d] as f32;
+                                let fg = item.color[d] as f32;
+                                frame[idx + d] = (bg * inv_alpha + fg * coverage) as u8;
+                            }
+                            frame[idx + 3] = 255;
+                        }
+                    }
+                }
+            }
+
+            // Since we're dealing with words, not characters, we need to
+            // move the starting x of the next character by the x_advance
+            cursor_x += pos.x_advance as f32 * scale_factor;
+            cursor_y -= pos.y_advance as f32 * scale_factor;
+        }
+    }
+
+    /// Draws the underline and strikethrough of a text item, if it has
+    /// them, shifted by `offset`
+    pub(crate) fn draw_lines(&self, frame: &mut [u8], item: &TextItem, offset: (i32, i32)) {
+        if !item.underline && !item.strikethrough {
+            return;
+        }
+        let scale = item.scale();
+        let scaled_font = item.font.as_scaled(scale);
+        let scale_factor = scale.x / item.font.height_unscaled();
+        let baseline = item.y as i32 + offset.1;
+        let thickness = (scale.y / DECORATION_THICKNESS).round().max(1.0) as u32;
+        if item.vertical {
+            // Vertical text is underlined down its right side, and struck
+            // through down the middle
+            let (left, right) = item.x_extent(&item.range);
+            let (top, bottom) = item.vertical_extent();
+            let top = top as i32 + offset.1;
+            let height = (bottom - item.y as f32).round() as u32;
+            if item.underline {
+                let x = right as i32 + offset.0 - thickness as i32;
+                self.fill_rect(frame, x, top, thickness, height, item.color);
+            }
+            if item.strikethrough {
+                let x = ((left + right) / 2.0) as i32 + offset.0 - thickness as i32 / 2;
+                self.fill_rect(frame, x, top, thickness, height, item.color);
+            }
+            return;
+        }
+
+        // Decorations run under the whole word, as far as the advances went
+        let start = item.x as i32 + offset.0;
+        let mut cursor_x = start as f32;
+        for pos in item.glyphs.glyph_positions() {
+            cursor_x += pos.x_advance as f32 * scale_factor;
+        }
+        let width = (cursor_x - start as f32).round() as u32;
+        if item.underline {
+            let y = baseline + (scale.y * UNDERLINE_OFFSET).round() as i32;
+            self.fill_rect(frame, start, y, width, thickness, item.color);
+        }
+        if item.strikethrough {
+            // Strike through the middle of the lowercase letters, using the
+            // height of an x from the font itself when it has one
+            let x_height = self
+                .glyph_cache
+                .borrow_mut()
+                .get(item.font, scaled_font.glyph_id('x'), scale, (0.0, 0.0))
+                .map_or(scale.y * 0.5, |(x, _)| -x.top as f32);
+            let y = baseline - (x_height / 2.0).round() as i32 - thickness as i32 / 2;
+            self.fill_rect(frame, start, y, width, thickness, item.color);
+        }
+    }
+
+    /// Writes one line per display list item, for debugging layout without
+    /// opening a window
+    pub fn dump_layout(&self, out: &mut impl Write) -> Result<(), std::io::Error> {
+        for item in &self.display_list {
+            match item {
+                DisplayItem::Text(item) => {
+                    let mut style = Vec::new();
+                    if item.font_properties.font_weight == FontWeight::Bold {
+                        style.push("bold");
+                    }
+                    if item.font_properties.font_style != FontStyle::Normal {
+                        style.push("italic");
+                    }
+                    if item.underline {
+                        style.push("underline");
+                    }
+                    if item.strikethrough {
+                        style.push("strikethrough");
+                    }
+                    if style.is_empty() {
+                        style.push("normal");
+                    }
+                    writeln!(
+                        out,
+                        "x={} y={} font={:?} size={} style={} color=#{:02x}{:02x}{:02x}{} text={:?}",
+                        item.x,
+                        item.y,
+                        item.font_properties.font_family,
+                        item.font_size.0,
+                        style.join(","),
+                        item.color[0],
+                        item.color[1],
+                        item.color[2],
+                        item.href
+                            .as_ref()
+                            .map_or(String::new(), |href| format!(" href={:?}", href)),
+                        item.text()
+                    )?
+                }
+                DisplayItem::Image(image) => writeln!(
+                    out,
+                    "x={} y={} image={}x{} loaded={} alt={:?}",
+                    image.x,
+                    image.y,
+                    image.width,
+                    image.height,
+                    image.pixels.is_some(),
+                    image.alt.as_ref().map(TextItem::text)
+                )?,
+                DisplayItem::Input(input) => writeln!(
+                    out,
+                    "x={} y={} input={}x{} type={} name={:?} value={:?}",
+                    input.x,
+                    input.y,
+                    input.width,
+                    input.height,
+                    match (input.submit, input.password) {
+                        (true, _) => "submit",
+                        (false, true) => "password",
+                        (false, false) => "text",
+                    },
+                    input.name.as_deref().unwrap_or_default(),
+                    // What's shown, which for a password is only the mask
+                    if input.password {
+                        input.label.as_ref().map_or("", TextItem::text)
+                    } else {
+                        &input.value
+                    }
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Resizes the page to fit a window `width` x `height` px, laying it out
+    /// again straight away
+    pub fn resize_browser(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.resized = None;
+        self.relayout();
+    }
+
+    /// Resizes the page for a window that's being resized. The page is drawn
+    /// at the new size straight away, but only laid out again for it once
+    /// the window has kept the same size for `RESIZE_SETTLE`, when a `tick`
+    /// comes.
+    pub fn resize(&mut self, width: u32, height: u32, now: Instant) {
+        self.width = width;
+        self.height = height;
+        self.resized = Some(now);
+        self.dirty = true;
+    }
+
+    /// Lays the page out for the current size, if it was resized since it
+    /// last was
+    pub fn finish_resize(&mut self) {
+        if self.resized.take().is_some() {
+            self.relayout();
+        }
+    }
+
+    /// Lays the page out again, e.g. at a new size, keeping the text that
+    /// was at the top of the view there rather than wherever the old scroll
+    /// offset now lands
+    pub(crate) fn relayout(&mut self) {
+        let anchor = self.scroll_anchor();
+        self.layout();
+        self.selection = None;
+        if let Some((source, start, offset)) = anchor {
+            let top = self.text_items().find_map(|(_, item)| {
+                (Rc::ptr_eq(&item.source, &source) && item.range.contains(&start))
+                    .then(|| item.vertical_extent().0 as i64)
+            });
+            if let Some(top) = top {
+                self.scroll = (top - offset).max(0) as u32;
+            }
+        }
+        self.reset_scroll();
+        self.update_find();
+        self.dirty = true;
+    }
+}
+
+/// The body of a fetched page, and how to show it. A secure connection that
+/// couldn't be set up gets a page saying so, rather than an error.
+pub(crate) fn page_body(
+    response: Result<Response, BrowserError>,
+) -> Result<(String, BodyKind), BrowserError> {
+    match response {
+        Ok(response) => Ok((response.text(), response.body_kind())),
+        Err(BrowserError::Tls(tls_err)) => Ok((
+            format!(
+                "<b>Secure connection failed</b> {}",
+                escape(&tls_err.to_string())
+            ),
+            BodyKind::Html,
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+/// Case-insensitively searches the text of the display list, treating
+/// consecutive items as separated by a single space unless one carries
+/// straight on from the other
+pub(crate) fn find_matches(display_list: &[DisplayItem], query: &str) -> Vec<FindMatch> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let query: Vec<char> = query.chars().map(fold).collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    // Flatten the page into folded characters, each pointing back at the
+    // item and byte range it came from
+    let mut chars: Vec<(char, Option<TextPosition>)> = Vec::new();
+    let mut last: Option<&TextItem> = None;
+    for (index, item) in display_list.iter().enumerate() {
+        let Some(item) = item.as_text() else {
+            continue;
+        };
+        if last.is_some_and(|last| !item.continues(last)) {
+            chars.push((' ', None));
+        }
+        last = Some(item);
+        for (offset, c) in item.text().char_indices() {
+            let start = item.range.start + offset;
+            chars.push((fold(c), Some((index, start..start + c.len_utf8()))));
+        }
+    }
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + query.len() <= chars.len() {
+        let candidate = &chars[start..start + query.len()];
+        if !candidate.iter().map(|(c, _)| *c).eq(query.iter().copied()) {
+            start += 1;
+            continue;
+        }
+
+        let mut found: FindMatch = Vec::new();
+        for (index, range) in candidate.iter().filter_map(|(_, source)| source.as_ref()) {
+            match found.last_mut() {
+                Some((last, last_range)) if last == index => last_range.end = range.end,
+                _ => found.push((*index, range.clone())),
+            }
+        }
+        matches.push(found);
+        start += query.len();
+    }
+
+    matches
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::http::{Response, last_header};
+
+// How many bytes of response bodies are kept by default
+pub(crate) const DEFAULT_HTTP_CACHE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Responses that came with an ETag or Last-Modified, by the URL they were
+/// fetched from. Asking for one again sends its validators along, so a
+/// server with nothing new can answer 304 Not Modified instead of sending
+/// the whole body again. Once they'd take up more than the budget, the
+/// least recently used are dropped.
+pub(crate) struct HttpCache {
+    // Each response along with when it was last used
+    responses: HashMap<String, (Response, u64)>,
+    budget: usize,
+    size: usize,
+    clock: u64,
+}
+
+impl HttpCache {
+    pub(crate) fn new(budget: usize) -> Self {
+        Self {
+            responses: HashMap::new(),
+            budget,
+            size: 0,
+            clock: 0,
+        }
+    }
+
+    /// The response kept for `url`, if any, counting as a use
+    pub(crate) fn get(&mut self, url: &str) -> Option<Response> {
+        self.clock += 1;
+        let (response, last_used) = self.responses.get_mut(url)?;
+        *last_used = self.clock;
+        Some(response.clone())
+    }
+
+    /// Keeps `response` as the one for `url` if it can be revalidated, or
+    /// forgets any kept before if it can't
+    pub(crate) fn store(&mut self, url: String, response: &Response) {
+        if let Some((old, _)) = self.responses.remove(&url) {
+            self.size -= old.body.len();
+        }
+        let no_store = response
+            .header_values("cache-control")
+            .iter()
+            .any(|value| value.to_ascii_lowercase().contains("no-store"));
+        if response.status != 200
+            || no_store
+            || validators(response).is_empty()
+            || response.body.len() > self.budget
+        {
+            return;
+        }
+        self.clock += 1;
+        self.size += response.body.len();
+        self.responses.insert(url, (response.clone(), self.clock));
+        while self.size > self.budget {
+            self.evict();
+        }
+    }
+
+    /// Drops the least recently used response
+    fn evict(&mut self) {
+        let Some(oldest) = self
+            .responses
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(url, _)| url.clone())
+        else {
+            return;
+        };
+        let (response, _) = self.responses.remove(&oldest).unwrap();
+        self.size -= response.body.len();
+    }
+}
+
+/// The headers that ask the server whether `response` is still current
+pub(crate) fn validators(response: &Response) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(etag) = last_header(&response.headers, "etag") {
+        headers.push(("If-None-Match".to_owned(), etag.to_owned()));
+    }
+    if let Some(modified) = last_header(&response.headers, "last-modified") {
+        headers.push(("If-Modified-Since".to_owned(), modified.to_owned()));
+    }
+    headers
+}
@@ -0,0 +1,103 @@
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+use crate::lexer::Tag;
+
+// How far into a page to look for a <meta> declaring its encoding
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Decodes a page body into text. A byte order mark wins, then a charset in
+/// the Content-Type header, then one declared by a <meta> tag near the start
+/// of the page, and otherwise it is taken to be UTF-8.
+pub(crate) fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(content_type_charset))
+        .or_else(|| meta_charset(body))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+/// Decodes a stylesheet into text. As with pages, a byte order mark wins,
+/// then a charset in the Content-Type header, then an @charset rule at the
+/// very start, and otherwise it is taken to be UTF-8.
+pub(crate) fn decode_stylesheet(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(content_type_charset))
+        .or_else(|| at_charset(body))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+/// The encoding an `@charset "name";` rule opening a stylesheet names. Like
+/// <meta> for pages, UTF-16 there can only be a mistake, so means UTF-8.
+fn at_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let rest = body.strip_prefix(b"@charset \"")?;
+    let end = rest.iter().position(|&byte| byte == b'"')?;
+    if !rest[end..].starts_with(b"\";") {
+        return None;
+    }
+    let encoding = Encoding::for_label(&rest[..end])?;
+    Some(if encoding == UTF_16LE || encoding == UTF_16BE {
+        UTF_8
+    } else {
+        encoding
+    })
+}
+
+/// The encoding named by the charset parameter of a Content-Type value, as in
+/// "text/html; charset=windows-1251"
+pub(crate) fn content_type_charset(value: &str) -> Option<&'static Encoding> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (name, label) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(label.trim().trim_matches(['"', '\'']).as_bytes())
+    })
+}
+
+/// Looks through the start of the page for <meta charset> or a Content-Type
+/// <meta http-equiv>. The tags themselves are ASCII in any encoding a page
+/// can declare this way, so they can be read before the page is decoded.
+pub(crate) fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    // A page that could be read this far as ASCII can't really be UTF-16,
+    // whatever it says, so it's taken to mean UTF-8
+    prescan(body).map(|encoding| {
+        if encoding == UTF_16LE || encoding == UTF_16BE {
+            UTF_8
+        } else {
+            encoding
+        }
+    })
+}
+
+fn prescan(body: &[u8]) -> Option<&'static Encoding> {
+    let start = String::from_utf8_lossy(&body[..body.len().min(PRESCAN_LENGTH)]);
+    let lowercase = start.to_ascii_lowercase();
+    let mut rest = lowercase.as_str();
+    while let Some(index) = rest.find("<meta") {
+        let tag = &rest[index + 1..];
+        let end = tag.find('>')?;
+        rest = &tag[end..];
+
+        let tag = Tag::parse(&tag[..end]);
+        if tag.name != "meta" {
+            continue;
+        }
+        if let Some(label) = tag.attributes.get("charset") {
+            return Encoding::for_label(label.trim().as_bytes());
+        }
+        if tag
+            .attributes
+            .get("http-equiv")
+            .is_some_and(|value| value.trim() == "content-type")
+            && let Some(content) = tag.attributes.get("content")
+        {
+            return content_type_charset(content);
+        }
+    }
+    None
+}
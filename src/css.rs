@@ -0,0 +1,516 @@
+use crate::browser::Browser;
+use crate::charset::decode_stylesheet;
+use crate::dom::{Element, Node, descendants, elements};
+use crate::font::{FontStyle, FontWeight};
+use crate::layout::TEXT_COLOR;
+use crate::url::URL;
+
+// The browser's own styles, which the page's override
+pub(crate) const USER_AGENT_STYLESHEET: &str = "
+    b, strong, th { font-weight: bold }
+    i, em { font-style: italic }
+    u, a:link, a:visited { text-decoration: underline }
+    s, strike, del { text-decoration: line-through }
+";
+
+pub(crate) const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("black", [0, 0, 0]),
+    ("silver", [192, 192, 192]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("white", [255, 255, 255]),
+    ("maroon", [128, 0, 0]),
+    ("red", [255, 0, 0]),
+    ("purple", [128, 0, 128]),
+    ("fuchsia", [255, 0, 255]),
+    ("green", [0, 128, 0]),
+    ("lime", [0, 255, 0]),
+    ("olive", [128, 128, 0]),
+    ("yellow", [255, 255, 0]),
+    ("navy", [0, 0, 128]),
+    ("blue", [0, 0, 255]),
+    ("teal", [0, 128, 128]),
+    ("aqua", [0, 255, 255]),
+    ("orange", [255, 165, 0]),
+];
+
+/// The style an element ends up with, once the rules that apply to it have
+/// been cascaded and what it doesn't set is inherited from its parent
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    pub(crate) color: [u8; 3],
+    pub(crate) font_weight: FontWeight,
+    pub(crate) font_style: FontStyle,
+    // Decorations aren't inherited as such, but a parent's are drawn through
+    // the text inside it too, so an element can only turn off its own
+    pub(crate) underline: bool,
+    pub(crate) strikethrough: bool,
+    // display: none, which leaves the element out altogether. This one isn't
+    // inherited, though nothing inside such an element is shown either.
+    pub(crate) display_none: bool,
+    // visibility: hidden, which leaves the space the element takes empty
+    pub(crate) hidden: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            color: TEXT_COLOR,
+            font_weight: FontWeight::Normal,
+            font_style: FontStyle::Normal,
+            underline: false,
+            strikethrough: false,
+            display_none: false,
+            hidden: false,
+        }
+    }
+}
+
+impl Style {
+    /// Sets the property `name` to `value`, leaving it as it was when the
+    /// value isn't one that's understood
+    pub(crate) fn set(&mut self, name: &str, value: &str) {
+        let value = value.to_ascii_lowercase();
+        match name {
+            "color" => {
+                if let Some(color) = parse_color(&value) {
+                    self.color = color;
+                }
+            }
+            "font-weight" => match value.as_str() {
+                "bold" | "bolder" => self.font_weight = FontWeight::Bold,
+                "normal" | "lighter" => self.font_weight = FontWeight::Normal,
+                _ => {
+                    if let Ok(weight) = value.parse::<u32>() {
+                        self.font_weight = if weight >= 600 {
+                            FontWeight::Bold
+                        } else {
+                            FontWeight::Normal
+                        };
+                    }
+                }
+            },
+            "font-style" => match value.as_str() {
+                "italic" | "oblique" => self.font_style = FontStyle::Italic,
+                "normal" => self.font_style = FontStyle::Normal,
+                _ => {}
+            },
+            "display" => self.display_none = value == "none",
+            "visibility" => match value.as_str() {
+                "hidden" | "collapse" => self.hidden = true,
+                "visible" => self.hidden = false,
+                _ => {}
+            },
+            // Any other words, like "none", leave the line off; the parent's
+            // decorations are added back once the element's style is cascaded
+            "text-decoration" | "text-decoration-line" => {
+                let words: Vec<&str> = value.split_whitespace().collect();
+                self.underline = words.contains(&"underline");
+                self.strikethrough = words.contains(&"line-through");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses a color as a name, #rgb, #rrggbb or rgb(r, g, b)
+pub(crate) fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<_>>()?;
+        return match digits[..] {
+            [r, g, b] => Some([r * 17, g * 17, b * 17]),
+            [r1, r2, g1, g2, b1, b2] => Some([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2]),
+            _ => None,
+        };
+    }
+    if let Some(arguments) = value
+        .strip_prefix("rgb(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let channels: Vec<u8> = arguments
+            .split(',')
+            .map(|channel| channel.trim().parse::<f32>().ok())
+            .map(|channel| channel.map(|channel| channel.clamp(0.0, 255.0) as u8))
+            .collect::<Option<_>>()?;
+        return channels.try_into().ok();
+    }
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, color)| *color)
+}
+
+/// One part of a selector, matching a single element by its tag name, id,
+/// classes and whether it's a visited link, any of which can be left out
+#[derive(Debug, Default)]
+pub(crate) struct Compound {
+    pub(crate) tag: Option<String>,
+    pub(crate) id: Option<String>,
+    pub(crate) classes: Vec<String>,
+    // :link or :visited, which only match links that haven't or have been
+    // visited
+    pub(crate) visited: Option<bool>,
+}
+
+impl Compound {
+    fn parse(text: &str) -> Option<Self> {
+        let mut compound = Compound::default();
+        let mut rest = text;
+        let name_end = rest.find(['#', '.', ':']).unwrap_or(rest.len());
+        match &rest[..name_end] {
+            "" | "*" => {}
+            name if name.chars().all(is_name_char) => {
+                compound.tag = Some(name.to_ascii_lowercase())
+            }
+            _ => return None,
+        }
+        rest = &rest[name_end..];
+        while let Some(marker) = rest.chars().next() {
+            let end = rest[1..]
+                .find(['#', '.', ':'])
+                .map_or(rest.len(), |end| end + 1);
+            let name = &rest[1..end];
+            if name.is_empty() || !name.chars().all(is_name_char) {
+                return None;
+            }
+            match (marker, name.to_ascii_lowercase().as_str()) {
+                ('#', _) => compound.id = Some(name.to_owned()),
+                ('.', _) => compound.classes.push(name.to_owned()),
+                (_, "link") => compound.visited = Some(false),
+                (_, "visited") => compound.visited = Some(true),
+                // Other pseudo-classes aren't supported
+                _ => return None,
+            }
+            rest = &rest[end..];
+        }
+        Some(compound)
+    }
+
+    fn matches(&self, element: &Element, visited: &dyn Fn(&str) -> bool) -> bool {
+        let attribute = |name: &str| element.tag.attributes.get(name);
+        self.tag.as_ref().is_none_or(|tag| tag == element.name())
+            && self.visited.is_none_or(|want| {
+                matches!(element.name(), "a" | "area")
+                    && attribute("href").is_some_and(|href| visited(href) == want)
+            })
+            && self
+                .id
+                .as_ref()
+                .is_none_or(|id| attribute("id").is_some_and(|value| value == id))
+            && self.classes.iter().all(|class| {
+                attribute("class")
+                    .is_some_and(|value| value.split_whitespace().any(|name| name == class))
+            })
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+/// A selector: compounds separated by spaces, each one matching an ancestor
+/// of the element the next one matches, and the last the element itself
+#[derive(Debug)]
+pub(crate) struct Selector {
+    pub(crate) compounds: Vec<Compound>,
+}
+
+impl Selector {
+    /// Parses a selector, or returns None for one using syntax that isn't
+    /// supported, like child combinators or pseudo-classes other than :link
+    /// and :visited, so that it never matches
+    fn parse(text: &str) -> Option<Self> {
+        let compounds = text
+            .split_whitespace()
+            .map(Compound::parse)
+            .collect::<Option<Vec<_>>>()?;
+        (!compounds.is_empty()).then_some(Self { compounds })
+    }
+
+    /// How specific the selector is: its ids, then its classes and
+    /// pseudo-classes, then its tag names. More specific selectors win over
+    /// less specific ones.
+    pub(crate) fn specificity(&self) -> (usize, usize, usize) {
+        self.compounds
+            .iter()
+            .fold((0, 0, 0), |(ids, classes, tags), compound| {
+                (
+                    ids + compound.id.is_some() as usize,
+                    classes + compound.classes.len() + compound.visited.is_some() as usize,
+                    tags + compound.tag.is_some() as usize,
+                )
+            })
+    }
+
+    /// Whether the selector matches `element`, whose ancestors are
+    /// `ancestors`, outermost first. `visited` tells whether a link's href
+    /// has been visited.
+    fn matches(
+        &self,
+        element: &Element,
+        ancestors: &[&Element],
+        visited: &dyn Fn(&str) -> bool,
+    ) -> bool {
+        let Some((last, rest)) = self.compounds.split_last() else {
+            return false;
+        };
+        if !last.matches(element, visited) {
+            return false;
+        }
+        // Match the rest against ancestors from the inside out, taking the
+        // innermost ancestor each compound can match
+        let mut ancestors = ancestors.iter().rev();
+        rest.iter()
+            .rev()
+            .all(|compound| ancestors.any(|ancestor| compound.matches(ancestor, visited)))
+    }
+}
+
+/// A style rule: the declarations that apply to elements any of its
+/// selectors match
+#[derive(Debug)]
+pub(crate) struct Rule {
+    pub(crate) selectors: Vec<Selector>,
+    pub(crate) declarations: Vec<(String, String)>,
+}
+
+/// Parses a stylesheet into its rules. Comments and at-rules like @media are
+/// skipped.
+pub(crate) fn parse_stylesheet(text: &str) -> Vec<Rule> {
+    let text = strip_comments(text);
+    let mut rules = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(open) = rest.find('{') {
+        // At-rules without a block, like @charset and @import, end at a
+        // semicolon instead
+        if rest.trim_start().starts_with('@')
+            && let Some(semicolon) = rest[..open].find(';')
+        {
+            rest = &rest[semicolon + 1..];
+            continue;
+        }
+        let prelude = rest[..open].trim();
+        // The block runs to the matching brace, so one with blocks nested
+        // inside it is skipped whole
+        let mut depth = 0;
+        let close = rest[open..]
+            .char_indices()
+            .find_map(|(index, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(open + index)
+            })
+            .unwrap_or(rest.len());
+        let block = rest.get(open + 1..close).unwrap_or_default();
+        if !prelude.starts_with('@') {
+            rules.push(Rule {
+                selectors: prelude.split(',').filter_map(Selector::parse).collect(),
+                declarations: parse_declarations(block),
+            });
+        }
+        rest = rest.get(close + 1..).unwrap_or_default();
+    }
+    rules
+}
+
+/// Parses the declarations in a rule's block or a style attribute, as
+/// lowercased property names and their values. Declarations marked
+/// !important aren't treated any differently.
+pub(crate) fn parse_declarations(text: &str) -> Vec<(String, String)> {
+    text.split(';')
+        .filter_map(|declaration| {
+            let (name, value) = declaration.split_once(':')?;
+            let value = value.trim();
+            let value = value.strip_suffix("!important").unwrap_or(value).trim();
+            let name = name.trim().to_ascii_lowercase();
+            (!name.is_empty() && !value.is_empty()).then(|| (name, value.to_owned()))
+        })
+        .collect()
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Computes the style of every element among `nodes` and their descendants.
+/// Each stylesheet, like the browser's own or the page's, overrides the ones
+/// before it; within one, more specific rules override less specific ones,
+/// then later rules earlier ones. A style attribute overrides them all.
+/// `visited` tells whether a link's href has been visited.
+pub(crate) fn compute_styles(
+    nodes: &mut [Node],
+    stylesheets: &[Vec<Rule>],
+    visited: &dyn Fn(&str) -> bool,
+) {
+    compute(
+        nodes,
+        &Style::default(),
+        stylesheets,
+        visited,
+        &mut Vec::new(),
+    );
+}
+
+fn compute(
+    nodes: &mut [Node],
+    parent: &Style,
+    stylesheets: &[Vec<Rule>],
+    visited: &dyn Fn(&str) -> bool,
+    ancestors: &mut Vec<Element>,
+) {
+    for node in nodes {
+        let Node::Element(element) = node else {
+            continue;
+        };
+        let mut style = Style {
+            display_none: false,
+            ..parent.clone()
+        };
+        let outer: Vec<&Element> = ancestors.iter().collect();
+        for rules in stylesheets {
+            let mut matched: Vec<_> = rules
+                .iter()
+                .flat_map(|rule| {
+                    rule.selectors
+                        .iter()
+                        .filter(|selector| selector.matches(element, &outer, visited))
+                        .map(|selector| (selector.specificity(), &rule.declarations))
+                        .max_by_key(|(specificity, _)| *specificity)
+                })
+                .collect();
+            // Sorting is stable, so rules of the same specificity keep their
+            // order
+            matched.sort_by_key(|(specificity, _)| *specificity);
+            for (_, declarations) in matched {
+                for (name, value) in declarations {
+                    style.set(name, value);
+                }
+            }
+        }
+        if let Some(inline) = element.tag.attributes.get("style") {
+            for (name, value) in parse_declarations(inline) {
+                style.set(&name, &value);
+            }
+        }
+        style.underline |= parent.underline;
+        style.strikethrough |= parent.strikethrough;
+        element.style = style;
+
+        // Ancestors are only matched by their tags, so a childless copy will do
+        ancestors.push(Element::new(element.tag.clone()));
+        compute(
+            &mut element.children,
+            &element.style,
+            stylesheets,
+            visited,
+            ancestors,
+        );
+        ancestors.pop();
+    }
+}
+
+impl Browser {
+    /// Fetches the stylesheets the page links to with <link rel=stylesheet>,
+    /// keeping their text by href as written. Any that fail are left out.
+    pub(crate) fn load_stylesheets(&mut self, base: Option<&URL>) {
+        self.stylesheets.clear();
+        let mut hrefs: Vec<String> = Vec::new();
+        let mut urls = Vec::new();
+        for element in elements(&self.document).filter(|element| is_stylesheet_link(element)) {
+            let Some(href) = element.tag.attributes.get("href") else {
+                continue;
+            };
+            let url = match base {
+                Some(base) => base.resolve(href),
+                None => URL::new(href).ok(),
+            };
+            if hrefs.contains(href) {
+                continue;
+            }
+            let Some(url) = url else {
+                continue;
+            };
+            hrefs.push(href.clone());
+            urls.push(url);
+        }
+
+        let fetched = self.http_client.fetch_all(&urls);
+        for (href, response) in hrefs.into_iter().zip(fetched) {
+            let text = response
+                .map(|response| decode_stylesheet(&response.body, response.header("content-type")));
+            match text {
+                Ok(text) => {
+                    self.stylesheets.insert(href, text);
+                }
+                Err(err) => eprintln!("Failed to load stylesheet {}: {}", href, err),
+            }
+        }
+    }
+
+    /// Works out the style of every element on the page, from the browser's
+    /// own stylesheet and then the page's: its <style> elements and linked
+    /// stylesheets in the order they appear
+    pub(crate) fn compute_styles(&mut self) {
+        let mut page = Vec::new();
+        for element in elements(&self.document) {
+            if element.name() == "style" {
+                let text: String = descendants(&element.children)
+                    .filter_map(|node| match node {
+                        Node::Text(text) => Some(text.as_ref()),
+                        Node::Element(_) => None,
+                    })
+                    .collect();
+                page.extend(parse_stylesheet(&text));
+            } else if is_stylesheet_link(element)
+                && let Some(text) = element
+                    .tag
+                    .attributes
+                    .get("href")
+                    .and_then(|href| self.stylesheets.get(href))
+            {
+                page.extend(parse_stylesheet(text));
+            }
+        }
+        // Links are colored by the browser's settings, unless the page says
+        // otherwise
+        let hex = |[r, g, b]: [u8; 3]| format!("#{:02x}{:02x}{:02x}", r, g, b);
+        let links = format!(
+            "a:link {{ color: {} }} a:visited {{ color: {} }}",
+            hex(self.link_color),
+            hex(self.visited_link_color)
+        );
+        let user_agent = format!("{}{}", USER_AGENT_STYLESHEET, links);
+        let stylesheets = [parse_stylesheet(&user_agent), page];
+        let visited = |href: &str| {
+            self.base
+                .as_ref()
+                .and_then(|base| base.resolve(href))
+                .is_some_and(|url| self.visited.contains(&url.to_string()))
+        };
+        compute_styles(&mut self.document, &stylesheets, &visited);
+    }
+}
+
+fn is_stylesheet_link(element: &Element) -> bool {
+    element.name() == "link"
+        && element.tag.attributes.get("rel").is_some_and(|rel| {
+            rel.split_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("stylesheet"))
+        })
+}
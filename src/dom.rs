@@ -0,0 +1,383 @@
+use std::rc::Rc;
+
+use crate::css::Style;
+use crate::lexer::{Tag, Token};
+
+// Elements that never have content, so nothing is put inside them
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+// Elements that end an open <p>, since a paragraph can't contain them
+pub(crate) const CLOSES_PARAGRAPH: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dd",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+// Elements that style the text inside them. When one is closed early by
+// another element ending, it carries on in a new element after it, as
+// "<b>bold <i>both</b> italic</i>" does in browsers.
+pub(crate) const FORMATTING_ELEMENTS: &[&str] = &[
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+// Elements that describe the page rather than being part of what's shown,
+// which go in the <head>. They're never laid out, even if they turn up in the
+// body.
+pub(crate) const HEAD_ELEMENTS: &[&str] = &[
+    "base", "link", "meta", "script", "style", "template", "title",
+];
+// Elements that tags inside them can't implicitly close, or reach past to
+// close something outside. Nor does formatting carry on out of them.
+pub(crate) const SCOPE_BOUNDARIES: &[&str] = &["table", "tr", "td", "th", "caption", "button"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(Rc<str>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Element {
+    // Always an opening tag, with the element's attributes
+    pub tag: Tag,
+    pub children: Vec<Node>,
+    // Worked out once the page's stylesheets are known
+    pub style: Style,
+}
+
+impl Element {
+    pub(crate) fn new(tag: Tag) -> Self {
+        Self {
+            tag,
+            children: Vec::new(),
+            style: Style::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.tag.name
+    }
+}
+
+/// Builds the tree of elements and text that a page's tokens describe,
+/// returning the nodes at the top level: an <html> with a <head> and a
+/// <body>. Like browsers, this fills in what the markup leaves out: the
+/// <head> holds the metadata before the page's content starts and the
+/// <body> the rest, whether or not their tags are there, and elements left
+/// open are closed where they have to end, e.g. a <p> at the start of the
+/// next paragraph or an <li> at the next item, or else at the end of the
+/// page. Closing tags with nothing open to close are dropped.
+pub fn parse(tokens: Vec<Token>) -> Vec<Node> {
+    // The open elements, outermost first, starting from the <html>
+    let mut open = vec![Element::new(Tag::parse("html"))];
+    let mut section = Section::BeforeHead;
+    for token in tokens {
+        if !place(&mut open, &mut section, &token) {
+            continue;
+        }
+        match token {
+            Token::Text(text) => append(&mut open, Node::Text(text)),
+            Token::Tag(tag) if tag.closing => close(&mut open, &tag.name),
+            Token::Tag(tag) => {
+                let name = tag.name.as_str();
+                if CLOSES_PARAGRAPH.contains(&name) {
+                    close_implied(&mut open, &["p"], SCOPE_BOUNDARIES);
+                }
+                match name {
+                    "li" => close_implied(
+                        &mut open,
+                        &["li"],
+                        &[SCOPE_BOUNDARIES, &["ul", "ol"]].concat(),
+                    ),
+                    "dd" | "dt" => close_implied(
+                        &mut open,
+                        &["dd", "dt"],
+                        &[SCOPE_BOUNDARIES, &["dl"]].concat(),
+                    ),
+                    // A new row or cell ends the one before it, but not one in
+                    // a table around this one
+                    "tr" => close_implied(&mut open, &["tr"], &["table"]),
+                    "td" | "th" => close_implied(&mut open, &["td", "th"], &["tr", "table"]),
+                    // As does a new section of the table, with its rows
+                    "thead" | "tbody" | "tfoot" => {
+                        close_implied(&mut open, &["thead", "tbody", "tfoot"], &["table"])
+                    }
+                    "option" => close_implied(&mut open, &["option"], &["select"]),
+                    // A group of options ends the option and group before it
+                    "optgroup" => {
+                        close_implied(&mut open, &["option"], &["select"]);
+                        close_implied(&mut open, &["optgroup"], &["select"]);
+                    }
+                    // Headings don't nest
+                    "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+                        if open
+                            .last()
+                            .is_some_and(|element| is_heading(element.name())) =>
+                    {
+                        let last = open.len() - 1;
+                        close_at(&mut open, last);
+                    }
+                    _ => {}
+                }
+
+                if VOID_ELEMENTS.contains(&name) {
+                    append(&mut open, Node::Element(Element::new(tag)));
+                } else {
+                    open.push(Element::new(tag));
+                }
+            }
+        }
+    }
+
+    if section != Section::Body {
+        start_body(&mut open, &mut section, Tag::parse("body"));
+    }
+    while open.len() > 1 {
+        pop(&mut open);
+    }
+    vec![Node::Element(open.pop().unwrap())]
+}
+
+/// The part of the document a page's tokens have reached
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    BeforeHead,
+    Head,
+    // A head opened by metadata rather than a <head> tag
+    ImplicitHead,
+    AfterHead,
+    Body,
+}
+
+/// Opens the <head> or <body> if `token` starts one, explicitly or not,
+/// returning whether the token still needs adding to the tree. <html>,
+/// <head> and <body> tags themselves only add their attributes.
+fn place(open: &mut Vec<Element>, section: &mut Section, token: &Token) -> bool {
+    if let Token::Tag(tag) = token {
+        match (tag.name.as_str(), tag.closing) {
+            ("html", false) => merge_attributes(&mut open[0], tag),
+            ("head", false) if *section == Section::BeforeHead => {
+                open.push(Element::new(tag.clone()));
+                *section = Section::Head;
+            }
+            ("head", true) if matches!(*section, Section::Head | Section::ImplicitHead) => {
+                while open.len() > 1 {
+                    pop(open);
+                }
+                *section = Section::AfterHead;
+            }
+            ("body", false) if *section != Section::Body => start_body(open, section, tag.clone()),
+            ("body", false) => merge_attributes(&mut open[1], tag),
+            // Anything after </body> or </html> still goes in the body
+            ("html" | "head" | "body", _) => {}
+            _ => return place_content(open, section, token),
+        }
+        return false;
+    }
+    place_content(open, section, token)
+}
+
+fn place_content(open: &mut Vec<Element>, section: &mut Section, token: &Token) -> bool {
+    match (*section, token) {
+        (Section::Body, _) => {}
+        // Metadata goes in the head, even after it's been closed
+        (_, Token::Tag(tag)) if !tag.closing && HEAD_ELEMENTS.contains(&tag.name.as_str()) => {
+            if !matches!(*section, Section::Head | Section::ImplicitHead) {
+                let head = match open[0].children.pop() {
+                    Some(Node::Element(head)) => head,
+                    _ => Element::new(Tag::parse("head")),
+                };
+                open.push(head);
+                *section = Section::ImplicitHead;
+            }
+        }
+        // The head ends at an element for the body
+        (Section::Head | Section::ImplicitHead, Token::Tag(tag)) if !tag.closing => {
+            start_body(open, section, Tag::parse("body"))
+        }
+        // Text the page put in a <head> stays there, but text after metadata
+        // that wasn't in one starts the body
+        (Section::ImplicitHead, Token::Text(text))
+            if open.len() == 2 && !text.trim().is_empty() =>
+        {
+            start_body(open, section, Tag::parse("body"))
+        }
+        (Section::Head | Section::ImplicitHead, _) => {}
+        // Space between the tags around the head isn't content
+        (_, Token::Text(text)) if text.trim().is_empty() => return false,
+        (_, Token::Tag(tag)) if tag.closing => return false,
+        _ => start_body(open, section, Tag::parse("body")),
+    }
+    true
+}
+
+/// Closes the head, adding an empty one if there wasn't any, and opens the
+/// body
+fn start_body(open: &mut Vec<Element>, section: &mut Section, tag: Tag) {
+    while open.len() > 1 {
+        pop(open);
+    }
+    if open[0].children.is_empty() {
+        append(open, Node::Element(Element::new(Tag::parse("head"))));
+    }
+    open.push(Element::new(tag));
+    *section = Section::Body;
+}
+
+/// Adds attributes from a repeated <html> or <body> tag that the element
+/// doesn't have yet
+fn merge_attributes(element: &mut Element, tag: &Tag) {
+    for (key, value) in &tag.attributes {
+        element
+            .tag
+            .attributes
+            .entry(key.clone())
+            .or_insert_with(|| value.clone());
+    }
+}
+
+/// The document's <head> or <body>, as `parse` returns it
+pub(crate) fn section<'a>(document: &'a [Node], name: &str) -> Option<&'a Element> {
+    let html = document.iter().find_map(|node| match node {
+        Node::Element(html) if html.name() == "html" => Some(html),
+        _ => None,
+    })?;
+    html.children.iter().find_map(|node| match node {
+        Node::Element(element) if element.name() == name => Some(element),
+        _ => None,
+    })
+}
+
+/// The content of the document's <head>: its title, metadata and the like
+pub fn head(document: &[Node]) -> &[Node] {
+    section(document, "head").map_or(&[], |head| &head.children)
+}
+
+/// The content of the document's <body>, which is what's shown
+pub fn body(document: &[Node]) -> &[Node] {
+    section(document, "body").map_or(&[], |body| &body.children)
+}
+
+/// Every node among `nodes` and their descendants, in document order
+pub(crate) fn descendants(nodes: &[Node]) -> impl Iterator<Item = &Node> {
+    let mut stack: Vec<&Node> = nodes.iter().rev().collect();
+    std::iter::from_fn(move || {
+        let node = stack.pop()?;
+        if let Node::Element(element) = node {
+            stack.extend(element.children.iter().rev());
+        }
+        Some(node)
+    })
+}
+
+/// Every element among `nodes` and their descendants, in document order
+pub(crate) fn elements(nodes: &[Node]) -> impl Iterator<Item = &Element> {
+    descendants(nodes).filter_map(|node| match node {
+        Node::Element(element) => Some(element),
+        Node::Text(_) => None,
+    })
+}
+
+fn is_heading(name: &str) -> bool {
+    matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+fn append(open: &mut [Element], node: Node) {
+    open.last_mut().unwrap().children.push(node);
+}
+
+/// Closes the innermost open element, putting it inside its parent
+fn pop(open: &mut Vec<Element>) {
+    let element = open.pop().unwrap();
+    append(open, Node::Element(element));
+}
+
+/// Where the innermost open `names` element is, if there's one that isn't
+/// hidden behind a `boundaries` element
+fn find_open(open: &[Element], names: &[&str], boundaries: &[&str]) -> Option<usize> {
+    for (index, element) in open.iter().enumerate().skip(1).rev() {
+        let name = element.name();
+        if names.contains(&name) {
+            return Some(index);
+        }
+        if boundaries.contains(&name) {
+            return None;
+        }
+    }
+    None
+}
+
+/// Closes the element at `index` and everything inside it that's still
+/// open, then reopens any formatting elements among those so their style
+/// carries on after it
+fn close_at(open: &mut Vec<Element>, index: usize) {
+    let mut reopen = Vec::new();
+    while open.len() > index {
+        let element = open.last().unwrap();
+        let name = element.name();
+        if open.len() > index + 1 && FORMATTING_ELEMENTS.contains(&name) {
+            reopen.push(element.tag.clone());
+        } else if SCOPE_BOUNDARIES.contains(&name) {
+            reopen.clear();
+        }
+        pop(open);
+    }
+    open.extend(reopen.into_iter().rev().map(Element::new));
+}
+
+/// Closes the open element an opening tag implies the end of, if there is one
+fn close_implied(open: &mut Vec<Element>, names: &[&str], boundaries: &[&str]) {
+    if let Some(index) = find_open(open, names, boundaries) {
+        close_at(open, index);
+    }
+}
+
+/// Handles the closing tag for `name`
+fn close(open: &mut Vec<Element>, name: &str) {
+    // Table parts close whatever is left open inside them, cells included
+    let boundaries: &[&str] = match name {
+        "table" => &[],
+        "tr" | "td" | "th" | "thead" | "tbody" | "tfoot" | "caption" => &["table"],
+        _ => SCOPE_BOUNDARIES,
+    };
+    match find_open(open, &[name], boundaries) {
+        Some(index) => close_at(open, index),
+        // A </p> with no paragraph open still ends one, an empty one
+        None if name == "p" => append(open, Node::Element(Element::new(Tag::parse("p")))),
+        None => {}
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+
+use crate::http::TlsError;
+
+/// Everything that can go wrong loading a page
+#[derive(Debug)]
+pub enum BrowserError {
+    /// A URL that can't be parsed, or uses a scheme that isn't supported
+    Url(String),
+    /// A request that can't be made as asked, like one with a malformed
+    /// header
+    Request(String),
+    /// A server that broke off the exchange or didn't speak HTTP properly,
+    /// or a response the client won't accept, like an oversized body
+    Network(String),
+    /// A secure connection that couldn't be set up
+    Tls(TlsError),
+    /// Content that can't be decoded, like a broken image
+    Encoding(String),
+    /// Reading a file or a socket failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for BrowserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BrowserError::Url(message)
+            | BrowserError::Request(message)
+            | BrowserError::Network(message)
+            | BrowserError::Encoding(message) => write!(f, "{}", message),
+            BrowserError::Tls(err) => write!(f, "{}", err),
+            BrowserError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for BrowserError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BrowserError::Tls(err) => Some(err),
+            BrowserError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for BrowserError {
+    fn from(err: std::io::Error) -> Self {
+        BrowserError::Io(err)
+    }
+}
+
+impl From<TlsError> for BrowserError {
+    fn from(err: TlsError) -> Self {
+        BrowserError::Tls(err)
+    }
+}
@@ -0,0 +1,92 @@
+use std::rc::Rc;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use image::RgbaImage;
+
+use crate::browser::Browser;
+use crate::dom::{elements, head};
+use crate::http::Progress;
+use crate::url::URL;
+
+impl Browser {
+    /// The page's icon: the first <link rel="icon"> in its head, or failing
+    /// that /favicon.ico on its server. It's fetched in the background the
+    /// first time it's asked for, and `tick` picks it up once it arrives.
+    /// None until then, if there isn't one or it couldn't be loaded, or while
+    /// the page is still loading.
+    pub fn favicon(&mut self) -> Option<Rc<RgbaImage>> {
+        if self.is_loading() {
+            return None;
+        }
+        if let Some(favicon) = &self.favicon {
+            return favicon.clone();
+        }
+        if self.favicon_loading.is_none() {
+            match self.favicon_url() {
+                Some(url) => self.start_favicon(url),
+                None => self.favicon = Some(None),
+            }
+        }
+        None
+    }
+
+    /// Fetches the icon at `url` on another thread
+    fn start_favicon(&mut self, url: URL) {
+        let (sender, receiver) = mpsc::channel();
+        let mut client = self.http_client.fork();
+        if let Some(connection) = self.http_client.take_idle(&url) {
+            client.release(&url, connection);
+        }
+        thread::spawn(move || {
+            // Any icon that can't be loaded is as good as none
+            let image = url
+                .fetch(&mut client)
+                .ok()
+                .and_then(|response| image::load_from_memory(&response.body).ok())
+                .map(|image| image.to_rgba8());
+            // Nobody is listening any more if another page was loaded since
+            let _ = sender.send(Progress::Icon(image, Box::new(client)));
+        });
+        self.favicon_loading = Some(receiver);
+    }
+
+    /// Keeps the page's icon if it's arrived
+    pub(crate) fn poll_favicon(&mut self) {
+        let Some(receiver) = &self.favicon_loading else {
+            return;
+        };
+        let favicon = match receiver.try_recv() {
+            Ok(Progress::Icon(image, client)) => {
+                self.http_client.join(*client);
+                image.map(Rc::new)
+            }
+            Err(TryRecvError::Empty) => return,
+            Ok(_) | Err(TryRecvError::Disconnected) => None,
+        };
+        self.favicon = Some(favicon);
+        self.favicon_loading = None;
+        self.dirty = true;
+    }
+
+    /// Where the page's icon is, resolved against its base
+    fn favicon_url(&self) -> Option<URL> {
+        let base = self.base.as_ref()?;
+        let href = elements(head(&self.document))
+            .filter(|element| element.name() == "link")
+            .filter(|element| {
+                // Including the old "shortcut icon"
+                element.tag.attributes.get("rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("icon"))
+                })
+            })
+            .find_map(|element| element.tag.attributes.get("href"));
+        match href {
+            Some(href) => base.resolve(href),
+            // Only web servers are expected to have one there
+            None if base.scheme == "http" || base.scheme == "https" => base.resolve("/favicon.ico"),
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use ab_glyph::FontRef;
+use rustybuzz::Face;
+
+use font_kit::family_name::FamilyName;
+use font_kit::handle::Handle;
+use font_kit::properties::{Properties, Style, Weight};
+use font_kit::source::{Source, SystemSource};
+
+// Tried in order for the page's base font, unless --font picks another. These
+// cover the usual sans-serif fonts on macOS, Windows and Linux.
+pub(crate) const DEFAULT_FONT_FAMILIES: &[&str] = &[
+    "Arial Unicode MS",
+    "Helvetica",
+    "Arial",
+    "Segoe UI",
+    "DejaVu Sans",
+    "Liberation Sans",
+    "Noto Sans",
+];
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum FontWeight {
+    Normal,
+    Bold,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum FontStyle {
+    Normal,
+    Italic,
+    #[allow(dead_code)]
+    Oblique,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) struct FontProperties {
+    pub(crate) font_family: String,
+    pub(crate) font_weight: FontWeight,
+    pub(crate) font_style: FontStyle,
+}
+
+impl Default for FontProperties {
+    fn default() -> Self {
+        Self {
+            font_family: DEFAULT_FONT_FAMILIES[0].into(),
+            font_weight: FontWeight::Normal,
+            font_style: FontStyle::Normal,
+        }
+    }
+}
+
+impl FontProperties {
+    /// The same family, upright and at normal weight
+    pub(crate) fn regular(&self) -> Self {
+        Self {
+            font_family: self.font_family.clone(),
+            font_weight: FontWeight::Normal,
+            font_style: FontStyle::Normal,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct FontSize(pub(crate) u32);
+
+/// Splits a comma-separated font family list, like
+/// `"Helvetica", Arial, sans-serif`, into the families to try in order
+pub(crate) fn family_names(list: &str) -> Vec<FamilyName> {
+    list.split(',')
+        .map(|name| name.trim().trim_matches(['"', '\'']).trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| match name.to_lowercase().as_str() {
+            "serif" => FamilyName::Serif,
+            "sans-serif" => FamilyName::SansSerif,
+            "monospace" => FamilyName::Monospace,
+            "cursive" => FamilyName::Cursive,
+            "fantasy" => FamilyName::Fantasy,
+            _ => FamilyName::Title(name.to_owned()),
+        })
+        .collect()
+}
+
+// Used when no system font can be found or loaded, so there is always
+// something to render with
+pub(crate) static BUNDLED_FONT: &[u8] = include_bytes!("../assets/Cantarell-Regular.ttf");
+
+#[derive(Debug)]
+pub(crate) enum FontError {
+    NotFound(String),
+    Load(String, String),
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FontError::NotFound(family) => write!(f, "No font found for {:?}", family),
+            FontError::Load(family, reason) => {
+                write!(f, "Couldn't load the font for {:?}: {}", family, reason)
+            }
+        }
+    }
+}
+
+impl Error for FontError {}
+
+#[derive(Clone, Copy)]
+pub(crate) struct CachedFont {
+    pub(crate) ab_font: &'static FontRef<'static>,
+    pub(crate) rb_face: &'static Face<'static>,
+}
+
+pub(crate) struct FontManager {
+    pub(crate) source: SystemSource,
+    pub(crate) cached_fonts: HashMap<FontProperties, CachedFont>,
+    // Fonts by the file (and index within it) they were loaded from, since
+    // several properties often resolve to the same file, e.g. bold in a family
+    // without a bold face
+    pub(crate) loaded_fonts: HashMap<(PathBuf, u32), CachedFont>,
+    pub(crate) bundled: Option<CachedFont>,
+    // Turned off to render everything in the bundled font, so output doesn't
+    // depend on which fonts the machine has installed
+    pub(crate) system_fonts: bool,
+}
+
+impl FontManager {
+    pub(crate) fn new() -> Self {
+        Self {
+            source: SystemSource::new(),
+            cached_fonts: HashMap::new(),
+            loaded_fonts: HashMap::new(),
+            bundled: None,
+            system_fonts: true,
+        }
+    }
+
+    /// Picks the base font family: `requested` if any family in it is
+    /// installed, otherwise the first of `DEFAULT_FONT_FAMILIES` that is
+    pub(crate) fn default_family(&self, requested: Option<&str>) -> String {
+        if !self.system_fonts {
+            return requested.unwrap_or(DEFAULT_FONT_FAMILIES[0]).to_owned();
+        }
+
+        if let Some(requested) = requested {
+            if family_names(requested)
+                .iter()
+                .any(|name| self.source.select_family_by_generic_name(name).is_ok())
+            {
+                return requested.to_owned();
+            }
+            eprintln!(
+                "Warning: font family {:?} was not found, using the default",
+                requested
+            );
+        }
+
+        DEFAULT_FONT_FAMILIES
+            .iter()
+            .find(|family| self.source.select_family_by_name(family).is_ok())
+            .unwrap_or(&DEFAULT_FONT_FAMILIES[0])
+            .to_string()
+    }
+
+    /// Finds the font for `font_properties`, falling back to the bundled font
+    /// if the system can't provide one
+    pub(crate) fn get_fonts(
+        &mut self,
+        font_properties: &FontProperties,
+    ) -> (&'static FontRef<'static>, &'static Face<'static>) {
+        if let Some(cached) = self.cached_fonts.get(font_properties) {
+            return (cached.ab_font, cached.rb_face);
+        }
+
+        if !self.system_fonts {
+            let bundled = self.bundled_font();
+            return (bundled.ab_font, bundled.rb_face);
+        }
+
+        let cached = self.load_font(font_properties).unwrap_or_else(|err| {
+            eprintln!("Warning: {}, using the bundled font", err);
+            self.bundled_font()
+        });
+        // Failures are cached too, so they're only reported once
+        self.cached_fonts.insert(font_properties.clone(), cached);
+
+        (cached.ab_font, cached.rb_face)
+    }
+
+    pub(crate) fn load_font(
+        &mut self,
+        font_properties: &FontProperties,
+    ) -> Result<CachedFont, FontError> {
+        let family = &font_properties.font_family;
+        let weight = match font_properties.font_weight {
+            FontWeight::Bold => Weight::BOLD,
+            _ => Weight::NORMAL,
+        };
+
+        let style = match font_properties.font_style {
+            FontStyle::Italic => Style::Italic,
+            FontStyle::Oblique => Style::Oblique,
+            _ => Style::Normal,
+        };
+
+        let mut properties = Properties::new();
+        properties.style = style;
+        properties.weight = weight;
+        let mut names = family_names(family);
+        names.extend([FamilyName::SansSerif, FamilyName::Serif]);
+        let handle = self
+            .source
+            .select_best_match(&names, &properties)
+            .map_err(|_| FontError::NotFound(family.clone()))?;
+        let load_error =
+            |err: &dyn std::fmt::Display| FontError::Load(family.clone(), err.to_string());
+        let (key, font_data) = match handle {
+            Handle::Path { path, font_index } => {
+                let key = (path, font_index);
+                if let Some(cached) = self.loaded_fonts.get(&key) {
+                    return Ok(*cached);
+                }
+                let font_data = fs::read(&key.0).map_err(|err| load_error(&err))?;
+                (Some(key), font_data)
+            }
+            Handle::Memory { bytes, font_index } => {
+                if font_index != 0 {
+                    return Err(load_error(&"unsupported font collection"));
+                }
+                (None, bytes.to_vec())
+            }
+        };
+        let font_index = key.as_ref().map_or(0, |(_, font_index)| *font_index);
+
+        // Use Box::leak() to give references a static lifetime, saving a lot of
+        // time and headache
+        let static_font_data: &'static [u8] = Box::leak(font_data.into_boxed_slice());
+
+        let ab_font = FontRef::try_from_slice_and_index(static_font_data, font_index)
+            .map_err(|err| load_error(&err))?;
+        let rb_face = Face::from_slice(static_font_data, font_index)
+            .ok_or_else(|| load_error(&"couldn't parse the font face"))?;
+        let cached = CachedFont {
+            ab_font: Box::leak(Box::new(ab_font)),
+            rb_face: Box::leak(Box::new(rb_face)),
+        };
+        if let Some(key) = key {
+            self.loaded_fonts.insert(key, cached);
+        }
+        Ok(cached)
+    }
+
+    pub(crate) fn bundled_font(&mut self) -> CachedFont {
+        *self.bundled.get_or_insert_with(|| CachedFont {
+            ab_font: Box::leak(Box::new(
+                FontRef::try_from_slice(BUNDLED_FONT).expect("bundled font should be valid"),
+            )),
+            rb_face: Box::leak(Box::new(
+                Face::from_slice(BUNDLED_FONT, 0).expect("bundled font should be valid"),
+            )),
+        })
+    }
+}
@@ -0,0 +1,391 @@
+use ab_glyph::{Font, FontRef, ScaleFont};
+use rustybuzz::Face;
+
+use crate::browser::Browser;
+use crate::dom::{Element, Node, descendants};
+use crate::error::BrowserError;
+use crate::layout::{DisplayItem, Layout, LineItem, TextItem};
+use crate::lexer::{Tag, words};
+use crate::url::URL;
+
+// How wide a text <input> is
+pub(crate) const INPUT_WIDTH: u32 = 200;
+// How submitted forms are encoded in a POST body
+pub(crate) const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+// The gap between the edge of an input or button and the text inside it
+pub(crate) const INPUT_PADDING: u32 = 4;
+// Shown in a password input in place of each character typed
+pub(crate) const PASSWORD_MASK: char = '\u{2022}';
+
+/// A <form>, which the inputs inside it refer to by index
+#[derive(Clone)]
+pub(crate) struct Form {
+    pub(crate) action: String,
+    // Lowercased, and either "get" or "post"
+    pub(crate) method: String,
+}
+
+/// The box for a text <input>, or a submit button, with its text positioned
+/// relative to the box's top left corner
+pub(crate) struct InputItem {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    // How far the text's baseline is below the top of the box
+    pub(crate) baseline: u32,
+    // Counts the inputs on the page in order, so edits can be found again
+    // after the page is laid out anew
+    pub(crate) index: usize,
+    pub(crate) form: Option<usize>,
+    pub(crate) submit: bool,
+    // A password input, whose label masks its value
+    pub(crate) password: bool,
+    pub(crate) name: Option<String>,
+    pub(crate) value: String,
+    pub(crate) label: Option<TextItem>,
+}
+
+/// Encodes form fields as application/x-www-form-urlencoded, the format of
+/// both GET query strings and POST bodies
+pub(crate) fn form_urlencode(fields: &[(String, String)]) -> String {
+    fields
+        .iter()
+        .map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Percent-encodes every byte except letters, digits and `*-._`, with spaces
+/// becoming `+`
+pub(crate) fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+impl Layout {
+    /// Lays out an <input>. Only text and password inputs and submit buttons
+    /// are shown; other types are left out.
+    pub(crate) fn input(
+        &mut self,
+        tag: &Tag,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let kind = tag
+            .attributes
+            .get("type")
+            .map_or("text".to_owned(), |kind| kind.trim().to_lowercase());
+        let name = tag.attributes.get("name").cloned();
+        let value = tag.attributes.get("value").cloned();
+        let input = match kind.as_str() {
+            "text" | "search" => {
+                self.input_item(false, name, value.unwrap_or_default(), None, font, face)
+            }
+            // Edited like a text input, but showing a mask in place of each
+            // character
+            "password" => {
+                let mut input =
+                    self.input_item(false, name, value.unwrap_or_default(), None, font, face);
+                let mask = PASSWORD_MASK
+                    .to_string()
+                    .repeat(input.value.chars().count());
+                input.label = self
+                    .input_label(&mask, input.baseline, font, face)
+                    .map(|(label, _)| label);
+                input.password = true;
+                input
+            }
+            "submit" => {
+                let value = value.unwrap_or_else(|| "Submit".to_owned());
+                self.input_item(true, name, value, None, font, face)
+            }
+            _ => return,
+        };
+        self.push_input(input, font);
+    }
+
+    /// Lays out a <button>, labelled with the text inside it
+    pub(crate) fn button(
+        &mut self,
+        button: &Element,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let label: Vec<&str> = descendants(&button.children)
+            .filter_map(|node| match node {
+                Node::Text(text) => Some(text),
+                Node::Element(_) => None,
+            })
+            .flat_map(|text| words(text).map(|(_, word)| word))
+            .collect();
+
+        let tag = &button.tag;
+
+        let name = tag.attributes.get("name").cloned();
+        let value = tag.attributes.get("value").cloned().unwrap_or_default();
+        let mut input = self.input_item(true, name, value, Some(label.join(" ")), font, face);
+        // Buttons of other types, like type=button, don't submit anything
+        if tag
+            .attributes
+            .get("type")
+            .is_some_and(|kind| !kind.trim().eq_ignore_ascii_case("submit"))
+        {
+            input.form = None;
+        }
+        self.push_input(input, font);
+    }
+
+    /// Builds the box for an input showing `label`, or its value when there's
+    /// no label. Text inputs pick up any edits made to them.
+    pub(crate) fn input_item(
+        &mut self,
+        submit: bool,
+        name: Option<String>,
+        value: String,
+        label: Option<String>,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) -> InputItem {
+        let index = self.inputs;
+        self.inputs += 1;
+        let value = match self.input_values.get(&index) {
+            Some(edited) if !submit => edited.clone(),
+            _ => value,
+        };
+        let label = label.unwrap_or_else(|| value.clone());
+
+        // Font size should be set in pt, not px
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+        let scaled_font = font.as_scaled(scale);
+        let baseline = INPUT_PADDING + scaled_font.ascent() as u32;
+        let height = scaled_font.height() as u32 + 2 * INPUT_PADDING;
+        let (label, label_width) = self
+            .input_label(&label, baseline, font, face)
+            .map_or((None, 0), |(label, width)| (Some(label), width));
+
+        InputItem {
+            // Filled in once it's placed on a line
+            x: 0,
+            y: 0,
+            width: if submit {
+                label_width + 2 * INPUT_PADDING
+            } else {
+                INPUT_WIDTH
+            },
+            height,
+            baseline,
+            index,
+            form: self.form,
+            submit,
+            password: false,
+            name,
+            value,
+            label,
+        }
+    }
+
+    /// Shapes the text shown in an input's box, returning it along with its
+    /// width, or None if there's nothing to show
+    pub(crate) fn input_label(
+        &mut self,
+        label: &str,
+        baseline: u32,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) -> Option<(TextItem, u32)> {
+        if label.is_empty() {
+            return None;
+        }
+        let (glyph_buffer, width) = self.shape(label, font, face);
+        let mut item = self.text_item(glyph_buffer, font, face, label.into(), 0..label.len());
+        item.x = INPUT_PADDING;
+        item.y = baseline;
+        Some((item, width))
+    }
+
+    /// Places an input on the current line, with its text on the baseline
+    pub(crate) fn push_input(&mut self, mut input: InputItem, font: &'static FontRef<'static>) {
+        if !self.nobr && self.cursor_x != self.left() && self.cursor_x + input.width > self.right()
+        {
+            self.flush();
+        }
+
+        // Font size should be set in pt, not px
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+        let scaled_font = font.as_scaled(scale);
+        input.x = self.cursor_x;
+        self.cursor_x += input.width + scaled_font.h_advance(scaled_font.glyph_id(' ')) as u32;
+        self.line.push(LineItem {
+            ascent: input.baseline as f32,
+            descent: (input.height - input.baseline) as f32,
+            item: DisplayItem::Input(input),
+            shift: 0,
+            hidden: self.style.hidden,
+        });
+    }
+}
+
+impl Browser {
+    /// Handles a click at a point in window coordinates on a form control,
+    /// focusing a text input or submitting a button's form, or on a frame's
+    /// box, opening the page it would show. Returns whether the click hit
+    /// one.
+    pub fn click(&mut self, x: f32, y: f32) -> Result<bool, BrowserError> {
+        let frame = self.frame_at(x, y).map(str::to_owned);
+        let x = x + self.scroll_x as f32;
+        let y = y + self.scroll as f32;
+        let hit = self.display_list.iter().find_map(|item| match item {
+            DisplayItem::Input(input)
+                if (input.x as f32..(input.x + input.width) as f32).contains(&x)
+                    && (input.y as f32..(input.y + input.height) as f32).contains(&y) =>
+            {
+                Some((input.index, input.submit, input.form))
+            }
+            _ => None,
+        });
+
+        let focus = hit
+            .filter(|(_, submit, _)| !submit)
+            .map(|(index, ..)| index);
+        if focus != self.focus {
+            self.focus = focus;
+            self.dirty = true;
+        }
+        match hit {
+            Some((index, true, Some(form))) => {
+                self.selection = None;
+                self.submit(form, Some(index))?;
+                Ok(true)
+            }
+            Some(_) => {
+                self.selection = None;
+                Ok(true)
+            }
+            None => match frame {
+                Some(frame) => {
+                    self.selection = None;
+                    let url = URL::new(&frame)?;
+                    // Like a refresh, a frame on a web page can't open a
+                    // local file
+                    if self.current_url.as_ref().is_some_and(URL::is_web) && !url.is_web() {
+                        return Ok(true);
+                    }
+                    self.start_load(url)?;
+                    self.scroll = 0;
+                    self.scroll_x = 0;
+                    self.reset_scroll();
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+        }
+    }
+
+    /// Whether a text input has focus, so typing goes into it
+    pub fn is_editing(&self) -> bool {
+        self.focus.is_some()
+    }
+
+    pub fn stop_editing(&mut self) {
+        self.focus = None;
+        self.dirty = true;
+    }
+
+    pub fn input_push(&mut self, text: &str) {
+        self.edit_input(|value| value.push_str(text));
+    }
+
+    pub fn input_pop(&mut self) {
+        self.edit_input(|value| {
+            value.pop();
+        });
+    }
+
+    /// Submits the form of the focused input, as pressing Enter does
+    pub fn submit_input(&mut self) -> Result<(), BrowserError> {
+        let Some(form) = self.focused_input().and_then(|input| input.form) else {
+            return Ok(());
+        };
+        self.submit(form, None)
+    }
+
+    pub(crate) fn focused_input(&self) -> Option<&InputItem> {
+        let focus = self.focus?;
+        self.display_list.iter().find_map(|item| match item {
+            DisplayItem::Input(input) if input.index == focus => Some(input),
+            _ => None,
+        })
+    }
+
+    /// Changes the value of the focused input, then lays the page out again
+    /// to show it
+    pub(crate) fn edit_input(&mut self, edit: impl FnOnce(&mut String)) {
+        let Some(input) = self.focused_input() else {
+            return;
+        };
+        let (index, mut value) = (input.index, input.value.clone());
+        edit(&mut value);
+        self.input_values.insert(index, value);
+        self.layout();
+        self.selection = None;
+        self.update_find();
+        self.dirty = true;
+    }
+
+    /// Navigates to a form's action with its named inputs in the query
+    /// string, or in the request body for a POST form. Of the submit
+    /// buttons, only the one used to submit counts.
+    pub(crate) fn submit(
+        &mut self,
+        form: usize,
+        submitter: Option<usize>,
+    ) -> Result<(), BrowserError> {
+        let (Some(current_url), Some(base)) = (&self.current_url, &self.base) else {
+            return Ok(());
+        };
+        let fields: Vec<(String, String)> = self
+            .display_list
+            .iter()
+            .filter_map(|item| match item {
+                DisplayItem::Input(input)
+                    if input.form == Some(form)
+                        && (!input.submit || submitter == Some(input.index)) =>
+                {
+                    Some((input.name.clone()?, input.value.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        // An empty action submits back to the page itself
+        let action = &self.forms[form].action;
+        let mut url = if action.trim().is_empty() {
+            current_url.clone()
+        } else {
+            base.resolve(action)
+                .ok_or_else(|| BrowserError::Url(format!("Unsupported form action {}", action)))?
+        };
+        let encoded = form_urlencode(&fields);
+        if self.forms[form].method == "post" {
+            self.navigate(url, Some(&encoded))?;
+        } else {
+            let path = url.path.split('?').next().unwrap_or_default();
+            url.path = format!("{}?{}", path, encoded);
+            self.load(url)?;
+        }
+        self.scroll = 0;
+        self.scroll_x = 0;
+        self.reset_scroll();
+        Ok(())
+    }
+}
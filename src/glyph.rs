@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ab_glyph::{Font, FontRef, GlyphId, Point, PxScale, point};
+
+// How many glyphs are kept rasterized before the least recently used ones
+// are dropped
+pub(crate) const GLYPH_CACHE_SIZE: usize = 4096;
+// Glyphs are rasterized at this many offsets within a pixel in each
+// direction, so text positioned between pixels keeps its spacing
+pub(crate) const SUBPIXEL_STEPS: f32 = 4.0;
+
+/// A rasterized glyph: its coverage of each pixel, row by row, and where its
+/// top left corner is relative to the whole pixel it was drawn at
+pub(crate) struct GlyphBitmap {
+    pub(crate) left: i32,
+    pub(crate) top: i32,
+    pub(crate) width: u32,
+    pub(crate) coverage: Vec<f32>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct GlyphKey {
+    // Fonts are loaded once and never freed, so their address identifies them
+    font: usize,
+    glyph: u16,
+    scale: (u32, u32),
+    subpixel: (u8, u8),
+}
+
+/// Glyphs rasterized so far, so redrawing the page doesn't outline every
+/// glyph on it again. Bold and italic text have fonts of their own, so the
+/// font in the key covers the style too.
+#[derive(Default)]
+pub(crate) struct GlyphCache {
+    // Glyphs without an outline, like spaces, are kept as None, along with
+    // when each glyph was last drawn
+    glyphs: HashMap<GlyphKey, (Option<Rc<GlyphBitmap>>, u64)>,
+    clock: u64,
+}
+
+impl GlyphCache {
+    /// The bitmap for a glyph drawn at `(x, y)`, along with the whole pixel
+    /// its position is relative to
+    pub(crate) fn get(
+        &mut self,
+        font: &'static FontRef<'static>,
+        glyph: GlyphId,
+        scale: PxScale,
+        (x, y): (f32, f32),
+    ) -> Option<(Rc<GlyphBitmap>, (i32, i32))> {
+        let (origin_x, step_x) = snap(x);
+        let (origin_y, step_y) = snap(y);
+        let key = GlyphKey {
+            font: font as *const FontRef as usize,
+            glyph: glyph.0,
+            scale: (scale.x.to_bits(), scale.y.to_bits()),
+            subpixel: (step_x, step_y),
+        };
+
+        if !self.glyphs.contains_key(&key) && self.glyphs.len() >= GLYPH_CACHE_SIZE {
+            self.evict();
+        }
+        self.clock += 1;
+        let (bitmap, last_used) = self.glyphs.entry(key).or_insert_with(|| {
+            let position = point(
+                step_x as f32 / SUBPIXEL_STEPS,
+                step_y as f32 / SUBPIXEL_STEPS,
+            );
+            (rasterize(font, glyph, scale, position).map(Rc::new), 0)
+        });
+        *last_used = self.clock;
+        Some((bitmap.clone()?, (origin_x, origin_y)))
+    }
+
+    /// Drops the least recently drawn quarter of the glyphs, so a page using
+    /// more glyphs than fit doesn't evict on every draw
+    fn evict(&mut self) {
+        let mut ages: Vec<u64> = self.glyphs.values().map(|(_, used)| *used).collect();
+        if ages.is_empty() {
+            return;
+        }
+        let evicted = (ages.len() / 4).max(1);
+        let (_, &mut newest_evicted, _) = ages.select_nth_unstable(evicted - 1);
+        self.glyphs.retain(|_, (_, used)| *used > newest_evicted);
+    }
+}
+
+/// Draws a glyph's outline into a coverage bitmap, if it has one
+fn rasterize(
+    font: &FontRef<'static>,
+    glyph: GlyphId,
+    scale: PxScale,
+    position: Point,
+) -> Option<GlyphBitmap> {
+    let outlined = font.outline_glyph(glyph.with_scale_and_position(scale, position))?;
+    let bounds = outlined.px_bounds();
+    let width = bounds.width() as u32;
+    let mut coverage = vec![0.0; (width * bounds.height() as u32) as usize];
+    outlined.draw(|x, y, value| coverage[(y * width + x) as usize] = value);
+    Some(GlyphBitmap {
+        left: bounds.min.x as i32,
+        top: bounds.min.y as i32,
+        width,
+        coverage,
+    })
+}
+
+/// Splits a coordinate into a whole pixel and the nearest subpixel step past it
+fn snap(value: f32) -> (i32, u8) {
+    let steps = (value * SUBPIXEL_STEPS).round() as i32;
+    let per_pixel = SUBPIXEL_STEPS as i32;
+    (
+        steps.div_euclid(per_pixel),
+        steps.rem_euclid(per_pixel) as u8,
+    )
+}
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use image::RgbaImage;
+use socket2::Socket;
+
+use crate::cache::{DEFAULT_HTTP_CACHE_SIZE, HttpCache};
+use crate::charset::decode;
+use crate::error::BrowserError;
+use crate::intercept::RequestInterceptor;
+use crate::url::URL;
+
+// The largest response body read by default, in bytes
+pub(crate) const DEFAULT_MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+// Subresources like images are fetched this many at a time from each host,
+// so a page full of them doesn't flood its server with connections
+pub(crate) const MAX_CONNECTIONS_PER_HOST: usize = 6;
+// How many more times a connection that's refused or times out is tried by
+// default, and how long to wait before the first of them. Each wait after
+// that is twice as long as the last.
+pub(crate) const DEFAULT_CONNECT_RETRIES: u32 = 2;
+pub(crate) const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(200);
+// No retry is started that would take connecting past this long altogether
+pub(crate) const CONNECT_BUDGET: Duration = Duration::from_secs(10);
+// Headers the client sets itself, since how a request is framed and whether
+// its connection can be reused depend on them
+pub(crate) const CLIENT_HEADERS: &[&str] =
+    &["content-length", "transfer-encoding", "connection", "host"];
+
+/// What a TLS connection names as the server it wants when connecting,
+/// which servers hosting many sites use to pick the one to answer for
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Sni {
+    /// The URL's host, as usual
+    #[default]
+    Host,
+    /// Another hostname, e.g. to ask a CDN for one site at another's
+    /// address. The certificate has to be valid for this name instead.
+    Name(String),
+    /// No name at all. The certificate is still checked against the host.
+    Off,
+}
+
+#[derive(Clone)]
+pub struct Response {
+    pub status: u16,
+    // Header names are lowercased, and each has every value it was sent
+    // with, in order
+    pub headers: HashMap<String, Vec<String>>,
+    pub body: Vec<u8>,
+    // The status line and headers as they were received, for logging
+    pub(crate) head: String,
+}
+
+impl Response {
+    /// The value of the header `name`, given in lowercase. For a header sent
+    /// more than once, that's the last value.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        last_header(&self.headers, name)
+    }
+
+    /// Every value of the header `name`, given in lowercase, like each
+    /// cookie of several Set-Cookie headers, in the order they were sent
+    pub fn header_values(&self, name: &str) -> &[String] {
+        self.headers.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The body decoded into text, using the charset from the headers or
+    /// the page itself
+    pub(crate) fn text(&self) -> String {
+        decode(&self.body, self.header("content-type"))
+    }
+
+    /// How to show the body, going by its Content-Type. Text other than
+    /// HTML, like text/plain or text/css, is shown as it is, and a response
+    /// without a Content-Type is taken to be HTML.
+    pub(crate) fn body_kind(&self) -> BodyKind {
+        let Some(content_type) = self.header("content-type") else {
+            return BodyKind::Html;
+        };
+        let media_type = content_type.split(';').next().unwrap_or_default();
+        match media_type.trim().to_ascii_lowercase().as_str() {
+            "text/html" => BodyKind::Html,
+            "application/json" => BodyKind::Json,
+            media_type if media_type.starts_with("text/") => BodyKind::PlainText,
+            _ => BodyKind::Html,
+        }
+    }
+}
+
+/// What kind of document a page is, which decides how it's shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BodyKind {
+    Html,
+    PlainText,
+    // Pretty-printed, if it parses
+    Json,
+}
+
+/// A body sent along with a request, such as a submitted form
+pub(crate) struct RequestBody<'a> {
+    pub(crate) content_type: &'a str,
+    pub(crate) data: &'a [u8],
+}
+
+/// How far a page loading in the background has got, sent back as it goes
+pub(crate) enum Progress {
+    /// The headers of the response that will be the page, before its body.
+    /// A retried request starts over with another of these.
+    Head(HashMap<String, Vec<String>>),
+    /// The next piece of that response's body
+    Body(Vec<u8>),
+    /// The whole response, and the client that fetched it, so the page's
+    /// client can keep its connection
+    Done(Result<Response, BrowserError>, Box<HttpClient>),
+    /// The page's icon, if it could be loaded, and the client that fetched
+    /// it
+    Icon(Option<RgbaImage>, Box<HttpClient>),
+}
+
+/// The last value of the header `name` in `headers`
+pub(crate) fn last_header<'a>(
+    headers: &'a HashMap<String, Vec<String>>,
+    name: &str,
+) -> Option<&'a str> {
+    headers.get(name)?.last().map(String::as_str)
+}
+
+/// The error for a response body longer than the client accepts
+pub(crate) fn too_large() -> BrowserError {
+    BrowserError::Network("Response too large".to_owned())
+}
+
+/// Reads a chunked body, passing each chunk to `report` as it arrives
+pub(crate) fn read_chunked_body<R: BufRead>(
+    reader: &mut R,
+    max_size: usize,
+    report: &mut impl FnMut(&[u8]),
+) -> Result<Vec<u8>, BrowserError> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = line.trim().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| BrowserError::Network("Invalid chunk size".to_owned()))?;
+
+        if size == 0 {
+            // Skip any trailers up to the final blank line
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                    break;
+                }
+            }
+            return Ok(body);
+        }
+
+        let start = body.len();
+        if size > max_size - start {
+            return Err(too_large());
+        }
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        report(&body[start..]);
+
+        // Every chunk is followed by a CRLF
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    InvalidName(String),
+    Certificate(String, rustls::CertificateError),
+    Handshake(String, rustls::Error),
+}
+
+impl std::fmt::Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::InvalidName(host) => {
+                write!(f, "\"{}\" is not a valid server name", host)
+            }
+            TlsError::Certificate(host, err) => {
+                write!(
+                    f,
+                    "The certificate for {} could not be verified: {}",
+                    host, err
+                )
+            }
+            TlsError::Handshake(host, err) => {
+                write!(f, "The TLS handshake with {} failed: {}", host, err)
+            }
+        }
+    }
+}
+
+impl Error for TlsError {}
+
+pub(crate) enum Connection {
+    Plain(Socket),
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, Socket>>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(socket) => socket.read(buf),
+            Connection::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(socket) => socket.write(buf),
+            Connection::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Plain(socket) => socket.flush(),
+            Connection::Tls(tls) => tls.flush(),
+        }
+    }
+}
+
+/// Accepts any server certificate. Only installed when the user explicitly
+/// opts in with `--insecure`, e.g. for local servers with self-signed certs
+#[derive(Debug)]
+pub(crate) struct NoCertificateVerification(Arc<rustls::crypto::CryptoProvider>);
+
+impl rustls::client::danger::ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+pub(crate) fn tls_config(insecure: bool) -> rustls::ClientConfig {
+    if insecure {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoCertificateVerification(provider)))
+            .with_no_client_auth()
+    } else {
+        let root_store =
+            rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth()
+    }
+}
+
+/// Keeps idle keep-alive connections around so later requests to the same
+/// host can skip the TCP (and TLS) handshake
+pub struct HttpClient {
+    pub(crate) idle: HashMap<(String, String, String), Vec<BufReader<Connection>>>,
+    pub(crate) tls_config: Arc<rustls::ClientConfig>,
+    // How much to log to stderr: nothing at 0, requests and responses at 1,
+    // and how long they took from 2
+    pub verbosity: u8,
+    // Responses with a longer body fail rather than using up memory
+    pub max_body_size: usize,
+    // How many more times to try connecting when the server refuses or
+    // doesn't answer, and how long to wait before the first retry
+    pub connect_retries: u32,
+    pub retry_delay: Duration,
+    pub sni: Sni,
+    // Sent with every request, after Host, which a Host header here replaces
+    pub(crate) headers: Vec<(String, String)>,
+    // Responses to revalidate rather than fetch again, shared with the
+    // clients this one is forked into
+    pub(crate) cache: Arc<Mutex<HttpCache>>,
+    // Sees each request before it's sent, and may answer it itself
+    pub(crate) interceptor: Option<Arc<dyn RequestInterceptor>>,
+    // Where to send the body of the page being loaded as it arrives, for a
+    // client loading one in the background
+    pub(crate) progress: Option<Sender<Progress>>,
+}
+
+impl HttpClient {
+    pub fn new(insecure: bool) -> Self {
+        Self {
+            idle: HashMap::new(),
+            tls_config: Arc::new(tls_config(insecure)),
+            verbosity: 0,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            connect_retries: DEFAULT_CONNECT_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            sni: Sni::Host,
+            headers: Vec::new(),
+            cache: Arc::new(Mutex::new(HttpCache::new(DEFAULT_HTTP_CACHE_SIZE))),
+            interceptor: None,
+            progress: None,
+        }
+    }
+
+    /// Keeps the idle connections of `fork`, a fork of this client that's
+    /// done with them
+    pub(crate) fn join(&mut self, fork: HttpClient) {
+        for (key, connections) in fork.idle {
+            self.idle.entry(key).or_default().extend(connections);
+        }
+    }
+
+    /// A client with the same settings, but none of the connections
+    pub(crate) fn fork(&self) -> Self {
+        Self {
+            idle: HashMap::new(),
+            tls_config: self.tls_config.clone(),
+            verbosity: self.verbosity,
+            max_body_size: self.max_body_size,
+            connect_retries: self.connect_retries,
+            retry_delay: self.retry_delay,
+            sni: self.sni.clone(),
+            headers: self.headers.clone(),
+            cache: self.cache.clone(),
+            interceptor: self.interceptor.clone(),
+            progress: None,
+        }
+    }
+
+    /// Sends a header with every request from now on, given as a
+    /// "Name: Value" line. The headers in CLIENT_HEADERS can't be added, and
+    /// a Content-Type is left out of requests with a body of their own.
+    pub fn add_header(&mut self, line: &str) -> Result<(), BrowserError> {
+        let invalid = || BrowserError::Request(format!("Invalid header {:?}", line));
+        let (name, value) = line.split_once(':').ok_or_else(invalid)?;
+        let value = value.trim();
+        let token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if name.is_empty()
+            || !name.chars().all(token)
+            || value.chars().any(|c| c.is_control() && c != '\t')
+        {
+            return Err(invalid());
+        }
+        if CLIENT_HEADERS
+            .iter()
+            .any(|header| name.eq_ignore_ascii_case(header))
+        {
+            return Err(BrowserError::Request(format!(
+                "The {} header is set by the browser and can't be added",
+                name
+            )));
+        }
+        self.headers.push((name.to_owned(), value.to_owned()));
+        Ok(())
+    }
+
+    /// Fetches each of `urls`, with up to MAX_CONNECTIONS_PER_HOST requests
+    /// to each host in flight at once. The results are in the same order as
+    /// `urls`.
+    pub(crate) fn fetch_all(&mut self, urls: &[URL]) -> Vec<Result<Response, BrowserError>> {
+        let mut hosts: HashMap<_, Vec<usize>> = HashMap::new();
+        for (index, url) in urls.iter().enumerate() {
+            hosts.entry(Self::key(url)).or_default().push(index);
+        }
+
+        // Each worker takes the next URL for its host until there are none
+        // left, starting with one of the idle connections there if any
+        let queues: Vec<_> = hosts
+            .values()
+            .map(|indices| (&urls[indices[0]], Mutex::new(indices.iter()), indices.len()))
+            .collect();
+        let mut workers = Vec::new();
+        for (first, queue, len) in &queues {
+            for _ in 0..std::cmp::min(*len, MAX_CONNECTIONS_PER_HOST) {
+                let mut client = self.fork();
+                if let Some(connection) = self.take_idle(first) {
+                    client.release(first, connection);
+                }
+                workers.push((client, queue));
+            }
+        }
+
+        let mut results: Vec<_> = urls.iter().map(|_| None).collect();
+        let finished = thread::scope(|scope| {
+            let handles: Vec<_> = workers
+                .into_iter()
+                .map(|(mut client, queue)| {
+                    scope.spawn(move || {
+                        let mut fetched = Vec::new();
+                        loop {
+                            let Some(&index) = queue.lock().unwrap().next() else {
+                                break;
+                            };
+                            fetched.push((index, urls[index].fetch(&mut client)));
+                        }
+                        (client, fetched)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        // Keep the connections the workers opened for whatever comes next
+        for (client, fetched) in finished {
+            self.join(client);
+            for (index, result) in fetched {
+                results[index] = Some(result);
+            }
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// Writes each line of `text` to stderr after `prefix`, when logging at
+    /// `level` is turned on
+    pub(crate) fn log(&self, level: u8, prefix: &str, text: &str) {
+        if self.verbosity < level {
+            return;
+        }
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            eprintln!("{}{}", prefix, line);
+        }
+    }
+
+    pub(crate) fn key(url: &URL) -> (String, String, String) {
+        (url.host.clone(), url.port.clone(), url.scheme.clone())
+    }
+
+    pub(crate) fn take_idle(&mut self, url: &URL) -> Option<BufReader<Connection>> {
+        self.idle.get_mut(&Self::key(url))?.pop()
+    }
+
+    pub(crate) fn release(&mut self, url: &URL, connection: BufReader<Connection>) {
+        self.idle
+            .entry(Self::key(url))
+            .or_default()
+            .push(connection);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use image::RgbaImage;
+
+// How many bytes of decoded images are kept by default
+pub(crate) const DEFAULT_IMAGE_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Decoded images by the URL they were loaded from, so pages using the same
+/// images, or the same page loaded again, don't fetch and decode them again.
+/// Once they'd take up more than the budget, the least recently drawn are
+/// dropped, to be loaded again if they're needed after all.
+pub struct ImageCache {
+    // Each image along with when it was last drawn
+    images: HashMap<String, (Rc<RgbaImage>, u64)>,
+    budget: usize,
+    size: usize,
+    clock: u64,
+}
+
+/// Roughly how much memory a decoded image takes up, in bytes
+fn image_size(image: &RgbaImage) -> usize {
+    image.as_raw().len()
+}
+
+impl ImageCache {
+    /// An empty cache keeping at most `budget` bytes of images
+    pub fn new(budget: usize) -> Self {
+        Self {
+            images: HashMap::new(),
+            budget,
+            size: 0,
+            clock: 0,
+        }
+    }
+
+    /// The image loaded from `url`, if it's still kept, counting as a use
+    pub fn get(&mut self, url: &str) -> Option<Rc<RgbaImage>> {
+        self.clock += 1;
+        let (image, last_used) = self.images.get_mut(url)?;
+        *last_used = self.clock;
+        Some(image.clone())
+    }
+
+    /// Marks the image loaded from `url` as just drawn
+    pub fn touch(&mut self, url: &str) {
+        self.get(url);
+    }
+
+    /// Keeps `image` as the one loaded from `url`, dropping the least
+    /// recently drawn images to make room. An image bigger than the whole
+    /// budget isn't kept at all.
+    pub fn insert(&mut self, url: String, image: Rc<RgbaImage>) {
+        let size = image_size(&image);
+        if size > self.budget {
+            return;
+        }
+        self.clock += 1;
+        if let Some((old, _)) = self.images.insert(url, (image, self.clock)) {
+            self.size -= image_size(&old);
+        }
+        self.size += size;
+        while self.size > self.budget {
+            self.evict();
+        }
+    }
+
+    /// Drops the image loaded from `url`, so it's fetched again next time
+    pub fn remove(&mut self, url: &str) {
+        if let Some((image, _)) = self.images.remove(url) {
+            self.size -= image_size(&image);
+        }
+    }
+
+    /// Drops the least recently drawn image
+    fn evict(&mut self) {
+        let Some(oldest) = self
+            .images
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(url, _)| url.clone())
+        else {
+            return;
+        };
+        let (image, _) = self.images.remove(&oldest).unwrap();
+        self.size -= image_size(&image);
+    }
+
+    /// Whether the image loaded from `url` is still kept, without counting
+    /// as a use
+    pub fn contains(&self, url: &str) -> bool {
+        self.images.contains_key(url)
+    }
+
+    /// How many bytes of images are kept at most
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// How many bytes the images kept take up
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Changes how many bytes of images are kept, dropping the least
+    /// recently drawn ones if they no longer fit
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        while self.size > self.budget {
+            self.evict();
+        }
+    }
+}
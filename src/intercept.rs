@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::browser::Browser;
+use crate::http::Response;
+use crate::url::URL;
+
+/// Sees every HTTP request before it's sent, for pages, images, stylesheets
+/// and forms alike. It can change where the request goes and what headers
+/// it has, or answer it itself so nothing is sent at all, e.g. to test
+/// offline or block content. Requests are made on other threads too, so an
+/// interceptor has to be shareable between them.
+pub trait RequestInterceptor: Send + Sync {
+    /// Called with the request's method, URL and headers, which can be
+    /// changed in place. The headers start out as the ones the client sends
+    /// with every request. Host, unless one of them is, and the headers
+    /// describing a body are added afterwards, from the URL and body, and
+    /// take the place of any Content-Type or Content-Length given here.
+    /// Returning a response answers the request with it instead of sending
+    /// it; redirects in it are still followed.
+    fn intercept(
+        &self,
+        method: &str,
+        url: &mut URL,
+        headers: &mut Vec<(String, String)>,
+    ) -> Option<Response>;
+}
+
+impl Response {
+    /// A response made up rather than received, like an interceptor's
+    pub fn new(status: u16, headers: HashMap<String, Vec<String>>, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers,
+            body,
+            head: format!("HTTP/1.1 {} (intercepted)", status),
+        }
+    }
+}
+
+impl Browser {
+    /// Has `interceptor` see every request made from now on, in place of
+    /// any set before
+    pub fn set_interceptor(&mut self, interceptor: impl RequestInterceptor + 'static) {
+        self.http_client.interceptor = Some(Arc::new(interceptor));
+    }
+}
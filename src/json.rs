@@ -0,0 +1,6 @@
+/// Re-indents a JSON document for reading, or None if it isn't valid JSON.
+/// Object keys keep the order they were written in.
+pub(crate) fn pretty_print(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    serde_json::to_string_pretty(&value).ok()
+}
@@ -0,0 +1,1075 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use rustybuzz::{Face, GlyphBuffer, UnicodeBuffer, shape};
+
+use image::RgbaImage;
+use image::imageops::FilterType;
+
+use crate::css::Style;
+use crate::dom::{Element, HEAD_ELEMENTS, Node, VOID_ELEMENTS};
+use crate::font::{FontManager, FontProperties, FontSize, FontStyle, FontWeight};
+use crate::form::{Form, InputItem};
+use crate::lexer::{SOFT_HYPHEN, TAB_SIZE, Tag, is_word_break, words};
+use crate::url::URL;
+use crate::vertical::Vertical;
+use crate::{HSTEP, VSTEP};
+
+// Used for an <img> dimension that isn't given by its attributes
+pub(crate) const DEFAULT_IMAGE_SIZE: u32 = 100;
+pub(crate) const IMAGE_PADDING: u32 = 4;
+// The size of an <iframe> without width and height attributes, as in HTML
+pub(crate) const DEFAULT_FRAME_WIDTH: u32 = 300;
+pub(crate) const DEFAULT_FRAME_HEIGHT: u32 = 150;
+// How far <sub> and <sup> move text, as a fraction of the surrounding font size
+pub(crate) const SUBSCRIPT_SHIFT: f32 = 0.2;
+pub(crate) const SUPERSCRIPT_SHIFT: f32 = 0.35;
+// The font size of <h1> through <h6>, in percent of the text around them
+pub(crate) const HEADING_SIZES: [u32; 6] = [200, 150, 117, 100, 83, 67];
+// How much <small> shrinks text, and <big> grows it, in percent
+pub(crate) const SMALL_SIZE: u32 = 83;
+pub(crate) const BIG_SIZE: u32 = 120;
+// How far each <blockquote> indents from both sides of the column
+pub(crate) const BLOCKQUOTE_INDENT: u32 = 40;
+pub(crate) const TEXT_COLOR: [u8; 3] = [0, 0, 0];
+pub(crate) const LINK_COLOR: [u8; 3] = [0, 0, 238];
+pub(crate) const VISITED_LINK_COLOR: [u8; 3] = [85, 26, 139];
+// The gap left above and below a block like <blockquote>
+pub(crate) const BLOCK_SPACING: u32 = 16;
+// How tall lines are by default, as a multiple of the font's height
+pub(crate) const LINE_HEIGHT: f32 = 1.2;
+// The family asked for in <pre>, which picks the system's monospace font
+pub(crate) const PRE_FONT_FAMILY: &str = "monospace";
+
+pub(crate) enum DisplayItem {
+    Text(TextItem),
+    Image(ImageItem),
+    Input(InputItem),
+}
+
+impl DisplayItem {
+    pub(crate) fn as_text(&self) -> Option<&TextItem> {
+        match self {
+            DisplayItem::Text(item) => Some(item),
+            DisplayItem::Image(_) | DisplayItem::Input(_) => None,
+        }
+    }
+
+    pub(crate) fn top(&self) -> u32 {
+        match self {
+            DisplayItem::Text(item) => item.vertical_extent().0.max(0.0) as u32,
+            DisplayItem::Image(image) => image.y,
+            DisplayItem::Input(input) => input.y,
+        }
+    }
+
+    pub(crate) fn bottom(&self) -> u32 {
+        match self {
+            DisplayItem::Text(item) => item.vertical_extent().1.ceil() as u32,
+            DisplayItem::Image(image) => image.y + image.height,
+            DisplayItem::Input(input) => input.y + input.height,
+        }
+    }
+
+    /// How far right the item reaches
+    pub(crate) fn right(&self) -> u32 {
+        match self {
+            DisplayItem::Text(text) => text.x_extent(&text.range).1.ceil() as u32,
+            DisplayItem::Image(image) => image.x + image.width,
+            DisplayItem::Input(input) => input.x + input.width,
+        }
+    }
+}
+
+/// The box reserved for an <img>. Its alt text is positioned relative to the
+/// box's top left corner, and is only drawn if the image couldn't be loaded.
+pub(crate) struct ImageItem {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) alt: Option<TextItem>,
+    // The decoded image, scaled to the box
+    pub(crate) pixels: Option<Rc<RgbaImage>>,
+    // Where the image was loaded from, which it's kept in the image cache by
+    pub(crate) url: Option<Rc<str>>,
+    // For an <iframe>'s box, the resolved URL of the page it would show
+    pub(crate) frame: Option<Rc<str>>,
+}
+
+/// A shaped word positioned on the page, along with the text it came from
+pub(crate) struct TextItem {
+    pub(crate) glyphs: GlyphBuffer,
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) font: &'static FontRef<'static>,
+    pub(crate) font_properties: FontProperties,
+    pub(crate) font_size: FontSize,
+    pub(crate) underline: bool,
+    pub(crate) strikethrough: bool,
+    // The word is a range into its text token rather than its own copy
+    pub(crate) source: Rc<str>,
+    pub(crate) range: Range<usize>,
+    // The title of the innermost element around this word, shown on hover
+    pub(crate) title: Option<Rc<str>>,
+    // Where the link around this word goes, if it's in one
+    pub(crate) href: Option<Rc<str>>,
+    pub(crate) color: [u8; 3],
+    // Which block, like a paragraph or a table cell, the word is part of
+    pub(crate) block: usize,
+    // Italic was asked for but the font has no italic face, so the glyphs
+    // are sheared when drawn instead
+    pub(crate) synthetic_oblique: bool,
+    // The same for bold, where the glyphs are drawn thicker
+    pub(crate) synthetic_bold: bool,
+    // Shaped top to bottom in vertical text, with x the middle of its column
+    // and y the top of the first glyph rather than the baseline
+    pub(crate) vertical: bool,
+}
+
+impl TextItem {
+    pub(crate) fn text(&self) -> &str {
+        &self.source[self.range.clone()]
+    }
+
+    pub(crate) fn scale(&self) -> PxScale {
+        // Font size should be set in pt, not px
+        self.font.pt_to_px_scale(self.font_size.0 as f32).unwrap()
+    }
+
+    /// The horizontal span covered by the glyphs shaped from `range` (a byte
+    /// range within this item's text)
+    pub(crate) fn x_extent(&self, range: &Range<usize>) -> (f32, f32) {
+        if self.vertical {
+            // Vertical items are a single character, so take up the middle
+            // of their column whatever the range
+            let half = self.font.as_scaled(self.scale()).height() / 2.0;
+            return (self.x as f32 - half, self.x as f32 + half);
+        }
+        let scale_factor = self.scale().x / self.font.height_unscaled();
+        let mut cursor_x = self.x as f32;
+        let mut start = None;
+        let mut end = cursor_x;
+        for (info, pos) in self
+            .glyphs
+            .glyph_infos()
+            .iter()
+            .zip(self.glyphs.glyph_positions())
+        {
+            let advance = pos.x_advance as f32 * scale_factor;
+            if range.contains(&(self.range.start + info.cluster as usize)) {
+                start.get_or_insert(cursor_x);
+                end = cursor_x + advance;
+            }
+            cursor_x += advance;
+        }
+
+        (start.unwrap_or(end), end)
+    }
+
+    /// The top and bottom of the line box around this item's baseline, or in
+    /// vertical text, how far down its column the glyphs reach
+    pub(crate) fn vertical_extent(&self) -> (f32, f32) {
+        if self.vertical {
+            let scale_factor = self.scale().y / self.font.height_unscaled();
+            let advance: i32 = self
+                .glyphs
+                .glyph_positions()
+                .iter()
+                .map(|pos| -pos.y_advance)
+                .sum();
+            return (self.y as f32, self.y as f32 + advance as f32 * scale_factor);
+        }
+        let scaled_font = self.font.as_scaled(self.scale());
+        (
+            self.y as f32 - scaled_font.ascent(),
+            self.y as f32 - scaled_font.descent(),
+        )
+    }
+
+    /// Whether this item carries straight on from `last` with nothing in
+    /// between, like the pieces of a word hyphenated across lines or the
+    /// characters of vertical text
+    pub(crate) fn continues(&self, last: &TextItem) -> bool {
+        Rc::ptr_eq(&last.source, &self.source)
+            && self
+                .source
+                .get(last.range.end..self.range.start)
+                .is_some_and(|gap| gap.chars().all(is_word_break))
+    }
+}
+
+/// An item on a line that hasn't been placed yet, with how far it extends
+/// above and below the baseline
+pub(crate) struct LineItem {
+    pub(crate) item: DisplayItem,
+    pub(crate) ascent: f32,
+    pub(crate) descent: f32,
+    // Moves text down from the baseline, or up when negative
+    pub(crate) shift: i32,
+    // Takes up its space on the line without being shown
+    pub(crate) hidden: bool,
+}
+
+pub(crate) struct Layout {
+    pub(crate) cursor_x: u32,
+    pub(crate) cursor_y: u32,
+    pub(crate) window_width: u32,
+    pub(crate) font_properties: FontProperties,
+    pub(crate) font_size: FontSize,
+    // Text is never set smaller than this, however small the page asks for
+    pub(crate) min_font_size: Option<FontSize>,
+    // The font size to go back to for each open <small>, <big> or heading
+    pub(crate) sizes: Vec<FontSize>,
+    // Inside <nobr>, where words never wrap onto a new line
+    pub(crate) nobr: bool,
+    // The computed style of the element being laid out
+    pub(crate) style: Style,
+    pub(crate) max_content_width: Option<u32>,
+    // How tall a line of text is, as a multiple of the font's height
+    pub(crate) line_height: f32,
+    // How far text is indented from both sides of the column, e.g. by
+    // nested <blockquote>s
+    pub(crate) indent: u32,
+    // The x and width of the table cell being laid out, which takes the place
+    // of the text column
+    pub(crate) cell: Option<(u32, u32)>,
+    // The open elements that have a title attribute, innermost last
+    pub(crate) titles: Vec<(String, Rc<str>)>,
+    // Items on the current line, waiting for the line to be finished so they
+    // can share a baseline
+    pub(crate) line: Vec<LineItem>,
+    // How far below the baseline text currently sits, negative for above,
+    // along with the font size to go back to, for each open <sub> or <sup>
+    pub(crate) baseline_shift: i32,
+    pub(crate) scripts: Vec<(FontSize, i32)>,
+    pub(crate) display_list: Vec<DisplayItem>,
+    pub(crate) images: HashMap<String, Rc<RgbaImage>>,
+    // The forms seen so far, and the one currently open
+    pub(crate) forms: Vec<Form>,
+    pub(crate) form: Option<usize>,
+    // How many inputs have been laid out, and the values typed into them
+    // by their index
+    pub(crate) inputs: usize,
+    pub(crate) input_values: HashMap<usize, String>,
+    // The open <a> elements, innermost last, with where they link to
+    // resolved against `base`
+    pub(crate) links: Vec<Option<Rc<str>>>,
+    pub(crate) base: Option<URL>,
+    // The top of each heading's first line
+    pub(crate) headings: Vec<u32>,
+    // Counts the blocks started so far, so that words can be told apart by
+    // the paragraph or cell they're in
+    pub(crate) block: usize,
+    // Set to lay text out in vertical columns instead of lines
+    pub(crate) vertical: Option<Vertical>,
+    // The font family to go back to for each open <pre>, innermost last
+    pub(crate) pre: Vec<String>,
+}
+
+impl Layout {
+    pub(crate) fn new(window_width: u32) -> Self {
+        Self {
+            cursor_x: 0,
+            cursor_y: VSTEP,
+            window_width,
+            font_properties: FontProperties::default(),
+            font_size: FontSize(16),
+            min_font_size: None,
+            sizes: Vec::new(),
+            nobr: false,
+            style: Style::default(),
+            max_content_width: None,
+            line_height: LINE_HEIGHT,
+            indent: 0,
+            cell: None,
+            titles: Vec::new(),
+            line: Vec::new(),
+            baseline_shift: 0,
+            scripts: Vec::new(),
+            display_list: Vec::new(),
+            images: HashMap::new(),
+            forms: Vec::new(),
+            form: None,
+            inputs: 0,
+            input_values: HashMap::new(),
+            links: Vec::new(),
+            base: None,
+            headings: Vec::new(),
+            block: 0,
+            vertical: None,
+            pre: Vec::new(),
+        }
+    }
+
+    /// The width of the text column, before any indent
+    pub(crate) fn column_width(&self) -> u32 {
+        if let Some((_, width)) = self.cell {
+            return width;
+        }
+        let available = self.window_width.saturating_sub(2 * HSTEP);
+        self.max_content_width
+            .map_or(available, |max| std::cmp::min(max, available))
+    }
+
+    pub(crate) fn content_width(&self) -> u32 {
+        self.column_width().saturating_sub(2 * self.indent)
+    }
+
+    /// Where lines start, which centers the text column when its width is
+    /// capped
+    pub(crate) fn left(&self) -> u32 {
+        match self.cell {
+            Some((x, _)) => x + self.indent,
+            None => (self.window_width - self.column_width()) / 2 + self.indent,
+        }
+    }
+
+    /// Where lines wrap
+    pub(crate) fn right(&self) -> u32 {
+        self.left() + self.content_width()
+    }
+
+    /// Lays out a page's nodes from the top of the text column, returning
+    /// what to draw
+    pub(crate) fn document(
+        &mut self,
+        nodes: &[Node],
+        font_manager: &mut FontManager,
+    ) -> Vec<DisplayItem> {
+        self.cursor_x = match &self.vertical {
+            Some(vertical) => {
+                self.cursor_y = vertical.top;
+                self.right()
+            }
+            None => self.left(),
+        };
+        self.nodes(nodes, font_manager);
+        self.flush();
+        std::mem::take(&mut self.display_list)
+    }
+
+    pub(crate) fn nodes(&mut self, nodes: &[Node], font_manager: &mut FontManager) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.text(text, font_manager),
+                Node::Element(element) => self.element(element, font_manager),
+            }
+        }
+    }
+
+    pub(crate) fn text(&mut self, text: &Rc<str>, font_manager: &mut FontManager) {
+        let (font, face) = font_manager.get_fonts(&self.font_properties);
+        if !self.pre.is_empty() && self.vertical.is_none() {
+            self.preformatted(text, font, face);
+            return;
+        }
+        let (space_font, _) = font_manager.get_fonts(&self.font_properties.regular());
+        for (tabs, word) in words(text) {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            let source = (text.clone(), start..start + word.len());
+            if self.vertical.is_some() {
+                self.vertical_word(source, font, face, space_font);
+                continue;
+            }
+            self.tab(tabs, space_font);
+            self.word(source, font, face, space_font);
+        }
+    }
+
+    /// Lays out an element: its opening tag, what's inside it, then its
+    /// closing tag
+    pub(crate) fn element(&mut self, element: &Element, font_manager: &mut FontManager) {
+        let name = element.name();
+        if element.style.display_none || HEAD_ELEMENTS.contains(&name) {
+            return;
+        }
+        // Vertical text is experimental and only covers text so far: tables
+        // and buttons are laid out as the text inside them, while images,
+        // frames and inputs are left out
+        if self.vertical.is_some() && ["table", "button", "img", "iframe", "input"].contains(&name)
+        {
+            self.nodes(&element.children, font_manager);
+            return;
+        }
+        let outer = self.style.clone();
+        self.set_style(&element.style);
+        let (font, face) = font_manager.get_fonts(&self.font_properties);
+        match name {
+            "table" => self.table(element, font_manager),
+            // Buttons take the text inside them as their label
+            "button" => self.button(element, font, face),
+            // What's inside a frame is only for browsers that can't show
+            // frames at all
+            "iframe" => self.frame(&element.tag, font, face),
+            _ => {
+                // Track elements carrying a title so the words inside can
+                // show it as a tooltip
+                let title = element.tag.attributes.get("title");
+                if let Some(title) = title {
+                    self.titles.push((name.to_owned(), title.as_str().into()));
+                }
+                self.tag(&element.tag, font, face);
+                self.nodes(&element.children, font_manager);
+                if !VOID_ELEMENTS.contains(&name) {
+                    let (font, face) = font_manager.get_fonts(&self.font_properties);
+                    self.tag(&Tag::parse(&format!("/{name}")), font, face);
+                }
+                if title.is_some() {
+                    self.titles.pop();
+                }
+            }
+        }
+        self.set_style(&outer);
+    }
+
+    pub(crate) fn tag(
+        &mut self,
+        tag: &Tag,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        match (tag.closing, tag.name.as_str()) {
+            (false, "blockquote") => {
+                self.block_break();
+                self.indent += BLOCKQUOTE_INDENT;
+                if self.vertical.is_none() {
+                    self.cursor_x = self.left();
+                }
+            }
+            (true, "blockquote") => {
+                self.block_break();
+                self.indent = self.indent.saturating_sub(BLOCKQUOTE_INDENT);
+                if self.vertical.is_none() {
+                    self.cursor_x = self.left();
+                }
+            }
+            // Divs only start and end lines, so empty lines between nested
+            // ones collapse
+            (_, "div") => self.start_block(),
+            (false, "p") => self.start_block(),
+            (true, "p") => self.block_break(),
+            // Headings get lines of their own too, and where they start is
+            // kept for jumping between them
+            (closing, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                self.start_block();
+                if closing {
+                    self.pop_font_size();
+                } else {
+                    self.headings.push(self.cursor_y);
+                    let level = tag.name[1..].parse::<usize>().unwrap();
+                    self.push_font_size(HEADING_SIZES[level - 1]);
+                }
+            }
+            (false, "br") => self.line_break(font),
+            // Preformatted text is in a monospace font, and set apart like a
+            // paragraph
+            (false, "pre") => {
+                self.start_block();
+                let family = std::mem::replace(
+                    &mut self.font_properties.font_family,
+                    PRE_FONT_FAMILY.to_owned(),
+                );
+                self.pre.push(family);
+            }
+            (true, "pre") => {
+                if let Some(family) = self.pre.pop() {
+                    self.block_break();
+                    self.font_properties.font_family = family;
+                }
+            }
+            (false, "nobr") => self.nobr = true,
+            (true, "nobr") => self.nobr = false,
+            (false, "img") => self.image(tag, font, face),
+            (false, "input") => self.input(tag, font, face),
+            (false, "form") => {
+                // Anything but a POST form is submitted with GET
+                let method = match tag.attributes.get("method") {
+                    Some(method) if method.trim().eq_ignore_ascii_case("post") => "post",
+                    _ => "get",
+                };
+                self.forms.push(Form {
+                    action: tag.attributes.get("action").cloned().unwrap_or_default(),
+                    method: method.to_owned(),
+                });
+                self.form = Some(self.forms.len() - 1);
+            }
+            (true, "form") => self.form = None,
+            (false, "a") => {
+                let href = tag.attributes.get("href").map(|href| {
+                    let resolved = self.base.as_ref().and_then(|base| base.resolve(href));
+                    resolved.map_or(href.as_str().into(), |url| url.to_string().into())
+                });
+                self.links.push(href);
+            }
+            (true, "a") => {
+                self.links.pop();
+            }
+            (false, "sub" | "sup") => {
+                // Shrink the text and move it by a fraction of the surrounding
+                // font size, down for subscripts and up for superscripts
+                let size = font.pt_to_px_scale(self.font_size.0 as f32).unwrap().y;
+                let shift = if tag.name == "sub" {
+                    size * SUBSCRIPT_SHIFT
+                } else {
+                    -size * SUPERSCRIPT_SHIFT
+                };
+                self.scripts.push((self.font_size, self.baseline_shift));
+                self.set_font_size(FontSize((self.font_size.0 * 5 / 6).max(1)));
+                self.baseline_shift += shift.round() as i32;
+            }
+            (true, "sub" | "sup") => {
+                if let Some((font_size, baseline_shift)) = self.scripts.pop() {
+                    self.font_size = font_size;
+                    self.baseline_shift = baseline_shift;
+                }
+            }
+            // Small print is a step smaller, like a subscript but on the line,
+            // and big text a step bigger
+            (false, "small") => self.push_font_size(SMALL_SIZE),
+            (false, "big") => self.push_font_size(BIG_SIZE),
+            (true, "small" | "big") => self.pop_font_size(),
+            _ => {}
+        }
+    }
+
+    /// Scales the font size by `percent` until the matching
+    /// `pop_font_size`, as tags setting the size relative to the text
+    /// around them do
+    pub(crate) fn push_font_size(&mut self, percent: u32) {
+        self.sizes.push(self.font_size);
+        self.set_font_size(FontSize((self.font_size.0 * percent / 100).max(1)));
+    }
+
+    pub(crate) fn pop_font_size(&mut self) {
+        if let Some(font_size) = self.sizes.pop() {
+            self.font_size = font_size;
+        }
+    }
+
+    /// Changes the font size, keeping it at or above the minimum
+    pub(crate) fn set_font_size(&mut self, font_size: FontSize) {
+        self.font_size = self
+            .min_font_size
+            .map_or(font_size, |min| font_size.max(min));
+    }
+
+    /// Sets the text style to an element's computed style
+    pub(crate) fn set_style(&mut self, style: &Style) {
+        self.font_properties.font_weight = style.font_weight.clone();
+        self.font_properties.font_style = style.font_style.clone();
+        self.style = style.clone();
+    }
+
+    pub(crate) fn word(
+        &mut self,
+        (source, range): (Rc<str>, Range<usize>),
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+        space_font: &'static FontRef<'static>,
+    ) {
+        let word = &source[range.clone()];
+        let (glyph_buffer, word_width_in_px) = self.shape(word, font, face);
+
+        // A word that ends right at the margin still fits; the space after it
+        // is left hanging past the margin rather than wrapping the word
+        if self.nobr || self.cursor_x + word_width_in_px <= self.right() {
+            let item = self.text_item(glyph_buffer, font, face, source, range);
+            self.push_text(item);
+            self.cursor_x += word_width_in_px + self.space_width(space_font) as u32;
+            return;
+        }
+
+        // Otherwise it's cut into pieces that fit, measured from the word as
+        // shaped already, so each piece is only shaped once more to be shown
+        let widths = self.cluster_widths(&glyph_buffer, font, word.len());
+        let width_at =
+            |offset: usize| widths[widths.partition_point(|&(cluster, _)| cluster < offset)].1;
+        let breaks: Vec<(usize, char)> = word
+            .char_indices()
+            .filter(|(_, c)| is_word_break(*c))
+            .collect();
+        let mut hyphen_width = None;
+        let mut start = 0;
+        loop {
+            let room = self.right().saturating_sub(self.cursor_x) as f32;
+            let start_width = width_at(start);
+            let fits = |width: f32| width - start_width <= room;
+            if fits(width_at(word.len())) {
+                break;
+            }
+
+            // Break at the last soft hyphen or <wbr> that leaves a piece that
+            // still fits, along with the visible hyphen a soft hyphen turns into
+            let first = breaks.partition_point(|(split, _)| *split < start);
+            let last = breaks.partition_point(|(split, _)| fits(width_at(*split)));
+            let hyphenated = breaks[first..last.max(first)]
+                .iter()
+                .rev()
+                .find(|&&(split, c)| {
+                    let hyphen = match c {
+                        SOFT_HYPHEN => *hyphen_width
+                            .get_or_insert_with(|| self.shape("-", font, face).1 as f32),
+                        _ => 0.0,
+                    };
+                    fits(width_at(split) + hyphen)
+                });
+            if let Some(&(split, c)) = hyphenated {
+                let piece = range.start + start..range.start + split;
+                self.line_piece(&source, piece, c == SOFT_HYPHEN, font, face);
+                start = split + c.len_utf8();
+                continue;
+            }
+
+            // Otherwise try again on a line of its own, where there's more
+            // room to break it
+            if !self.line.is_empty() {
+                self.flush();
+                continue;
+            }
+
+            // A word too wide even for that, like a long URL, is broken
+            // wherever it reaches the edge rather than running off it: after
+            // as many of its clusters as fit, but at least one so what's left
+            // gets shorter. Clusters keep a character together with its
+            // combining marks.
+            let next = widths[widths.partition_point(|(cluster, _)| *cluster <= start)].0;
+            let split = widths[..widths.partition_point(|(_, width)| fits(*width))]
+                .last()
+                .map_or(next, |(cluster, _)| (*cluster).max(next));
+            if split >= word.len() {
+                break;
+            }
+            let piece = range.start + start..range.start + split;
+            self.line_piece(&source, piece, false, font, face);
+            start = split;
+        }
+
+        let rest = range.start + start..range.end;
+        let (glyph_buffer, width) = self.shape(&source[rest.clone()], font, face);
+        let item = self.text_item(glyph_buffer, font, face, source, rest);
+        self.push_text(item);
+        self.cursor_x += width + self.space_width(space_font) as u32;
+    }
+
+    /// Lays out `range` of `source`, the piece of a word before a break, then
+    /// ends the line after it. A piece broken at a soft hyphen shows one.
+    fn line_piece(
+        &mut self,
+        source: &Rc<str>,
+        range: Range<usize>,
+        hyphen: bool,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let piece = &source[range.clone()];
+        let (glyph_buffer, _) = match hyphen {
+            true => self.shape(&format!("{}-", piece), font, face),
+            false => self.shape(piece, font, face),
+        };
+        let item = self.text_item(glyph_buffer, font, face, source.clone(), range);
+        self.push_text(item);
+        self.flush();
+    }
+
+    /// Reserves an inline box for an <img>, sized by its width and height
+    /// attributes, with its alt text inside. A missing dimension comes from
+    /// the loaded image, keeping its aspect ratio.
+    pub(crate) fn image(
+        &mut self,
+        tag: &Tag,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let dimension = |name: &str| -> Option<u32> {
+            tag.attributes
+                .get(name)
+                .and_then(|value| value.trim().trim_end_matches("px").parse().ok())
+        };
+        let source = tag
+            .attributes
+            .get("src")
+            .and_then(|src| self.images.get(src))
+            .cloned();
+        let (width, height) = match (dimension("width"), dimension("height"), &source) {
+            (Some(width), Some(height), _) => (width, height),
+            (Some(width), None, Some(source)) => {
+                (width, width * source.height() / source.width().max(1))
+            }
+            (None, Some(height), Some(source)) => {
+                (height * source.width() / source.height().max(1), height)
+            }
+            (None, None, Some(source)) => source.dimensions(),
+            (width, height, None) => (
+                width.unwrap_or(DEFAULT_IMAGE_SIZE),
+                height.unwrap_or(DEFAULT_IMAGE_SIZE),
+            ),
+        };
+        let url = tag.attributes.get("src").and_then(|src| {
+            let url = match &self.base {
+                Some(base) => base.resolve(src),
+                None => URL::new(src).ok(),
+            };
+            url.map(|url| url.to_string().into())
+        });
+        let pixels = source.filter(|_| width > 0 && height > 0).map(|source| {
+            if source.dimensions() == (width, height) {
+                source
+            } else {
+                Rc::new(image::imageops::resize(
+                    source.as_ref(),
+                    width,
+                    height,
+                    FilterType::Triangle,
+                ))
+            }
+        });
+
+        let image = ImageItem {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            alt: None,
+            pixels,
+            url,
+            frame: None,
+        };
+        let alt = tag.attributes.get("alt").filter(|alt| !alt.is_empty());
+        self.inline_box(image, alt.map(String::as_str), font, face);
+    }
+
+    /// Reserves an inline box for an <iframe>, sized by its width and height
+    /// attributes like an image. Frames aren't loaded; the box shows where
+    /// it would have come from instead, as a link to open it.
+    pub(crate) fn frame(
+        &mut self,
+        tag: &Tag,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let dimension = |name: &str| -> Option<u32> {
+            tag.attributes
+                .get(name)
+                .and_then(|value| value.trim().trim_end_matches("px").parse().ok())
+        };
+        let width = dimension("width").unwrap_or(DEFAULT_FRAME_WIDTH);
+        let height = dimension("height").unwrap_or(DEFAULT_FRAME_HEIGHT);
+        let src = tag
+            .attributes
+            .get("src")
+            .map(|src| src.trim())
+            .filter(|src| !src.is_empty());
+        let href: Option<Rc<str>> = src.map(|src| {
+            let resolved = self.base.as_ref().and_then(|base| base.resolve(src));
+            resolved.map_or(src.into(), |url| url.to_string().into())
+        });
+        let label = format!("frame: {}", href.as_deref().unwrap_or("(none)"));
+
+        let image = ImageItem {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            alt: None,
+            pixels: None,
+            url: None,
+            frame: href,
+        };
+        self.inline_box(image, Some(&label), font, face);
+    }
+
+    /// Lays out an image or frame box on the current line, with `label`
+    /// inside it for when there's nothing to draw there
+    fn inline_box(
+        &mut self,
+        mut image: ImageItem,
+        label: Option<&str>,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let (width, height) = (image.width, image.height);
+        if !self.nobr && self.cursor_x != self.left() && self.cursor_x + width > self.right() {
+            self.flush();
+        }
+
+        // Font size should be set in pt, not px
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+        let scaled_font = font.as_scaled(scale);
+        image.alt = label.map(|label| {
+            let (glyph_buffer, _) = self.shape(label, font, face);
+            let mut item = self.text_item(glyph_buffer, font, face, label.into(), 0..label.len());
+            item.x = IMAGE_PADDING;
+            item.y = IMAGE_PADDING + scaled_font.ascent() as u32;
+            item
+        });
+
+        image.x = self.cursor_x;
+        self.line.push(LineItem {
+            item: DisplayItem::Image(image),
+            ascent: height as f32,
+            descent: 0.0,
+            shift: 0,
+            hidden: self.style.hidden,
+        });
+        self.cursor_x += width + scaled_font.h_advance(scaled_font.glyph_id(' ')) as u32;
+    }
+
+    pub(crate) fn text_item(
+        &self,
+        glyphs: GlyphBuffer,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+        source: Rc<str>,
+        range: Range<usize>,
+    ) -> TextItem {
+        // Slant the glyphs ourselves when the font has no italic face to use
+        let synthetic_oblique = self.font_properties.font_style != FontStyle::Normal
+            && !face.is_italic()
+            && !face.is_oblique();
+        // Likewise embolden them when there's no bold face
+        let synthetic_bold = self.font_properties.font_weight == FontWeight::Bold
+            && face.weight().to_number() < rustybuzz::ttf_parser::Weight::SemiBold.to_number();
+        let href = self.links.iter().rev().flatten().next().cloned();
+        TextItem {
+            glyphs,
+            x: self.cursor_x,
+            // Filled in once the line's baseline is known
+            y: 0,
+            font,
+            font_properties: self.font_properties.clone(),
+            font_size: self.font_size,
+            underline: self.style.underline,
+            strikethrough: self.style.strikethrough,
+            source,
+            range,
+            title: self.titles.last().map(|(_, title)| title.clone()),
+            color: self.style.color,
+            href,
+            block: self.block,
+            synthetic_oblique,
+            synthetic_bold,
+            vertical: self.vertical.is_some(),
+        }
+    }
+
+    pub(crate) fn push_text(&mut self, item: TextItem) {
+        let scaled_font = item.font.as_scaled(item.scale());
+        let ascent = scaled_font.ascent();
+        let descent = -scaled_font.descent();
+
+        // Split any extra line spacing evenly above and below the text
+        let leading = scaled_font.height() * self.line_height - (ascent + descent);
+        let shift = self.baseline_shift;
+        self.line.push(LineItem {
+            item: DisplayItem::Text(item),
+            ascent: ascent + leading / 2.0 - shift as f32,
+            descent: descent + leading / 2.0 + shift as f32,
+            shift,
+            hidden: self.style.hidden,
+        });
+    }
+
+    /// Finishes the current line: places everything on it on a baseline low
+    /// enough for its tallest item, then moves down to start the next line
+    pub(crate) fn flush(&mut self) {
+        if self.vertical.is_some() {
+            self.flush_column();
+            return;
+        }
+        if self.line.is_empty() {
+            return;
+        }
+
+        let ascent = self
+            .line
+            .iter()
+            .map(|line_item| line_item.ascent)
+            .fold(0.0, f32::max);
+        let descent = self
+            .line
+            .iter()
+            .map(|line_item| line_item.descent)
+            .fold(0.0, f32::max);
+        let baseline = self.cursor_y + ascent as u32;
+        for LineItem {
+            mut item,
+            shift,
+            hidden,
+            ..
+        } in self.line.drain(..)
+        {
+            if hidden {
+                continue;
+            }
+            match &mut item {
+                DisplayItem::Text(text) => text.y = baseline.saturating_add_signed(shift),
+                DisplayItem::Image(image) => image.y = baseline - image.height,
+                DisplayItem::Input(input) => input.y = baseline - input.baseline,
+            }
+            self.display_list.push(item);
+        }
+
+        self.cursor_x = self.left();
+        self.cursor_y += (ascent + descent) as u32;
+    }
+
+    /// Ends the current line, and the block it was part of
+    pub(crate) fn start_block(&mut self) {
+        self.flush();
+        self.block += 1;
+    }
+
+    /// Ends the current line, or leaves an empty one if nothing is on it, so
+    /// that each of several <br>s in a row starts a line of its own
+    pub(crate) fn line_break(&mut self, font: &'static FontRef<'static>) {
+        if self.line.is_empty() {
+            // Font size should be set in pt, not px
+            let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+            let height = (font.as_scaled(scale).height() * self.line_height) as u32;
+            match self.vertical {
+                Some(_) => self.cursor_x = self.cursor_x.saturating_sub(height),
+                None => {
+                    self.cursor_x = self.left();
+                    self.cursor_y += height;
+                }
+            }
+        }
+        self.start_block();
+    }
+
+    /// Ends the current line and leaves a gap before the next block
+    pub(crate) fn block_break(&mut self) {
+        self.start_block();
+        match self.vertical {
+            // Blocks follow each other leftwards in vertical text
+            Some(_) => self.cursor_x = self.cursor_x.saturating_sub(BLOCK_SPACING),
+            None => self.cursor_y += BLOCK_SPACING,
+        }
+    }
+
+    /// Widens the gap before the next word for each tab in it. Tabs at the
+    /// start of a line collapse like any other leading whitespace.
+    pub(crate) fn tab(&mut self, tabs: usize, font: &'static FontRef<'static>) {
+        if tabs == 0 || self.cursor_x == self.left() {
+            return;
+        }
+
+        // The word before already left one space's worth of gap
+        let spaces = tabs as u32 * TAB_SIZE - 1;
+        self.cursor_x += (self.space_width(font) * spaces as f32) as u32;
+    }
+
+    /// Lays out text inside a <pre> as it's written: every space is kept,
+    /// each newline starts a new line, and long lines aren't wrapped
+    pub(crate) fn preformatted(
+        &mut self,
+        text: &Rc<str>,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) {
+        let space_width = self.space_width(font);
+        let mut spaces = 0;
+        let mut word_start = None;
+        for (offset, c) in text.char_indices().chain([(text.len(), '\n')]) {
+            if !matches!(c, ' ' | '\t' | '\n') {
+                word_start.get_or_insert(offset);
+                continue;
+            }
+            if let Some(start) = word_start.take() {
+                self.cursor_x += (spaces as f32 * space_width).round() as u32;
+                spaces = 0;
+                let (glyph_buffer, width) = self.shape(&text[start..offset], font, face);
+                let item = self.text_item(glyph_buffer, font, face, text.clone(), start..offset);
+                self.push_text(item);
+                self.cursor_x += width;
+            }
+            match c {
+                // The newline added at the end only finishes the last word
+                '\n' if offset == text.len() => {}
+                '\n' => {
+                    spaces = 0;
+                    self.line_break(font);
+                }
+                '\t' => spaces += TAB_SIZE as usize,
+                _ => spaces += 1,
+            }
+        }
+        // Spaces at the end carry on into whatever follows, like a <b>
+        self.cursor_x += (spaces as f32 * space_width).round() as u32;
+    }
+
+    /// How wide a space is at the current font size in `font`. The gap after
+    /// a word is always measured in the regular face of the current family,
+    /// not the word's own, so a bold or italic word followed by plain text
+    /// isn't set further apart than two plain words would be.
+    pub(crate) fn space_width(&self, font: &'static FontRef<'static>) -> f32 {
+        // Font size should be set in pt, not px
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+        let scaled_font = font.as_scaled(scale);
+        scaled_font.h_advance(scaled_font.glyph_id(' '))
+    }
+
+    /// Shapes `text` at the current font size, returning the glyphs and their
+    /// total width in px
+    pub(crate) fn shape(
+        &self,
+        text: &str,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) -> (GlyphBuffer, u32) {
+        // Font size should be set in pt, not px
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+
+        // RustyBuzz offsets / advances need to be manually scaled to px values
+        let unscaled_height = font.height_unscaled();
+        let scale_factor = scale.x / unscaled_height;
+
+        let mut buffer: UnicodeBuffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        let glyph_buffer = shape(face, &[], buffer);
+
+        let width_in_px: u32 = (glyph_buffer
+            .glyph_positions()
+            .iter()
+            .map(|p| p.x_advance)
+            .sum::<i32>() as f32
+            * scale_factor) as u32;
+
+        (glyph_buffer, width_in_px)
+    }
+
+    /// How wide a shaped word of `len` bytes is before each of its clusters,
+    /// and at its end, in order of where they start in the word. A word can
+    /// only break between clusters.
+    pub(crate) fn cluster_widths(
+        &self,
+        glyphs: &GlyphBuffer,
+        font: &'static FontRef<'static>,
+        len: usize,
+    ) -> Vec<(usize, f32)> {
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+        let scale_factor = scale.x / font.height_unscaled();
+        // Right-to-left text is shaped last character first
+        let mut advances: Vec<(usize, f32)> = glyphs
+            .glyph_infos()
+            .iter()
+            .zip(glyphs.glyph_positions())
+            .map(|(info, pos)| (info.cluster as usize, pos.x_advance as f32 * scale_factor))
+            .collect();
+        advances.sort_by_key(|(cluster, _)| *cluster);
+
+        let mut widths: Vec<(usize, f32)> = Vec::new();
+        let mut width = 0.0;
+        for (cluster, advance) in advances {
+            if widths.last().is_none_or(|(last, _)| *last < cluster) {
+                widths.push((cluster, width));
+            }
+            width += advance;
+        }
+        widths.push((len, width));
+        widths
+    }
+}
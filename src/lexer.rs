@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub(crate) const NBSP: char = '\u{a0}';
+// Invisible unless a line breaks at it, where it shows as a hyphen
+pub(crate) const SOFT_HYPHEN: char = '\u{ad}';
+// What a <wbr> becomes: a zero-width space, where a line can break without
+// anything showing
+pub(crate) const WORD_BREAK: char = '\u{200b}';
+pub(crate) const TAB_SIZE: u32 = 4;
+
+pub(crate) const ENTITIES: &[(&str, char)] = &[
+    ("nbsp", NBSP),
+    ("shy", SOFT_HYPHEN),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("amp", '&'),
+    ("quot", '"'),
+    ("apos", '\''),
+];
+
+/// Whether a line can break at `c` within a word: a soft hyphen or a <wbr>
+pub(crate) fn is_word_break(c: char) -> bool {
+    c == SOFT_HYPHEN || c == WORD_BREAK
+}
+
+/// The character a numeric reference like "#233" or "#x1F600" (without its
+/// "&" and ";") stands for. Numbers that aren't a character, like a
+/// surrogate or zero, stand for U+FFFD.
+fn numeric_reference(name: &str) -> Option<char> {
+    let name = name.strip_prefix('#')?;
+    let (digits, radix) = match name.strip_prefix(['x', 'X']) {
+        Some(hex) => (hex, 16),
+        None => (name, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let c = u32::from_str_radix(digits, radix)
+        .ok()
+        .filter(|&code| code != 0)
+        .and_then(char::from_u32);
+    Some(c.unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+/// Replaces the named and numeric character references in `text`. Anything
+/// that isn't a known entity is left as is.
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest.find(';').and_then(|end| {
+            let name = &rest[1..end];
+            ENTITIES
+                .iter()
+                .find(|(entity, _)| *entity == name)
+                .map(|(_, c)| *c)
+                .or_else(|| numeric_reference(name))
+                .map(|c| (c, end))
+        });
+        match entity {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Splits text into words on whitespace, except for non-breaking spaces which
+/// stay inside the word so it can't wrap there.
+///
+/// Punctuation is never split from the text it touches, so "(word)." is one
+/// word, while a run of punctuation with whitespace on both sides ("a -- b")
+/// is a word of its own. Each word comes with the number of tabs in the
+/// whitespace before it, since a tab widens the gap to `TAB_SIZE` spaces
+/// rather than collapsing into a single one.
+pub(crate) fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_break = |c: char| c.is_whitespace() && c != NBSP;
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let start = rest.find(|c| !is_break(c))?;
+        let tabs = rest[..start].matches('\t').count();
+        let end = rest[start..]
+            .find(is_break)
+            .map_or(rest.len(), |end| start + end);
+        let word = &rest[start..end];
+        rest = &rest[end..];
+        Some((tabs, word))
+    })
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tag {
+    // Lowercased, and without the "/" of a closing tag
+    pub name: String,
+    pub closing: bool,
+    pub attributes: HashMap<String, String>,
+}
+
+impl Tag {
+    /// Parses the text between `<` and `>` into the tag name and its
+    /// attributes. Tag and attribute names are case-insensitive and values may
+    /// be double-quoted, single-quoted or bare.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim_start();
+        let (closing, text) = match text.strip_prefix('/') {
+            Some(text) => (true, text.trim_start()),
+            None => (false, text),
+        };
+        // The name also ends at the "/" of self-closing syntax, as in "<br/>"
+        let name_end = text
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(text.len());
+        let (name, mut rest) = text.split_at(name_end);
+        let mut attributes = HashMap::new();
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+
+            let key_end = rest
+                .find(|c: char| c == '=' || c.is_whitespace())
+                .unwrap_or(rest.len());
+            let key = rest[..key_end].to_lowercase();
+            rest = rest[key_end..].trim_start();
+
+            let mut value = "";
+            if let Some(after) = rest.strip_prefix('=') {
+                let after = after.trim_start();
+                if let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    value = &inner[..end];
+                    rest = inner.get(end + 1..).unwrap_or_default();
+                } else {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    value = &after[..end];
+                    rest = &after[end..];
+                }
+            }
+
+            // The first occurrence of an attribute wins, and a trailing "/"
+            // from self-closing syntax isn't an attribute at all
+            if !key.is_empty() && key != "/" {
+                attributes
+                    .entry(key)
+                    .or_insert_with(|| decode_entities(value));
+            }
+        }
+
+        Self {
+            name: name.to_lowercase(),
+            closing,
+            attributes,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Token {
+    Tag(Tag),
+    // Shared so display items can point back into it without copying
+    Text(Rc<str>),
+}
+
+/// Splits a page into tags and the text between them, with entities in the
+/// text decoded. Comments are dropped.
+///
+/// Like browsers, a tag only starts at a `<` directly followed by a letter,
+/// `/`, `!` or `?`, so "1 < 2" and "1<2" are text. Other malformed markup is
+/// kept as text rather than lost too: a `<` that is followed by another `<`
+/// before any `>`, a tag left unterminated at the end of the page, and a
+/// stray `>`.
+pub fn lex(body: &str) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::new();
+    let mut text = String::new();
+    let mut tag = String::new();
+    let mut in_tag = false;
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_tag && tag.starts_with("!--") {
+            // A comment only ends at "-->", so it can contain "<" and ">"
+            if c == '>' && tag.len() >= 5 && tag.ends_with("--") {
+                in_tag = false;
+                tag.clear();
+                if !text.is_empty() {
+                    out.push(Token::Text(decode_entities(&text).into()));
+                    text.clear();
+                }
+            } else {
+                tag.push(c);
+            }
+        } else if c == '<' {
+            if in_tag {
+                // The previous "<" never became a tag
+                text.push('<');
+                text.push_str(&tag);
+                tag.clear();
+            }
+            in_tag = chars
+                .peek()
+                .is_some_and(|next| next.is_alphabetic() || matches!(next, '/' | '!' | '?'));
+            if !in_tag {
+                text.push(c);
+            }
+        } else if c == '>' && in_tag {
+            in_tag = false;
+            let parsed = Tag::parse(&tag);
+            tag.clear();
+            // A <wbr> stays part of the word around it, as a place to break
+            if parsed.name == "wbr" && !parsed.closing {
+                text.push(WORD_BREAK);
+                continue;
+            }
+            if !text.is_empty() {
+                out.push(Token::Text(decode_entities(&text).into()));
+                text.clear();
+            }
+            out.push(Token::Tag(parsed));
+        } else if in_tag {
+            tag.push(c);
+        } else {
+            text.push(c);
+        }
+    }
+
+    if in_tag && !tag.starts_with("!--") {
+        text.push('<');
+        text.push_str(&tag);
+    }
+    if !text.is_empty() {
+        out.push(Token::Text(decode_entities(&text).into()));
+    }
+
+    out
+}
+
+/// Tokens that show `body` as it is inside a <pre>, keeping every space
+pub(crate) fn preformatted(body: &str) -> Vec<Token> {
+    let pre = |closing| {
+        Token::Tag(Tag {
+            name: "pre".to_owned(),
+            closing,
+            attributes: HashMap::new(),
+        })
+    };
+    vec![pre(false), Token::Text(body.into()), pre(true)]
+}
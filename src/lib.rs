@@ -0,0 +1,46 @@
+//! A small web browser engine: fetches a page, lays out its text and images
+//! and draws it into an RGBA frame buffer.
+
+mod about;
+mod blocklist;
+mod browser;
+mod cache;
+mod charset;
+mod css;
+mod dom;
+mod error;
+mod favicon;
+mod font;
+mod form;
+mod glyph;
+mod http;
+mod images;
+mod intercept;
+mod json;
+mod layout;
+mod lexer;
+mod loading;
+mod reader;
+mod refresh;
+mod table;
+mod tabs;
+mod text;
+mod url;
+mod vertical;
+mod zoom;
+
+pub use blocklist::Blocklist;
+pub use browser::Browser;
+pub use css::Style;
+pub use dom::{Element, Node, body, head, parse};
+pub use error::BrowserError;
+pub use http::{HttpClient, Response, Sni, TlsError};
+pub use images::ImageCache;
+pub use intercept::RequestInterceptor;
+pub use lexer::{Tag, Token, lex};
+pub use tabs::Tabs;
+pub use url::URL;
+
+// TODO: FIX VSTEP AND HSTEP
+const VSTEP: u32 = 40;
+const HSTEP: u32 = 40;
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::browser::{Browser, page_body};
+use crate::dom::parse;
+use crate::error::BrowserError;
+use crate::http::{BodyKind, Progress, Response};
+use crate::lexer::preformatted;
+use crate::url::URL;
+
+// How often a page loading in the background is checked for more of it
+pub(crate) const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A page being loaded on another thread, and as much of it as has arrived
+pub(crate) struct Loading {
+    pub(crate) url: URL,
+    pub(crate) receiver: Receiver<Progress>,
+    // The headers of the response, once they've arrived
+    pub(crate) headers: Option<HashMap<String, Vec<String>>>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Browser {
+    /// Starts loading `url` in the background. Once it starts to arrive it
+    /// replaces the current page, and each `tick` shows as much of it as
+    /// there is so far, so the top of a long page can be read and scrolled
+    /// while the rest loads. Only web pages load this way; anything else
+    /// loads straight away, as with `load`.
+    pub fn start_load(&mut self, url: URL) -> Result<(), BrowserError> {
+        if url.scheme != "http" && url.scheme != "https" {
+            return self.load(url);
+        }
+        let (sender, receiver) = mpsc::channel();
+        let mut client = self.http_client.fork();
+        if let Some(connection) = self.http_client.take_idle(&url) {
+            client.release(&url, connection);
+        }
+        client.progress = Some(sender.clone());
+        let page = url.clone();
+        thread::spawn(move || {
+            let response = page.request_page(&mut client, None);
+            client.progress = None;
+            // Nobody is listening any more if another page was loaded since
+            let _ = sender.send(Progress::Done(response, Box::new(client)));
+        });
+
+        self.refresh = None;
+        self.loading = Some(Loading {
+            url,
+            receiver,
+            headers: None,
+            body: Vec::new(),
+        });
+        Ok(())
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Shows whatever more of the loading page has arrived, finishing the
+    /// load once all of it has
+    pub(crate) fn poll_load(&mut self) {
+        let Some(loading) = &mut self.loading else {
+            return;
+        };
+        let mut started = false;
+        let mut arrived = false;
+        let done = loop {
+            match loading.receiver.try_recv() {
+                Ok(Progress::Head(headers)) => {
+                    loading.headers = Some(headers);
+                    loading.body.clear();
+                    started = true;
+                }
+                Ok(Progress::Body(piece)) => {
+                    loading.body.extend(piece);
+                    arrived = true;
+                }
+                Ok(Progress::Done(response, client)) => {
+                    // Keep the connection for the page's images and whatever
+                    // is loaded next
+                    self.http_client.join(*client);
+                    break Some(response);
+                }
+                // Icons come on a channel of their own
+                Ok(Progress::Icon(..)) => {}
+                Err(TryRecvError::Empty) => break None,
+                Err(TryRecvError::Disconnected) => {
+                    break Some(Err(BrowserError::Network(
+                        "The page stopped loading".to_owned(),
+                    )));
+                }
+            }
+        };
+
+        if let Some(response) = done {
+            let url = self.loading.take().unwrap().url;
+            match page_body(response) {
+                Ok((body, kind)) => self.show_body(url, body, kind),
+                Err(err) => self.show_error(&url.to_string(), &err),
+            }
+            return;
+        }
+
+        if started {
+            // The new page starts at the top, in place of the old one
+            let url = loading.url.clone();
+            self.show(Vec::new(), Some(url));
+            self.scroll = 0;
+            self.scroll_x = 0;
+            self.reset_scroll();
+        }
+        if arrived {
+            self.show_partial();
+        }
+    }
+
+    /// Lays out as much of the loading page as has arrived. Images aren't
+    /// loaded until all of it has.
+    pub(crate) fn show_partial(&mut self) {
+        let Some(loading) = &self.loading else {
+            return;
+        };
+        let headers = loading.headers.clone().unwrap_or_default();
+        let response = Response {
+            status: 200,
+            headers,
+            body: loading.body.clone(),
+            head: String::new(),
+        };
+        let text = response.text();
+        let tokens = match response.body_kind() {
+            BodyKind::Html => {
+                // A tag cut off partway isn't shown until the rest of it
+                // arrives
+                let end = match (text.rfind('<'), text.rfind('>')) {
+                    (Some(open), close) if close.is_none_or(|close| close < open) => open,
+                    _ => text.len(),
+                };
+                self.lex(text[..end].to_owned())
+            }
+            // JSON can only be pretty-printed once it's whole
+            BodyKind::PlainText | BodyKind::Json => preformatted(&text),
+        };
+        self.document = parse(tokens);
+        self.compute_styles();
+        self.layout();
+        self.selection = None;
+        self.update_find();
+        self.dirty = true;
+    }
+}
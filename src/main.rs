@@ -1,545 +1,242 @@
-use std::collections::HashMap;
 use std::env;
 use std::error::Error;
-use std::fs;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::ToSocketAddrs;
-use std::sync::Arc;
-use std::time::Duration;
-
-use socket2::{Domain, Protocol, Socket, Type};
+use std::io::Read;
+use std::rc::Rc;
+use std::time::Instant;
 
 use pixels::{Pixels, SurfaceTexture};
 use winit::dpi::LogicalSize;
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::EventLoop;
-use winit::keyboard::KeyCode;
-use winit::window::WindowBuilder;
+use winit::event::{Event, MouseButton, StartCause, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::keyboard::{Key, KeyCode, NamedKey};
+use winit::window::{Icon, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
-use ab_glyph::{Font, FontRef, ScaleFont, point};
-use rustybuzz::{Face, GlyphBuffer, UnicodeBuffer, shape};
-
-use font_kit::family_name::FamilyName;
-use font_kit::properties::{Properties, Style, Weight};
-use font_kit::source::SystemSource;
-// TODO: FIX VSTEP AND HSTEP
-const VSTEP: u32 = 40;
-const HSTEP: u32 = 40;
-
-// TODO: modularize structs / enums
-
-#[derive(Debug)]
-struct URL {
-    scheme: String,
-    host: String,
-    path: String,
-    port: String,
+use browser::{Blocklist, Browser, HttpClient, Sni, Tabs, URL};
+
+struct Options {
+    url: String,
+    insecure: bool,
+    // The name sent to TLS servers in place of the URL's host
+    sni: Sni,
+    // "Name: Value" lines to send with every request
+    headers: Vec<String>,
+    smooth_scroll: bool,
+    scroll_step: Option<u32>,
+    reverse_scroll: bool,
+    // How glyphs are blended into the page
+    gamma: Option<f32>,
+    antialias: bool,
+    dump_layout: bool,
+    // Print the page as plain text wrapped at `columns` instead of opening
+    // a window
+    text: bool,
+    columns: usize,
+    reading_width: Option<u32>,
+    min_font_size: Option<u32>,
+    vertical: bool,
+    font: Option<String>,
+    system_fonts: bool,
+    // A file of hosts and URLs never to request
+    blocklist: Option<String>,
+    verbosity: u8,
 }
 
-impl URL {
-    fn new(url: &str) -> Self {
-        let Some((scheme, mut url)) = url
-            .split_once("://")
-            .map(|(scheme, url)| (scheme.to_owned(), url.to_owned()))
-        else {
-            panic!("Invalid URL: Must include URL scheme (http://, https://, file://)");
-        };
-
-        assert!(
-            scheme == "http" || scheme == "https" || scheme == "file",
-            "Invalid URL scheme"
-        );
-
-        // TODO: Parse port into Option<u32>
-        let mut port = if scheme == "http" {
-            "80".to_owned()
-        } else {
-            "443".to_owned()
-        };
-
-        if !url.contains("/") {
-            url = format!("{}/", url);
-        }
-
-        let Some((mut host, url)) = url
-            .split_once("/")
-            .map(|(host, url)| (host.to_owned(), url.to_owned()))
-        else {
-            unreachable!()
-        };
-
-        if host.contains(":") {
-            (host, port) = host
-                .split_once(":")
-                .map(|(host, port)| (host.to_owned(), port.to_owned()))
-                .unwrap();
-        }
-
-        let path = format!("/{}", url);
-        Self {
-            scheme,
-            host,
-            path,
-            port,
-        }
-    }
-
-    fn request(self) -> Result<String, std::io::Error> {
-        let address = format!("{}:{}", self.host, self.port)
-            .to_socket_addrs()?
-            .next()
-            .unwrap();
-        let domain = if address.is_ipv4() {
-            Domain::IPV4
-        } else {
-            Domain::IPV6
-        };
-
-        let mut socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
-        socket.connect_timeout(&address.into(), Duration::from_secs(3))?;
-        let mut request = format!("GET {} HTTP/1.0\r\n", self.path);
-        request.push_str(&format!("Host: {}\r\n", self.host));
-        request.push_str("\r\n");
-        let response = if self.scheme == "https" {
-            let root_store =
-                rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-            let config = rustls::ClientConfig::builder()
-                .with_root_certificates(root_store)
-                .with_no_client_auth();
-
-            let rc_config = Arc::new(config);
-            let dns_name = self.host.clone().try_into().unwrap();
-            let server_name = rustls::pki_types::ServerName::DnsName(dns_name);
-            let mut sess = rustls::ClientConnection::new(rc_config, server_name).unwrap();
-            let mut tls = rustls::Stream::new(&mut sess, &mut socket);
-            self.read_http_response(&mut tls, &request)?
-        } else {
-            self.read_http_response(&mut socket, &request)?
-        };
-
-        Ok(response)
-    }
-
-    fn read_http_response<T: Read + Write>(
-        &self,
-        stream: &mut T,
-        request: &str,
-    ) -> Result<String, std::io::Error> {
-        stream.write_all(request.as_bytes())?;
-        stream.flush()?;
-
-        let mut reader = BufReader::new(stream);
-        let mut line = String::new();
-
-        reader.read_line(&mut line)?;
-        let mut statusline = line.splitn(3, " ");
-        let _version = statusline.next().unwrap();
-        let _status = statusline.next().unwrap();
-        let _explanation = statusline.next().unwrap();
-        line.clear();
-
-        let mut response_headers: HashMap<String, String> = HashMap::new();
-        loop {
-            reader.read_line(&mut line)?;
-            if line == "\r\n" {
-                break;
-            }
-            let temp_line = line.clone();
-            if let Some((header, value)) = temp_line.split_once(" ") {
-                response_headers.insert(header.trim().to_lowercase(), value.trim().to_owned());
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let mut url = None;
+        let mut insecure = env::var_os("BROWSER_INSECURE").is_some();
+        let mut sni = env::var("BROWSER_SNI").map_or(Sni::Host, Sni::Name);
+        let mut headers = Vec::new();
+        let mut smooth_scroll = true;
+        let mut scroll_step = env::var("BROWSER_SCROLL_STEP")
+            .ok()
+            .and_then(|step| step.parse().ok());
+        let mut reverse_scroll = env::var_os("BROWSER_REVERSE_SCROLL").is_some();
+        let mut gamma = env::var("BROWSER_GAMMA")
+            .ok()
+            .and_then(|gamma| gamma.parse().ok())
+            .filter(|&gamma: &f32| gamma > 0.0);
+        let mut antialias = env::var_os("BROWSER_NO_ANTIALIAS").is_none();
+        let mut dump_layout = false;
+        let mut text = false;
+        let mut columns = 80;
+        let mut reading_width = None;
+        let mut min_font_size = env::var("BROWSER_MIN_FONT_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok());
+        let mut vertical = false;
+        let mut font = env::var("BROWSER_FONT").ok();
+        let mut system_fonts = env::var_os("BROWSER_NO_SYSTEM_FONTS").is_none();
+        let mut blocklist = env::var("BROWSER_BLOCKLIST").ok();
+        let mut verbosity = 0;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--insecure" => insecure = true,
+                "--sni" => sni = Sni::Name(args.next()?),
+                "--no-sni" => sni = Sni::Off,
+                "-H" | "--header" => headers.push(args.next()?),
+                "--no-smooth-scroll" => smooth_scroll = false,
+                "--scroll-step" => scroll_step = Some(args.next()?.parse().ok()?),
+                "--reverse-scroll" => reverse_scroll = true,
+                "--gamma" => {
+                    gamma = Some(
+                        args.next()?
+                            .parse()
+                            .ok()
+                            .filter(|&gamma: &f32| gamma > 0.0)?,
+                    )
+                }
+                "--no-antialias" => antialias = false,
+                "--dump-layout" => dump_layout = true,
+                "--text" => text = true,
+                "--columns" => columns = args.next()?.parse().ok().filter(|&n| n > 0)?,
+                "--reading-width" => reading_width = Some(args.next()?.parse().ok()?),
+                "--min-font-size" => min_font_size = Some(args.next()?.parse().ok()?),
+                "--vertical" => vertical = true,
+                "--font" => font = Some(args.next()?),
+                "--no-system-fonts" => system_fonts = false,
+                "--blocklist" => blocklist = Some(args.next()?),
+                "-v" | "--verbose" => verbosity += 1,
+                "-vv" => verbosity += 2,
+                _ if url.is_none() => url = Some(arg),
+                _ => return None,
             }
-            line.clear();
         }
 
-        assert!(!response_headers.contains_key("transfer-encoding"));
-        assert!(!response_headers.contains_key("content-encoding"));
-
-        let mut body = String::new();
-        reader.read_to_string(&mut body)?;
-        Ok(body)
-    }
-
-    fn load_file(self) -> Result<String, std::io::Error> {
-        let contents = fs::read_to_string(self.path)?;
-        Ok(contents)
+        Some(Self {
+            url: url?,
+            insecure,
+            sni,
+            headers,
+            smooth_scroll,
+            scroll_step,
+            reverse_scroll,
+            gamma,
+            antialias,
+            dump_layout,
+            text,
+            columns,
+            reading_width,
+            min_font_size,
+            vertical,
+            font,
+            system_fonts,
+            blocklist,
+            verbosity,
+        })
     }
 }
 
-struct Browser {
-    scroll: u32,
-    tokens: Vec<Token>,
-    display_list: Vec<(GlyphBuffer, u32, u32, &'static FontRef<'static>, FontSize)>,
-    font_manager: FontManager,
-    width: u32,
-    height: u32,
-}
-
-impl Browser {
-    fn new(width: u32, height: u32) -> Self {
-        Self {
-            scroll: 0,
-            tokens: Vec::new(),
-            display_list: Vec::new(),
-            font_manager: FontManager::new(),
-            width,
-            height,
-        }
-    }
-
-    fn load(&mut self, url: URL) -> Result<(), std::io::Error> {
-        let body = match url.scheme.as_ref() {
-            "http" | "https" => url.request()?,
-            "file" => url.load_file()?,
-            _ => unreachable!()
-        };
-
-        self.tokens = self.lex(body);
-        let mut layout = Layout::new(self.width);
-        self.display_list = layout.token(&self.tokens, &mut self.font_manager);
-        Ok(())
-    }
-
-    fn lex(&self, body: String) -> Vec<Token> {
-        let mut out: Vec<Token> = Vec::new();
-        let mut buffer = String::new();
-        let mut in_tag = false;
-        for c in body.chars() {
-            if c == '<' {
-                in_tag = true;
-                if !buffer.is_empty() {
-                    out.push(Token::Text(buffer.clone()));
-                    buffer.clear();
-                }
-            } else if c == '>' {
-                in_tag = false;
-                out.push(Token::Tag(buffer.clone()));
-                buffer.clear();
-            } else {
-                buffer.push(c);
-            }
+const WINDOW_TITLE: &str = "Andy Browser";
+
+/// The window's icon for pages without one of their own: a blue dot
+fn default_icon() -> Icon {
+    let size = 32;
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (dx, dy) = (x as f32 - 15.5, y as f32 - 15.5);
+            let inside = dx * dx + dy * dy <= 15.0 * 15.0;
+            rgba.extend([40, 90, 200, if inside { 255 } else { 0 }]);
         }
-
-        if !in_tag && !buffer.is_empty() {
-            out.push(Token::Text(buffer.clone()));
-        }
-
-        out
     }
+    Icon::from_rgba(rgba, size, size).unwrap()
+}
 
-    fn reset_scroll(&mut self) {
-        self.scroll = std::cmp::min(
-            self.scroll,
-            self.display_list[self.display_list.len() - 1].2 - self.height + VSTEP,
+fn main() -> Result<(), Box<dyn Error>> {
+    let Some(options) = Options::parse(env::args().skip(1)) else {
+        println!(
+            "Usage: cargo run [--insecure] [--sni <host> | --no-sni] [-H | --header <name:value>]... [--no-smooth-scroll] [--scroll-step <px>] [--reverse-scroll] [--gamma <g>] [--no-antialias] [--dump-layout] [--text [--columns <n>]] [--reading-width <px>] [--min-font-size <pt>] [--vertical] [--font <family>] [--no-system-fonts] [--blocklist <file>] [-v | -vv] <URL | ->"
         );
+        return Ok(());
+    };
 
-        self.scroll = std::cmp::max(0, self.scroll);
-    }
-
-    fn scrolldown(&mut self) {
-        if self.display_list.is_empty() {
-            return;
-        }
-
-        self.scroll = std::cmp::min(
-            self.scroll + 20,
-            self.display_list[self.display_list.len() - 1].2 - self.height + VSTEP,
-        )
-    }
-
-    fn scrollup(&mut self) {
-        self.scroll = std::cmp::max(0, self.scroll as i32 - 20) as u32;
+    if options.insecure {
+        eprintln!("Warning: TLS certificate validation is disabled (--insecure)");
     }
 
-    fn draw(&self, frame: &mut [u8]) {
-        // Font size should be set in pt, not px
-        for (glyph_buffer, start_x, cursor_y, font, font_size) in &self.display_list {
-            let scale = font.pt_to_px_scale(font_size.0 as f32).unwrap();
-            let scaled_font = font.as_scaled(scale);
-            let infos = glyph_buffer.glyph_infos();
-            let positions = glyph_buffer.glyph_positions();
-            let mut cursor_x = *start_x as f32;
-            for (info, pos) in infos.iter().zip(positions.iter()) {
-                if *cursor_y + VSTEP < self.scroll {
-                    continue;
-                }
-
-                if *cursor_y > self.scroll + self.height {
-                    break;
-                }
-
-                // RustyBuzz offsets / advances need to be manually scaled to px values
-                let scale_factor = scale.x / font.height_unscaled();
-
-                let gid = ab_glyph::GlyphId(info.glyph_id as u16);
-                let x = cursor_x + (pos.x_offset as f32 * scale_factor);
-                let y = (*cursor_y as i32 - self.scroll as i32) as f32
-                    - (pos.y_offset as f32 * scale_factor);
-                let glyph = gid.with_scale_and_position(scale, point(x, y));
-
-                if let Some(outlined) = scaled_font.outline_glyph(glyph) {
-                    let bounds = outlined.px_bounds();
-                    outlined.draw(|gx, gy, coverage| {
-                        let gx = gx as i32 + bounds.min.x as i32;
-                        let gy = gy as i32 + bounds.min.y as i32;
-                        if gx < 0 || gx >= self.width as i32 || gy < 0 || gy >= self.height as i32 {
-                            return;
-                        }
-
-                        let idx = ((gy as u32 * self.width + gx as u32) * 4) as usize;
-                        let inv_alpha = 1.0 - coverage;
-                        let text_color = [0u8, 0u8, 0u8];
-                        for d in 0..3 {
-                            let bg = frame[idx + d] as f32;
-                            let fg = text_color[d] as f32;
-                            frame[idx + d] = (bg * inv_alpha + fg * coverage) as u8;
-                        }
-                        frame[idx + 3] = 255;
-                    });
-                }
+    let width = 800;
+    let height = 600;
 
-                // Since we're dealing with words, not characters, we need to
-                // move the starting x of the next character by the x_advance
-                cursor_x += pos.x_advance as f32 * scale_factor;
-            }
+    let mut http_client = HttpClient::new(options.insecure);
+    http_client.verbosity = options.verbosity;
+    http_client.sni = options.sni;
+    for header in &options.headers {
+        if let Err(err) = http_client.add_header(header) {
+            eprintln!("{}", err);
+            std::process::exit(1);
         }
     }
-
-    fn resize_browser(&mut self, width: u32, height: u32) {
-        self.width = width;
-        self.height = height;
-        let mut layout = Layout::new(width);
-        self.display_list = layout.token(&self.tokens, &mut self.font_manager);
-        self.reset_scroll();
+    let mut browser = Browser::new(width, height, http_client);
+    browser.smooth_scroll = options.smooth_scroll;
+    if let Some(step) = options.scroll_step {
+        browser.scroll_step = step;
     }
-}
-
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-enum FontWeight {
-    Normal,
-    Bold,
-}
-
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-enum FontStyle {
-    Normal,
-    Italic,
-    Oblique,
-}
-
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-struct FontProperties {
-    font_family: String,
-    font_weight: FontWeight,
-    font_style: FontStyle,
-}
-
-impl Default for FontProperties {
-    fn default() -> Self {
-        Self {
-            font_family: "Arial Unicode MS".into(),
-            font_weight: FontWeight::Normal,
-            font_style: FontStyle::Normal,
-        }
+    browser.reverse_scroll = options.reverse_scroll;
+    if let Some(gamma) = options.gamma {
+        browser.gamma = gamma;
     }
-}
-
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
-struct FontSize(u32);
-
-struct CachedFont {
-    ab_font: &'static FontRef<'static>,
-    rb_face: &'static Face<'static>,
-}
-
-struct FontManager {
-    source: SystemSource,
-    cached_fonts: HashMap<FontProperties, CachedFont>,
-}
-
-impl FontManager {
-    fn new() -> Self {
-        Self {
-            source: SystemSource::new(),
-            cached_fonts: HashMap::new(),
+    browser.antialias = options.antialias;
+    browser.max_content_width = options.reading_width;
+    browser.min_font_size = options.min_font_size;
+    browser.vertical_text = options.vertical;
+    browser.set_font(options.font.as_deref(), options.system_fonts);
+    if let Some(path) = &options.blocklist {
+        match Blocklist::load(path) {
+            Ok(mut blocklist) => {
+                blocklist.verbosity = options.verbosity;
+                browser.set_interceptor(blocklist);
+            }
+            Err(err) => {
+                eprintln!("Couldn't read the blocklist {}: {}", path, err);
+                std::process::exit(1);
+            }
         }
     }
-
-    fn get_fonts(
-        &mut self,
-        font_properties: &FontProperties,
-    ) -> (&'static FontRef<'static>, &'static Face<'static>) {
-        if let Some(cached) = self.cached_fonts.get(font_properties) {
-            return (cached.ab_font, cached.rb_face);
+    let dump = options.dump_layout || options.text;
+    if options.url == "-" {
+        // A document piped in, e.g. `cat page.html | browser -`
+        let mut html = Vec::new();
+        if let Err(err) = std::io::stdin().read_to_end(&mut html) {
+            eprintln!("Couldn't read from stdin: {err}");
+            std::process::exit(1);
         }
-
-        let weight = match font_properties.font_weight {
-            FontWeight::Bold => Weight::BOLD,
-            _ => Weight::NORMAL,
-        };
-
-        let style = match font_properties.font_style {
-            FontStyle::Italic => Style::Italic,
-            FontStyle::Oblique => Style::Oblique,
-            _ => Style::Normal,
-        };
-
-        let mut properties = Properties::new();
-        properties.style = style;
-        properties.weight = weight;
-        let handle = self
-            .source
-            .select_best_match(
-                &[
-                    FamilyName::Title(font_properties.font_family.clone()),
-                    FamilyName::Serif,
-                ],
-                &properties,
-            )
-            .expect("Failed to find a font");
-        let font = handle.load().expect("Failed to load font");
-        let font_data = font
-            .copy_font_data()
-            .expect("Failed to copy font data")
-            .to_vec();
-
-        // Use Box::leak() to give references a static lifetime, saving a lot of
-        // time and headache
-        let static_font_data: &'static [u8] = Box::leak(font_data.into_boxed_slice());
-
-        let ab_font = Box::leak(Box::new(
-            FontRef::try_from_slice(static_font_data).expect("Couldn't load a font"),
-        ));
-        let rb_face = Box::leak(Box::new(
-            Face::from_slice(static_font_data, 0).expect("Could not load font face"),
-        ));
-        self.cached_fonts
-            .insert(font_properties.clone(), CachedFont { ab_font, rb_face });
-
-        (ab_font, rb_face)
-    }
-}
-
-#[derive(Debug, Eq, PartialEq, Hash)]
-enum Token {
-    Tag(String),
-    Text(String),
-}
-
-struct Layout {
-    cursor_x: u32,
-    cursor_y: u32,
-    window_width: u32,
-    font_properties: FontProperties,
-    font_size: FontSize,
-}
-
-impl Layout {
-    fn new(window_width: u32) -> Self {
-        Self {
-            cursor_x: HSTEP,
-            cursor_y: VSTEP,
-            window_width,
-            font_properties: FontProperties::default(),
-            font_size: FontSize(16),
+        browser.load_html(&html);
+    } else if let Err(err) = URL::new(&options.url).and_then(|url| {
+        // The window shows the page as it arrives, but a dump needs all of it
+        if dump {
+            browser.load(url)
+        } else {
+            browser.start_load(url)
         }
-    }
-
-    fn token(
-        &mut self,
-        tokens: &Vec<Token>,
-        font_manager: &mut FontManager,
-    ) -> Vec<(GlyphBuffer, u32, u32, &'static FontRef<'static>, FontSize)> {
-        let mut display_list = Vec::<(GlyphBuffer, u32, u32, &FontRef, FontSize)>::new();
-        // TODO: reload font, face on font change in tag match block
-        for token in tokens {
-            let (font, face) = font_manager.get_fonts(&self.font_properties);
-            match token {
-                Token::Text(text) => {
-                    for word in text.split_whitespace() {
-                        self.word(word, &mut display_list, font, face);
-                    }
-                }
-                Token::Tag(tag) => {
-                    match tag.as_ref() {
-                        "i" => self.font_properties.font_style = FontStyle::Italic,
-                        "/i" => self.font_properties.font_style = FontStyle::Normal,
-                        "b" => self.font_properties.font_weight = FontWeight::Bold,
-                        "/b" => self.font_properties.font_weight = FontWeight::Normal,
-                        _ => continue,
-                    }
-                }
-            }
+    }) {
+        eprintln!("Couldn't load {}: {err}", options.url);
+        // Dumping an error page would only hide the failure
+        if dump {
+            std::process::exit(1);
         }
-
-        display_list
+        browser.show_error(&options.url, &err);
     }
 
-    fn word(
-        &mut self,
-        word: &str,
-        display_list: &mut Vec<(GlyphBuffer, u32, u32, &FontRef, FontSize)>,
-        font: &'static FontRef<'static>,
-        face: &'static Face<'static>,
-    ) {
-        // Font size should be set in pt, not px
-        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
-        let scaled_font = font.as_scaled(scale);
-
-        // RustyBuzz offsets / advances need to be manually scaled to px values
-        let unscaled_height = font.height_unscaled();
-        let scale_factor = scale.x / unscaled_height;
-
-        let space_width_in_px = scaled_font.h_advance(scaled_font.glyph_id(' '));
-        let font_height = scaled_font.height();
-        let mut buffer: UnicodeBuffer = UnicodeBuffer::new();
-        buffer.push_str(word);
-        let glyph_buffer = shape(face, &[], buffer);
-
-        let word_width_in_px: u32 = (glyph_buffer
-            .glyph_positions()
-            .iter()
-            .map(|p| p.x_advance)
-            .sum::<i32>() as f32
-            * scale_factor) as u32;
-
-        if self.cursor_x + word_width_in_px >= self.window_width - HSTEP {
-            self.cursor_x = HSTEP;
-            self.cursor_y += (font_height * 1.2) as u32;
-        }
-
-        display_list.push((
-            glyph_buffer,
-            self.cursor_x,
-            self.cursor_y,
-            font,
-            self.font_size,
-        ));
-        self.cursor_x += word_width_in_px + space_width_in_px as u32;
+    if options.dump_layout {
+        browser.dump_layout(&mut std::io::stdout().lock())?;
+        return Ok(());
     }
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: cargo run <URL>");
+    if options.text {
+        browser.dump_text(options.columns, &mut std::io::stdout().lock())?;
         return Ok(());
     }
 
-    let width = 800;
-    let height = 600;
-
-    let url = URL::new(&args[1]);
-    let mut browser = Browser::new(width, height);
-    browser.load(url)?;
-
+    let mut tabs = Tabs::new(browser);
     let event_loop = EventLoop::new().unwrap();
     let mut input = WinitInputHelper::new();
 
     let window = {
         let size = LogicalSize::new(width as f64, height as f64);
         WindowBuilder::new()
-            .with_title("Andy Browser")
+            .with_title(WINDOW_TITLE)
+            .with_window_icon(Some(default_icon()))
             .with_inner_size(size)
             .with_min_inner_size(size)
             .build(&event_loop)
@@ -552,50 +249,245 @@ fn main() -> Result<(), Box<dyn Error>> {
         Pixels::new(width, height, surface_texture)?
     };
 
+    // Created on first copy, and kept alive since on some platforms the
+    // copied text disappears along with the clipboard handle
+    let mut clipboard: Option<arboard::Clipboard> = None;
+    // What the window's title and icon were last set to
+    let mut shown_title = WINDOW_TITLE.to_owned();
+    let mut shown_favicon = None;
+
     event_loop.run(|event, elwt| {
+        match event {
+            // Sleep until input arrives, or until a pending tooltip or more
+            // of a loading page is due
+            Event::AboutToWait => match tabs.active().next_wakeup() {
+                Some(deadline) => elwt.set_control_flow(ControlFlow::WaitUntil(deadline)),
+                None => elwt.set_control_flow(ControlFlow::Wait),
+            },
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => window.request_redraw(),
+            _ => {}
+        }
+
         if let Event::WindowEvent {
             event: WindowEvent::RedrawRequested,
             ..
         } = event
         {
-            let frame = pixels.frame_mut();
-            frame.fill(255);
-            browser.draw(frame);
+            // The window system can ask for a redraw on its own (e.g. when
+            // uncovered), but unless something changed the last frame is
+            // still in the buffer and only needs presenting again
+            let scrolling = tabs.active_mut().tick(Instant::now());
+            if tabs.is_dirty() {
+                // The window shows the current tab's title and icon
+                let title = match tabs.active().title() {
+                    Some(title) => format!("{title} - {WINDOW_TITLE}"),
+                    None => WINDOW_TITLE.to_owned(),
+                };
+                if title != shown_title {
+                    window.set_title(&title);
+                    shown_title = title;
+                }
+                let favicon = tabs.active_mut().favicon();
+                let changed = match (&favicon, &shown_favicon) {
+                    (Some(favicon), Some(shown)) => !Rc::ptr_eq(favicon, shown),
+                    (favicon, shown) => favicon.is_some() != shown.is_some(),
+                };
+                if changed {
+                    let icon = favicon
+                        .as_ref()
+                        .and_then(|favicon| {
+                            Icon::from_rgba(favicon.to_vec(), favicon.width(), favicon.height())
+                                .ok()
+                        })
+                        .unwrap_or_else(default_icon);
+                    window.set_window_icon(Some(icon));
+                    shown_favicon = favicon;
+                }
+
+                let frame = pixels.frame_mut();
+                frame.fill(255);
+                tabs.draw(frame);
+            }
+
             if let Err(err) = pixels.render() {
+                eprintln!("{err}");
                 elwt.exit();
                 return;
             }
+
+            // Keep frames coming until smooth scrolling settles
+            if scrolling {
+                window.request_redraw();
+            }
         }
         // Handle input events
         if input.update(&event) {
-            // Close events
-            if input.key_pressed(KeyCode::Escape) || input.close_requested() {
+            if input.held_control() && input.key_pressed(KeyCode::KeyT) {
+                tabs.open();
+            }
+            // Closing the last tab closes the browser
+            if input.held_control() && input.key_pressed(KeyCode::KeyW) && !tabs.close() {
                 elwt.exit();
                 return;
             }
+            if input.held_control() && input.key_pressed(KeyCode::Tab) {
+                tabs.switch(if input.held_shift() { -1 } else { 1 });
+            }
 
-            if input.key_held(KeyCode::ArrowDown) {
-                browser.scrolldown();
-                window.request_redraw();
+            // Clicks on the tab strip switch tabs, and everywhere else the
+            // mouse goes to the page in the current one
+            let on_tabs = input.mouse_pressed(MouseButton::Left)
+                && input.cursor().is_some_and(|(x, y)| tabs.click(x, y));
+            let position = input
+                .cursor()
+                .and_then(|(x, y)| tabs.page_position(x, y))
+                .filter(|_| !on_tabs);
+            let browser = tabs.active_mut();
+
+            // Escape leaves find mode, then the focused input, before it
+            // closes the browser
+            if input.key_pressed(KeyCode::Escape) && browser.is_finding() {
+                browser.close_find();
+            } else if input.key_pressed(KeyCode::Escape) && browser.is_editing() {
+                browser.stop_editing();
+            } else if input.key_pressed(KeyCode::Escape) || input.close_requested() {
+                elwt.exit();
+                return;
             }
 
-            if input.key_held(KeyCode::ArrowUp) {
-                browser.scrollup();
-                window.request_redraw();
+            if let Some((x, y)) = position {
+                if input.cursor_diff() != (0.0, 0.0) {
+                    browser.hover(x, y, Instant::now());
+                }
+
+                if input.mouse_pressed(MouseButton::Left) {
+                    match browser.click(x, y) {
+                        Ok(true) => {}
+                        Ok(false) => browser.start_selection(x, y),
+                        Err(err) => eprintln!("Couldn't load the page: {err}"),
+                    }
+                } else if input.mouse_held(MouseButton::Left) {
+                    browser.extend_selection(x, y);
+                }
+            }
+
+            if input.held_control() && input.key_pressed(KeyCode::KeyC) {
+                let text = browser.selected_text();
+                if !text.is_empty() {
+                    let copied = match &mut clipboard {
+                        Some(clipboard) => clipboard.set_text(text),
+                        None => arboard::Clipboard::new().and_then(|mut new_clipboard| {
+                            let result = new_clipboard.set_text(text);
+                            clipboard = Some(new_clipboard);
+                            result
+                        }),
+                    };
+                    if let Err(err) = copied {
+                        eprintln!("Couldn't copy to the clipboard: {err}");
+                    }
+                }
+            }
+
+            // Ctrl with + and - steps through the zoom levels, and Ctrl+0
+            // goes back to the default
+            if input.held_control() {
+                if input.key_pressed(KeyCode::Equal) || input.key_pressed(KeyCode::NumpadAdd) {
+                    browser.zoom_in();
+                }
+                if input.key_pressed(KeyCode::Minus) || input.key_pressed(KeyCode::NumpadSubtract) {
+                    browser.zoom_out();
+                }
+                if input.key_pressed(KeyCode::Digit0) || input.key_pressed(KeyCode::Numpad0) {
+                    browser.reset_zoom();
+                }
+            }
+
+            if input.key_pressed(KeyCode::F9) {
+                browser.toggle_reader_mode();
+            }
+
+            let reload = input.key_pressed(KeyCode::F5)
+                || (input.held_control() && input.key_pressed(KeyCode::KeyR));
+            if reload && let Err(err) = browser.reload() {
+                eprintln!("Couldn't reload the page: {err}");
+            }
+
+            if input.held_control() && input.key_pressed(KeyCode::KeyF) {
+                browser.open_find();
+            } else if browser.is_finding() {
+                for key in input.text() {
+                    match key {
+                        Key::Character(text) if !input.held_control() => browser.find_push(text),
+                        Key::Named(NamedKey::Space) => browser.find_push(" "),
+                        Key::Named(NamedKey::Backspace) => browser.find_pop(),
+                        Key::Named(NamedKey::Enter) => browser.find_next(),
+                        _ => {}
+                    }
+                }
+            } else if browser.is_editing() {
+                for key in input.text() {
+                    match key {
+                        Key::Character(text) if !input.held_control() => browser.input_push(text),
+                        Key::Named(NamedKey::Space) => browser.input_push(" "),
+                        Key::Named(NamedKey::Backspace) => browser.input_pop(),
+                        Key::Named(NamedKey::Enter) => {
+                            if let Err(err) = browser.submit_input() {
+                                eprintln!("Couldn't submit the form: {err}");
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            // With Ctrl held the arrows jump between headings instead
+            if input.held_control() {
+                if input.key_pressed(KeyCode::ArrowDown) {
+                    browser.next_heading();
+                }
+                if input.key_pressed(KeyCode::ArrowUp) {
+                    browser.previous_heading();
+                }
+            } else {
+                if input.key_held(KeyCode::ArrowDown) {
+                    browser.scrolldown();
+                }
+
+                if input.key_held(KeyCode::ArrowUp) {
+                    browser.scrollup();
+                }
+            }
+            let (_, wheel) = input.scroll_diff();
+            if wheel != 0.0 {
+                browser.scroll_wheel(wheel);
+            }
+            if input.key_held(KeyCode::ArrowRight) {
+                browser.scroll_right();
+            }
+            if input.key_held(KeyCode::ArrowLeft) {
+                browser.scroll_left();
             }
 
             if let Some(size) = input.window_resized() {
                 if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                    eprintln!("{err}");
                     elwt.exit();
                     return;
                 }
 
                 if let Err(err) = pixels.resize_buffer(size.width, size.height) {
+                    eprintln!("{err}");
                     elwt.exit();
                     return;
                 }
 
-                browser.resize_browser(size.width, size.height);
+                // The page is laid out again once resizing settles
+                tabs.resize(size.width, size.height, Instant::now());
+            }
+
+            // Request at most one redraw per batch of input, and only when
+            // something actually changed
+            if tabs.is_dirty() || tabs.active().is_scrolling() {
                 window.request_redraw();
             }
         }
@@ -0,0 +1,39 @@
+use crate::browser::Browser;
+use crate::dom::{Element, elements};
+use crate::font::FontSize;
+
+// The text column is at most this wide in reader mode, whatever the window
+pub(crate) const READER_WIDTH: u32 = 640;
+pub(crate) const READER_FONT_SIZE: FontSize = FontSize(20);
+
+/// The element holding a page's main content: its first <article>, or
+/// failing that its first <main>. Pages with neither are kept whole.
+pub(crate) fn main_content(body: &Element) -> &Element {
+    ["article", "main"]
+        .iter()
+        .find_map(|name| elements(&body.children).find(|element| element.name() == *name))
+        .unwrap_or(body)
+}
+
+impl Browser {
+    /// Switches between showing the whole page and only its main content,
+    /// in larger text at a comfortable reading width
+    pub fn toggle_reader_mode(&mut self) {
+        self.reader_mode = !self.reader_mode;
+        // Inputs are counted from the start of what's shown, so what was
+        // typed into them would end up in the wrong ones
+        self.input_values.clear();
+        self.focus = None;
+        self.layout();
+        self.selection = None;
+        self.scroll = 0;
+        self.scroll_x = 0;
+        self.reset_scroll();
+        self.update_find();
+        self.dirty = true;
+    }
+
+    pub fn is_reader_mode(&self) -> bool {
+        self.reader_mode
+    }
+}
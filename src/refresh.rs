@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use crate::browser::Browser;
+use crate::dom::{elements, head};
+
+/// Splits the content of a <meta http-equiv="refresh">, like "5" or
+/// "0; url=/next", into how many seconds to wait and where to go, if
+/// somewhere other than the page itself. Delays too long for a `Duration`
+/// are ignored along with the rest of the tag.
+pub(crate) fn parse_refresh(content: &str) -> Option<(Duration, Option<&str>)> {
+    let content = content.trim();
+    let delay_end = content
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(content.len());
+    let delay: f32 = content[..delay_end].parse().ok()?;
+    let rest = content[delay_end..]
+        .trim_start_matches(|c: char| c.is_whitespace() || c == ';' || c == ',');
+    // The URL may come with or without "url=" before it, and quoted or not
+    let target = match rest.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url=") => &rest[4..],
+        _ => rest,
+    };
+    let target = target.trim().trim_matches(|c| c == '"' || c == '\'').trim();
+    let target = (!target.is_empty()).then_some(target);
+    Some((Duration::try_from_secs_f32(delay).ok()?, target))
+}
+
+impl Browser {
+    /// Sets the page to go to another, or load again, once the delay its
+    /// <meta http-equiv="refresh"> gives has passed. Only the first such tag
+    /// counts, and pages without a URL, like one from stdin, never refresh.
+    pub(crate) fn schedule_refresh(&mut self) {
+        self.refresh = None;
+        let Some(url) = &self.current_url else {
+            return;
+        };
+        let refresh = elements(head(&self.document))
+            .filter(|element| element.name() == "meta")
+            .filter(|element| {
+                element
+                    .tag
+                    .attributes
+                    .get("http-equiv")
+                    .is_some_and(|equiv| equiv.trim().eq_ignore_ascii_case("refresh"))
+            })
+            .find_map(|element| parse_refresh(element.tag.attributes.get("content")?));
+        let Some((delay, target)) = refresh else {
+            return;
+        };
+        let target = match target {
+            Some(target) => match self.base.as_ref().unwrap_or(url).resolve(target) {
+                Some(target) => target,
+                None => return,
+            },
+            None => url.clone(),
+        };
+        // Like redirects, a page on the web can't send the browser to a
+        // local file
+        if url.is_web() && !target.is_web() {
+            return;
+        }
+        // A delay past the end of time never comes due
+        if let Some(due) = Instant::now().checked_add(delay) {
+            self.refresh = Some((due, target));
+        }
+    }
+
+    /// Goes where the page's refresh leads once its time has come
+    pub(crate) fn follow_refresh(&mut self, now: Instant) {
+        if self.refresh.as_ref().is_none_or(|(due, _)| now < *due) {
+            return;
+        }
+        let (_, url) = self.refresh.take().unwrap();
+        if let Err(err) = self.start_load(url.clone()) {
+            self.show_error(&url.to_string(), &err);
+        }
+    }
+}
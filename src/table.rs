@@ -0,0 +1,151 @@
+use crate::dom::{Element, Node};
+use crate::font::FontManager;
+use crate::layout::{BLOCK_SPACING, DisplayItem, Layout};
+
+// The gap left between the columns of a table
+pub(crate) const CELL_SPACING: u32 = 16;
+// How wide cells are laid out when measuring how wide their content wants to be
+pub(crate) const UNBOUNDED_WIDTH: u32 = 1 << 20;
+
+/// A <td> or <th>
+pub(crate) struct Cell<'a> {
+    pub(crate) element: &'a Element,
+}
+
+/// Splits a <table> into rows of cells, taking rows from inside a <thead>,
+/// <tbody> or <tfoot> too. Anything outside a cell is dropped, and tables
+/// nested in a cell stay part of it.
+pub(crate) fn table_cells(table: &Element) -> Vec<Vec<Cell<'_>>> {
+    let mut rows: Vec<Vec<Cell>> = Vec::new();
+    for node in &table.children {
+        let Node::Element(element) = node else {
+            continue;
+        };
+        match element.name() {
+            _ if element.style.display_none => {}
+            "tr" => rows.push(row_cells(element)),
+            "thead" | "tbody" | "tfoot" => {
+                rows.extend(element.children.iter().filter_map(|node| match node {
+                    Node::Element(row) if row.name() == "tr" && !row.style.display_none => {
+                        Some(row_cells(row))
+                    }
+                    _ => None,
+                }))
+            }
+            // A cell outside any row joins the last one
+            _ => {
+                if let Some(cell) = cell(node) {
+                    match rows.last_mut() {
+                        Some(row) => row.push(cell),
+                        None => rows.push(vec![cell]),
+                    }
+                }
+            }
+        }
+    }
+    rows
+}
+
+fn row_cells(row: &Element) -> Vec<Cell<'_>> {
+    row.children.iter().filter_map(cell).collect()
+}
+
+/// The cell `node` is, if it's a <td> or <th>
+fn cell(node: &Node) -> Option<Cell<'_>> {
+    match node {
+        Node::Element(element)
+            if matches!(element.name(), "td" | "th") && !element.style.display_none =>
+        {
+            Some(Cell { element })
+        }
+        _ => None,
+    }
+}
+
+impl Layout {
+    /// Lays out a <table>. Each column is as wide as its widest cell,
+    /// narrowed when they don't all fit, and each row is as tall as its
+    /// tallest cell.
+    pub(crate) fn table(&mut self, table: &Element, font_manager: &mut FontManager) {
+        let rows = table_cells(table);
+        self.block_break();
+
+        // Measure every cell laid out on a single line
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in &rows {
+            for (column, cell) in row.iter().enumerate() {
+                let mut layout = self.cell_layout(cell, 0, UNBOUNDED_WIDTH);
+                let items = layout.document(&cell.element.children, font_manager);
+                // Lines wrap once a word reaches the right side, so leave a
+                // pixel spare for the last one
+                let width = items
+                    .iter()
+                    .map(DisplayItem::right)
+                    .max()
+                    .map_or(0, |edge| edge + 1);
+                widths[column] = widths[column].max(width);
+            }
+        }
+
+        let spacing = CELL_SPACING * columns.saturating_sub(1) as u32;
+        let available = self.content_width().saturating_sub(spacing);
+        if widths.iter().sum::<u32>() > available {
+            // Columns narrower than an even share keep their width, and the
+            // wider ones split what's left in proportion to their widths
+            let share = available / columns as u32;
+            let narrow: u32 = widths.iter().filter(|&&width| width <= share).sum();
+            let wide: u32 = widths.iter().filter(|&&width| width > share).sum();
+            let rest = available - narrow;
+            for width in widths.iter_mut().filter(|width| **width > share) {
+                *width = (*width as u64 * rest as u64 / wide as u64).max(1) as u32;
+            }
+        }
+
+        // Then place them in their columns, wrapping within the column width
+        for row in &rows {
+            let mut x = self.left();
+            let mut bottom = self.cursor_y;
+            for (cell, &width) in row.iter().zip(&widths) {
+                let mut layout = self.cell_layout(cell, x, width);
+                let items = layout.document(&cell.element.children, font_manager);
+                self.display_list.extend(items);
+                bottom = bottom.max(layout.cursor_y);
+                // Carry forms and inputs on to the next cell
+                self.forms = layout.forms;
+                self.form = layout.form;
+                self.inputs = layout.inputs;
+                self.headings.extend(layout.headings);
+                self.block = layout.block + 1;
+                x += width + CELL_SPACING;
+            }
+            self.cursor_y = bottom;
+        }
+
+        self.cursor_y += BLOCK_SPACING;
+        self.cursor_x = self.left();
+    }
+
+    /// A layout for the content of `cell`, starting at the current line in
+    /// the current text style
+    pub(crate) fn cell_layout(&self, cell: &Cell, x: u32, width: u32) -> Layout {
+        let mut layout = Layout::new(self.window_width);
+        layout.cell = Some((x, width));
+        layout.cursor_y = self.cursor_y;
+        layout.block = self.block;
+        layout.font_properties = self.font_properties.clone();
+        layout.font_size = self.font_size;
+        layout.min_font_size = self.min_font_size;
+        layout.line_height = self.line_height;
+        layout.set_style(&cell.element.style);
+        layout.titles = self.titles.clone();
+        layout.images = self.images.clone();
+        layout.forms = self.forms.clone();
+        layout.form = self.form;
+        layout.inputs = self.inputs;
+        layout.input_values = self.input_values.clone();
+        layout.links = self.links.clone();
+        layout.base = self.base.clone();
+        layout
+    }
+}
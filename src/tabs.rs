@@ -0,0 +1,225 @@
+use std::time::Instant;
+
+use crate::browser::Browser;
+use crate::layout::TextItem;
+
+pub(crate) const TAB_STRIP_HEIGHT: u32 = 30;
+pub(crate) const TAB_STRIP_COLOR: [u8; 3] = [215, 215, 215];
+pub(crate) const TAB_BORDER_COLOR: [u8; 3] = [160, 160, 160];
+pub(crate) const ACTIVE_TAB_COLOR: [u8; 3] = [255, 255, 255];
+// Tabs are this wide until there are too many to fit, then share the width
+pub(crate) const TAB_WIDTH: u32 = 200;
+pub(crate) const TAB_PADDING: u32 = 8;
+
+/// The open pages, each a browser of its own below a strip of tabs along the
+/// top of the window
+pub struct Tabs {
+    pub(crate) tabs: Vec<Browser>,
+    pub(crate) active: usize,
+    pub(crate) width: u32,
+    // Set when the tab strip needs to be repainted, like `Browser::dirty`
+    pub(crate) dirty: bool,
+}
+
+impl Tabs {
+    /// Puts `browser` in the first tab, leaving room above it for the strip
+    pub fn new(mut browser: Browser) -> Self {
+        let (width, height) = (browser.width, browser.height);
+        browser.resize_browser(width, height.saturating_sub(TAB_STRIP_HEIGHT));
+        Self {
+            tabs: vec![browser],
+            active: 0,
+            width,
+            dirty: true,
+        }
+    }
+
+    pub fn active(&self) -> &Browser {
+        &self.tabs[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Browser {
+        &mut self.tabs[self.active]
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    /// Opens an empty tab after the current one, with the same settings,
+    /// and switches to it
+    pub fn open(&mut self) {
+        let tab = self.active().new_tab();
+        self.active += 1;
+        self.tabs.insert(self.active, tab);
+        self.dirty = true;
+    }
+
+    /// Closes the current tab, switching to the one after it. The last tab
+    /// can't be closed; returns whether the tab was.
+    pub fn close(&mut self) -> bool {
+        if self.tabs.len() == 1 {
+            return false;
+        }
+        self.tabs.remove(self.active);
+        self.active = self.active.min(self.tabs.len() - 1);
+        self.tabs[self.active].finish_resize();
+        self.tabs[self.active].dirty = true;
+        self.dirty = true;
+        true
+    }
+
+    /// Switches to the tab `offset` tabs to the right, wrapping around
+    pub fn switch(&mut self, offset: isize) {
+        let len = self.tabs.len() as isize;
+        self.select((self.active as isize + offset).rem_euclid(len) as usize);
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.tabs.len() && index != self.active {
+            self.active = index;
+            self.tabs[index].finish_resize();
+            self.tabs[index].dirty = true;
+            self.dirty = true;
+        }
+    }
+
+    /// Switches to the tab at a point in window coordinates, returning
+    /// whether the point was on the tab strip at all
+    pub fn click(&mut self, x: f32, y: f32) -> bool {
+        if y >= TAB_STRIP_HEIGHT as f32 {
+            return false;
+        }
+        let index = (x.max(0.0) / self.tab_width() as f32) as usize;
+        self.select(index);
+        true
+    }
+
+    /// Converts a point in window coordinates to one in the current tab's,
+    /// or None if it's on the tab strip
+    pub fn page_position(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let y = y - TAB_STRIP_HEIGHT as f32;
+        (y >= 0.0).then_some((x, y))
+    }
+
+    /// Whether anything needs to be repainted
+    pub fn is_dirty(&self) -> bool {
+        self.dirty || self.active().dirty
+    }
+
+    /// Draws the tab strip, with the current tab's page below it
+    pub fn draw(&mut self, frame: &mut [u8]) {
+        let strip = (self.width * TAB_STRIP_HEIGHT * 4) as usize;
+        self.tabs[self.active].draw(&mut frame[strip..]);
+        self.tabs[self.active].dirty = false;
+
+        let tab_width = self.tab_width();
+        let labels: Vec<TextItem> = (0..self.tabs.len())
+            .map(|index| self.label(index, tab_width))
+            .collect();
+        let browser = &self.tabs[self.active];
+        let (width, height) = (self.width, TAB_STRIP_HEIGHT);
+        browser.fill_rect(frame, 0, 0, width, height, TAB_STRIP_COLOR);
+        for (index, label) in labels.iter().enumerate() {
+            let x = (index as u32 * tab_width) as i32;
+            if index == self.active {
+                browser.fill_rect(frame, x, 0, tab_width, height, ACTIVE_TAB_COLOR);
+            }
+            browser.fill_rect(
+                frame,
+                x + tab_width as i32 - 1,
+                0,
+                1,
+                height,
+                TAB_BORDER_COLOR,
+            );
+            browser.draw_glyphs(frame, label, (0, 0));
+        }
+        // The current tab opens onto the page, the others are separated from it
+        let line_y = height as i32 - 1;
+        for index in (0..self.tabs.len()).filter(|&index| index != self.active) {
+            let x = (index as u32 * tab_width) as i32;
+            browser.fill_rect(frame, x, line_y, tab_width, 1, TAB_BORDER_COLOR);
+        }
+        let after_tabs = self.tabs.len() as u32 * tab_width;
+        let rest = width.saturating_sub(after_tabs);
+        browser.fill_rect(frame, after_tabs as i32, line_y, rest, 1, TAB_BORDER_COLOR);
+        self.dirty = false;
+    }
+
+    /// Resizes every tab for a window that's being resized. Each one is only
+    /// laid out again once resizing settles, or the current tab is switched
+    /// to it.
+    pub fn resize(&mut self, width: u32, height: u32, now: Instant) {
+        self.width = width;
+        for tab in &mut self.tabs {
+            tab.resize(width, height.saturating_sub(TAB_STRIP_HEIGHT), now);
+        }
+        self.dirty = true;
+    }
+
+    pub(crate) fn tab_width(&self) -> u32 {
+        std::cmp::min(TAB_WIDTH, self.width / self.tabs.len() as u32)
+    }
+
+    /// The title shown on a tab: its page's title, or else its URL, cut short
+    /// to fit the tab
+    pub(crate) fn label(&mut self, index: usize, tab_width: u32) -> TextItem {
+        let tab = &self.tabs[index];
+        let text = tab.title().unwrap_or_else(|| {
+            tab.current_url
+                .as_ref()
+                .map_or("New Tab".to_owned(), |url| url.to_string())
+        });
+        let x = index as u32 * tab_width + TAB_PADDING;
+        let y = TAB_STRIP_HEIGHT - TAB_STRIP_HEIGHT / 3;
+        let room = tab_width.saturating_sub(2 * TAB_PADDING) as f32;
+
+        let browser = &mut self.tabs[self.active];
+        let label = browser.ui_text(text, x, y);
+        if label.x_extent(&label.range).1 - x as f32 <= room {
+            return label;
+        }
+        let ellipsis = browser.ui_text("…".to_owned(), 0, 0);
+        let room = room - ellipsis.x_extent(&ellipsis.range).1;
+        let fits = label
+            .source
+            .char_indices()
+            .map(|(end, _)| end)
+            .take_while(|&end| label.x_extent(&(0..end)).1 - x as f32 <= room)
+            .last()
+            .unwrap_or(0);
+        browser.ui_text(format!("{}…", &label.source[..fits]), x, y)
+    }
+}
+
+impl Browser {
+    /// An empty browser with the same settings as this one, for a new tab
+    pub(crate) fn new_tab(&self) -> Browser {
+        let mut tab = Browser::new(self.width, self.height, self.http_client.fork());
+        tab.smooth_scroll = self.smooth_scroll;
+        tab.scroll_step = self.scroll_step;
+        tab.reverse_scroll = self.reverse_scroll;
+        tab.gamma = self.gamma;
+        tab.antialias = self.antialias;
+        tab.max_content_width = self.max_content_width;
+        tab.line_height = self.line_height;
+        tab.min_font_size = self.min_font_size;
+        tab.link_color = self.link_color;
+        tab.visited_link_color = self.visited_link_color;
+        tab.vertical_text = self.vertical_text;
+        tab.site_zoom = self.site_zoom.clone();
+        tab.set_image_cache_size(self.image_cache.borrow().budget());
+        tab.font_manager.system_fonts = self.font_manager.system_fonts;
+        tab.font_family = self.font_family.clone();
+        tab
+    }
+}
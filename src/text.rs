@@ -0,0 +1,307 @@
+use std::io::Write;
+
+use crate::browser::Browser;
+use crate::css::Style;
+use crate::dom::{Element, HEAD_ELEMENTS, Node, section};
+use crate::font::FontWeight;
+use crate::lexer::is_word_break;
+use crate::reader::main_content;
+
+// Elements that are set apart from what's around them by an empty line
+const PARAGRAPHS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "table",
+    "ul",
+    "ol",
+    "dl",
+];
+// Elements that only start and end lines
+const LINES: &[&str] = &["div", "li", "tr", "dt", "dd", "caption"];
+// Bold text is wrapped in this, except in headings, which are bold anyway
+const BOLD_MARKER: &str = "**";
+const BULLET: &str = "- ";
+
+/// A paragraph, heading, list item or the like, ready to be wrapped
+struct Block {
+    // Goes before the first line, like "# " or "- ". Later lines are
+    // indented as far.
+    marker: String,
+    // Goes before every line, like "> " for each quote the block is in
+    quote: String,
+    words: Vec<String>,
+    // Text from a <pre>, kept line for line rather than wrapped
+    preformatted: Option<String>,
+    // An empty line comes before the block
+    gap: bool,
+}
+
+/// Turns a page into plain text: its blocks with the words in each, and
+/// markers for the structure text alone can't show
+#[derive(Default)]
+struct TextDump {
+    blocks: Vec<Block>,
+    words: Vec<String>,
+    // The word so far, for words split across elements like "<b>a</b>b"
+    word: String,
+    // Markers for the start of the next word, like an opening "**"
+    pending: String,
+    marker: String,
+    quote: String,
+    gap: bool,
+    bold: bool,
+    heading: bool,
+    // Each open list, with the number of the last item in it if numbered
+    lists: Vec<Option<usize>>,
+    // Cells so far in the current table row
+    cells: usize,
+    pre: Option<String>,
+}
+
+impl TextDump {
+    fn nodes(&mut self, nodes: &[Node], style: &Style) {
+        for node in nodes {
+            match node {
+                Node::Text(text) if !style.hidden => self.text(text),
+                Node::Text(_) => {}
+                Node::Element(element) => self.element(element),
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(pre) = &mut self.pre {
+            pre.push_str(text);
+            return;
+        }
+        for c in text.chars().filter(|&c| !is_word_break(c)) {
+            if c.is_whitespace() && c != '\u{a0}' {
+                self.end_word();
+            } else {
+                if self.word.is_empty() {
+                    self.word = std::mem::take(&mut self.pending);
+                }
+                self.word.push(if c == '\u{a0}' { ' ' } else { c });
+            }
+        }
+    }
+
+    fn end_word(&mut self) {
+        if !self.word.is_empty() {
+            self.words.push(std::mem::take(&mut self.word));
+        }
+    }
+
+    /// Ends the current block, if anything is in it
+    fn end_block(&mut self) {
+        self.end_word();
+        // Bold carrying on into the next block is closed and opened again
+        if self.bold && !self.heading {
+            self.close_bold();
+            self.pending.push_str(BOLD_MARKER);
+        }
+        if self.words.is_empty() {
+            // A marker for an empty heading or item isn't left for the next
+            self.marker.clear();
+            return;
+        }
+        self.blocks.push(Block {
+            marker: std::mem::take(&mut self.marker),
+            quote: self.quote.clone(),
+            words: std::mem::take(&mut self.words),
+            preformatted: None,
+            gap: std::mem::take(&mut self.gap),
+        });
+    }
+
+    /// Ends the current block, leaving an empty line before the next
+    fn end_paragraph(&mut self) {
+        self.end_block();
+        self.gap = !self.blocks.is_empty();
+    }
+
+    fn close_bold(&mut self) {
+        if !self.word.is_empty() {
+            self.word.push_str(BOLD_MARKER);
+        } else if let Some(rest) = self.pending.strip_suffix(BOLD_MARKER) {
+            // Nothing was in bold after all
+            self.pending = rest.to_owned();
+        } else if let Some(last) = self.words.last_mut() {
+            last.push_str(BOLD_MARKER);
+        }
+    }
+
+    fn element(&mut self, element: &Element) {
+        let name = element.name();
+        if element.style.display_none || HEAD_ELEMENTS.contains(&name) {
+            return;
+        }
+        // Nested lists are part of the item they're in
+        let nested = matches!(name, "ul" | "ol") && !self.lists.is_empty();
+        let paragraph = PARAGRAPHS.contains(&name) && !nested;
+        if paragraph {
+            self.end_paragraph();
+        } else if LINES.contains(&name) {
+            self.end_block();
+        }
+
+        let quote = self.quote.len();
+        let heading = self.heading;
+        match name {
+            "br" => self.end_block(),
+            "img" => {
+                if let Some(alt) = element.tag.attributes.get("alt")
+                    && !alt.trim().is_empty()
+                {
+                    self.end_word();
+                    self.words.push(format!("[{}]", alt.trim()));
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap();
+                self.marker = format!("{} ", "#".repeat(level));
+                self.heading = true;
+            }
+            "blockquote" => self.quote.push_str("> "),
+            "ul" => self.lists.push(None),
+            "ol" => self.lists.push(Some(0)),
+            "li" => {
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                self.marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{indent}{number}. ")
+                    }
+                    _ => format!("{indent}{BULLET}"),
+                };
+            }
+            "tr" => self.cells = 0,
+            "td" | "th" => {
+                if self.cells > 0 {
+                    self.end_word();
+                    self.words.push("|".to_owned());
+                }
+                self.cells += 1;
+            }
+            "pre" => self.pre = Some(String::new()),
+            _ => {}
+        }
+
+        let bold = element.style.font_weight == FontWeight::Bold && !self.bold;
+        if bold && !self.heading {
+            if self.word.is_empty() {
+                self.pending.push_str(BOLD_MARKER);
+            } else {
+                self.word.push_str(BOLD_MARKER);
+            }
+        }
+        let outer_bold = self.bold;
+        self.bold |= bold;
+        self.nodes(&element.children, &element.style);
+        if bold && !self.heading {
+            self.close_bold();
+        }
+        self.bold = outer_bold;
+
+        if name == "pre"
+            && let Some(pre) = self.pre.take()
+        {
+            // The line break after <pre> doesn't count
+            let pre = pre.strip_prefix('\n').unwrap_or(&pre).trim_end();
+            if !pre.is_empty() {
+                self.blocks.push(Block {
+                    marker: String::new(),
+                    quote: self.quote.clone(),
+                    words: Vec::new(),
+                    preformatted: Some(pre.to_owned()),
+                    gap: std::mem::take(&mut self.gap),
+                });
+            }
+        }
+        match name {
+            "ul" | "ol" => {
+                self.lists.pop();
+            }
+            _ => {}
+        }
+        if paragraph {
+            self.end_paragraph();
+        } else if LINES.contains(&name) {
+            self.end_block();
+        }
+        self.quote.truncate(quote);
+        self.heading = heading;
+    }
+}
+
+/// Wraps `words` into lines at most `columns` characters wide where they
+/// fit, each starting with `first` or, after the first line, `rest`. Words
+/// too long for a line of their own go on one anyway.
+fn wrap(words: &[String], columns: usize, first: &str, rest: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = first.to_owned();
+    let mut empty = true;
+    for word in words {
+        let width = line.chars().count() + 1 + word.chars().count();
+        if !empty && width > columns {
+            lines.push(std::mem::replace(&mut line, rest.to_owned()));
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(word);
+        empty = false;
+    }
+    if !empty {
+        lines.push(line);
+    }
+    lines
+}
+
+impl Browser {
+    /// Writes out the page as plain text, wrapped at `columns` characters,
+    /// for reading in a terminal. Headings start with "#"s, bold text is
+    /// wrapped in "**", list items start with "- " or their number, and
+    /// quotes with "> ". Paragraphs are set apart by empty lines.
+    pub fn dump_text(&self, columns: usize, out: &mut impl Write) -> Result<(), std::io::Error> {
+        let mut shown = section(&self.document, "body");
+        if self.reader_mode {
+            shown = shown.map(main_content);
+        }
+        let mut dump = TextDump::default();
+        if let Some(shown) = shown {
+            dump.nodes(&shown.children, &shown.style);
+        }
+        dump.end_block();
+
+        for (index, block) in dump.blocks.iter().enumerate() {
+            if index > 0 && block.gap {
+                writeln!(out, "{}", block.quote.trim_end())?;
+            }
+            let lines = match &block.preformatted {
+                Some(text) => text
+                    .lines()
+                    .map(|line| format!("{}{}", block.quote, line))
+                    .collect(),
+                None => {
+                    let first = format!("{}{}", block.quote, block.marker);
+                    let indent = " ".repeat(block.marker.chars().count());
+                    let rest = format!("{}{}", block.quote, indent);
+                    wrap(&block.words, columns, &first, &rest)
+                }
+            };
+            for line in lines {
+                writeln!(out, "{}", line.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
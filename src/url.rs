@@ -0,0 +1,772 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::cache::validators;
+use crate::charset::decode;
+use crate::error::BrowserError;
+use crate::http::{
+    BodyKind, CONNECT_BUDGET, Connection, HttpClient, Progress, RequestBody, Response, Sni,
+    TlsError, last_header, read_chunked_body, too_large,
+};
+
+// How many redirects in a row are followed before giving up, in case of a loop
+const MAX_REDIRECTS: u32 = 10;
+// Bodies are read at most this many bytes at a time, so a page loading in the
+// background can be shown as it arrives
+const BODY_PIECE_SIZE: usize = 16 * 1024;
+
+/// Whether connecting might work if tried again in a moment, like when the
+/// server isn't listening yet or the network is briefly down
+fn is_transient(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::TimedOut
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    )
+}
+
+/// Sends `request` on `reader`'s connection and reads the status line of the
+/// response. None means the connection turned out to be closed before any of
+/// the response arrived.
+fn send_request<T: Read + Write>(
+    reader: &mut BufReader<T>,
+    request: &[u8],
+) -> Result<Option<String>, BrowserError> {
+    let mut line = String::new();
+    let mut exchange = || {
+        let stream = reader.get_mut();
+        stream.write_all(request)?;
+        stream.flush()?;
+        reader.read_line(&mut line)
+    };
+    match exchange() {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(line)),
+        Err(err) if line.is_empty() && is_closed(&err) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The error for a connection closed before any of the response arrived
+fn closed_early() -> BrowserError {
+    BrowserError::Network("Connection closed before a response was received".to_owned())
+}
+
+/// Whether an error sending or reading means the other end had closed the
+/// connection
+fn is_closed(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+/// The scheme an absolute reference starts with, like "https" in
+/// "https://example.org/" or "mailto" in "mailto:me@example.org". Only a
+/// colon before any '/', '?' or '#' can end a scheme.
+fn scheme_of(reference: &str) -> Option<&str> {
+    let end = reference.find([':', '/', '?', '#'])?;
+    let scheme = &reference[..end];
+    (reference[end..].starts_with(':')
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)))
+    .then_some(scheme)
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug)]
+pub struct URL {
+    pub(crate) scheme: String,
+    pub(crate) host: String,
+    pub(crate) path: String,
+    pub(crate) port: String,
+}
+
+impl std::fmt::Display for URL {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.scheme == "file" {
+            return write!(f, "file://{}", self.path);
+        }
+        if self.scheme == "about" {
+            return write!(f, "about:{}", self.path);
+        }
+        write!(f, "{}://{}", self.scheme, self.host_header())?;
+        let default_port = if self.scheme == "http" { "80" } else { "443" };
+        if self.port != default_port {
+            write!(f, ":{}", self.port)?;
+        }
+        write!(f, "{}", self.path)
+    }
+}
+
+impl URL {
+    pub fn new(url: &str) -> Result<Self, BrowserError> {
+        // Internal pages, like about:blank, are only a name
+        if let Some(page) = url.strip_prefix("about:") {
+            return Ok(Self {
+                scheme: "about".to_owned(),
+                host: String::new(),
+                path: page.to_owned(),
+                port: String::new(),
+            });
+        }
+
+        let Some((scheme, mut url)) = url
+            .split_once("://")
+            .map(|(scheme, url)| (scheme.to_owned(), url.to_owned()))
+        else {
+            // Schemes without a //, like mailto: or javascript:, are never
+            // supported. A colon followed by a number is a port instead.
+            if let Some((scheme, rest)) = url.split_once(':')
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                && !rest.starts_with(|c: char| c.is_ascii_digit())
+            {
+                return Err(BrowserError::Url(format!(
+                    "Unsupported URL scheme {}:",
+                    scheme
+                )));
+            }
+            return Err(BrowserError::Url(format!(
+                "{} has no scheme (http://, https:// or file://)",
+                url
+            )));
+        };
+
+        if scheme != "http" && scheme != "https" && scheme != "file" {
+            return Err(BrowserError::Url(format!(
+                "Unsupported URL scheme {}://",
+                scheme
+            )));
+        }
+
+        // TODO: Parse port into Option<u32>
+        let mut port = if scheme == "http" {
+            "80".to_owned()
+        } else {
+            "443".to_owned()
+        };
+
+        if !url.contains("/") {
+            url = format!("{}/", url);
+        }
+
+        let Some((mut host, url)) = url
+            .split_once("/")
+            .map(|(host, url)| (host.to_owned(), url.to_owned()))
+        else {
+            unreachable!()
+        };
+
+        if let Some(literal) = host.strip_prefix("[") {
+            // IPv6 literals are bracketed so that their colons aren't mistaken
+            // for the port separator
+            let Some((address, rest)) = literal.split_once("]") else {
+                return Err(BrowserError::Url(format!(
+                    "Unterminated IPv6 address in {}",
+                    host
+                )));
+            };
+            if let Some(literal_port) = rest.strip_prefix(":") {
+                port = literal_port.to_owned();
+            }
+            host = address.to_owned();
+        } else if host.contains(":") {
+            (host, port) = host
+                .split_once(":")
+                .map(|(host, port)| (host.to_owned(), port.to_owned()))
+                .unwrap();
+        }
+
+        let path = format!("/{}", url);
+        Ok(Self {
+            scheme,
+            host,
+            path,
+            port,
+        })
+    }
+
+    /// Requests the page, following redirects, and decodes it into text
+    pub fn request(&self, client: &mut HttpClient) -> Result<String, BrowserError> {
+        let response = self.request_following(client, "GET", None, MAX_REDIRECTS)?;
+        Ok(response.text())
+    }
+
+    /// POSTs `body` to the URL, following redirects, and decodes the page
+    /// that comes back into text
+    pub fn post(
+        &self,
+        client: &mut HttpClient,
+        content_type: &str,
+        body: &[u8],
+    ) -> Result<String, BrowserError> {
+        let body = RequestBody {
+            content_type,
+            data: body,
+        };
+        let response = self.request_following(client, "POST", Some(&body), MAX_REDIRECTS)?;
+        Ok(response.text())
+    }
+
+    /// Requests the page, or POSTs `body` to it if there is one, following
+    /// redirects
+    pub(crate) fn request_page(
+        &self,
+        client: &mut HttpClient,
+        body: Option<&RequestBody>,
+    ) -> Result<Response, BrowserError> {
+        let method = if body.is_some() { "POST" } else { "GET" };
+        self.request_following(client, method, body, MAX_REDIRECTS)
+    }
+
+    /// Requests the URL, following up to `redirects` more redirects. Each
+    /// Location is resolved against the URL that redirected to it.
+    ///
+    /// 307 and 308 ask for the request to be repeated as it was, body and
+    /// all. 303 asks for the result to be fetched with a GET, and so, as
+    /// browsers have long done, do 301 and 302 after a POST. Other methods
+    /// are kept through a 301 or 302.
+    fn request_following(
+        &self,
+        client: &mut HttpClient,
+        method: &str,
+        body: Option<&RequestBody>,
+        redirects: u32,
+    ) -> Result<Response, BrowserError> {
+        let response = self.send(client, method, body)?;
+        let Some(location) = response
+            .header("location")
+            .filter(|_| (300..400).contains(&response.status))
+        else {
+            return Ok(response);
+        };
+
+        if redirects == 0 {
+            return Err(BrowserError::Network("Too many redirects".to_owned()));
+        }
+        // Only follow redirects to the web, never to local files
+        let Some(next) = self.resolve(location).filter(URL::is_web) else {
+            return Err(BrowserError::Network(format!(
+                "Unsupported redirect to {}",
+                location
+            )));
+        };
+        let (method, body) = match response.status {
+            303 => ("GET", None),
+            301 | 302 if method == "POST" => ("GET", None),
+            _ => (method, body),
+        };
+        next.request_following(client, method, body, redirects - 1)
+    }
+
+    /// Sends a single request with `method`, without following redirects.
+    /// The response to a HEAD request has its headers but no body.
+    pub fn request_method(
+        &self,
+        client: &mut HttpClient,
+        method: &str,
+    ) -> Result<Response, BrowserError> {
+        self.send(client, method, None)
+    }
+
+    /// Sends a single request, once the client's interceptor, if it has
+    /// one, has seen it
+    pub(crate) fn send(
+        &self,
+        client: &mut HttpClient,
+        method: &str,
+        body: Option<&RequestBody>,
+    ) -> Result<Response, BrowserError> {
+        let mut url = self.clone();
+        let mut headers = client.headers.clone();
+        if let Some(interceptor) = &client.interceptor
+            && let Some(response) = interceptor.intercept(method, &mut url, &mut headers)
+        {
+            client.log(1, "* ", &format!("Intercepted {} {}", method, url));
+            return Ok(response);
+        }
+        if method != "GET" || body.is_some() {
+            return url.send_with(client, method, &headers, body);
+        }
+
+        // A page fetched before with a validator is only sent again if it's
+        // changed since
+        let key = url.to_string();
+        let cached = client.cache.lock().unwrap().get(&key);
+        if let Some(cached) = &cached {
+            for (name, value) in validators(cached) {
+                if !headers
+                    .iter()
+                    .any(|(set, _)| set.eq_ignore_ascii_case(&name))
+                {
+                    headers.push((name, value));
+                }
+            }
+        }
+        let response = url.send_with(client, method, &headers, body)?;
+        if let Some(cached) = cached
+            && response.status == 304
+        {
+            client.log(1, "* ", &format!("Using the cached copy of {}", url));
+            if let Some(progress) = &client.progress {
+                let _ = progress.send(Progress::Head(cached.headers.clone()));
+                let _ = progress.send(Progress::Body(cached.body.clone()));
+            }
+            return Ok(cached);
+        }
+        client.cache.lock().unwrap().store(key, &response);
+        Ok(response)
+    }
+
+    fn send_with(
+        &self,
+        client: &mut HttpClient,
+        method: &str,
+        headers: &[(String, String)],
+        body: Option<&RequestBody>,
+    ) -> Result<Response, BrowserError> {
+        let mut head = format!("{} {} HTTP/1.1\r\n", method, self.path);
+        if !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("host"))
+        {
+            head.push_str(&format!("Host: {}\r\n", self.host_header()));
+        }
+        for (name, value) in headers {
+            // A body's own headers are the only ones describing it
+            let describes_body = ["content-type", "content-length"]
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header));
+            if body.is_some() && describes_body {
+                continue;
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if let Some(body) = body {
+            head.push_str(&format!("Content-Type: {}\r\n", body.content_type));
+            head.push_str(&format!("Content-Length: {}\r\n", body.data.len()));
+        }
+        head.push_str("\r\n");
+        let mut request = head.clone().into_bytes();
+        if let Some(body) = body {
+            request.extend_from_slice(body.data);
+        }
+        let start = Instant::now();
+
+        // Servers are free to close idle connections whenever they like, so
+        // one found closed before any of the response arrives is replaced
+        // with a fresh one. Only requests that are safe to send twice are
+        // sent again, as the server may have acted on the first.
+        let mut reused = None;
+        if let Some(mut connection) = client.take_idle(self) {
+            reused = match send_request(&mut connection, &request)? {
+                Some(line) => Some((connection, line)),
+                None if matches!(method, "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE") => None,
+                None => return Err(closed_early()),
+            };
+        }
+        let (mut connection, status_line) = match reused {
+            Some(reused) => reused,
+            None => {
+                let connect_start = Instant::now();
+                let mut connection = BufReader::new(self.connect_retrying(client)?);
+                client.log(
+                    2,
+                    "* ",
+                    &format!(
+                        "Connected to {}:{} in {:?}",
+                        self.host_header(),
+                        self.port,
+                        connect_start.elapsed()
+                    ),
+                );
+                let status_line =
+                    send_request(&mut connection, &request)?.ok_or_else(closed_early)?;
+                (connection, status_line)
+            }
+        };
+        let (response, keep_alive) = self.read_http_response(
+            &mut connection,
+            status_line,
+            method,
+            client.max_body_size,
+            client.progress.as_ref(),
+        )?;
+        if keep_alive {
+            client.release(self, connection);
+        }
+
+        self.log_exchange(client, &head, &response, start);
+        Ok(response)
+    }
+
+    /// Logs a request sent for this URL and the head of its response, in the
+    /// style of curl's verbose output
+    fn log_exchange(
+        &self,
+        client: &HttpClient,
+        request: &str,
+        response: &Response,
+        start: Instant,
+    ) {
+        client.log(1, "> ", request);
+        client.log(1, "< ", &response.head);
+        client.log(
+            2,
+            "* ",
+            &format!("{} bytes in {:?}", response.body.len(), start.elapsed()),
+        );
+    }
+
+    /// Whether the URL is on the web, rather than a local file or an
+    /// internal page
+    pub(crate) fn is_web(&self) -> bool {
+        self.scheme == "http" || self.scheme == "https"
+    }
+
+    pub(crate) fn host_header(&self) -> String {
+        if self.host.contains(":") {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+
+    /// Connects to the server, trying again if the connection is refused or
+    /// times out, up to `client.connect_retries` more times with the wait
+    /// before each doubling. Other failures, like a bad certificate, aren't
+    /// retried, and nor is anything once connecting would run past
+    /// CONNECT_BUDGET.
+    fn connect_retrying(&self, client: &HttpClient) -> Result<Connection, BrowserError> {
+        let start = Instant::now();
+        let mut delay = client.retry_delay;
+        let mut retries = 0;
+        loop {
+            match self.connect(client.tls_config.clone(), &client.sni) {
+                Err(BrowserError::Io(err))
+                    if retries < client.connect_retries
+                        && is_transient(&err)
+                        && start.elapsed() + delay <= CONNECT_BUDGET =>
+                {
+                    client.log(
+                        2,
+                        "* ",
+                        &format!(
+                            "Couldn't connect to {}:{} ({}), trying again in {:?}",
+                            self.host_header(),
+                            self.port,
+                            err,
+                            delay
+                        ),
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub(crate) fn connect(
+        &self,
+        mut tls_config: Arc<rustls::ClientConfig>,
+        sni: &Sni,
+    ) -> Result<Connection, BrowserError> {
+        // Validate the TLS server name up front so a bad hostname is reported
+        // as such instead of as a DNS lookup failure
+        let server_name = if self.scheme == "https" {
+            let name = match sni {
+                Sni::Name(name) => name.clone(),
+                Sni::Host | Sni::Off => self.host.clone(),
+            };
+            Some(
+                rustls::pki_types::ServerName::try_from(name.clone())
+                    .map_err(|_| TlsError::InvalidName(name))?,
+            )
+        } else {
+            None
+        };
+        if *sni == Sni::Off && tls_config.enable_sni {
+            Arc::make_mut(&mut tls_config).enable_sni = false;
+        }
+
+        let port: u16 = self
+            .port
+            .parse()
+            .map_err(|_| BrowserError::Url(format!("Invalid port {}", self.port)))?;
+        // Resolving a (host, port) pair rather than a "host:port" string lets
+        // IPv6 literals through without re-adding brackets
+        let address = (self.host.as_str(), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| BrowserError::Network(format!("No address found for {}", self.host)))?;
+        let domain = if address.is_ipv4() {
+            Domain::IPV4
+        } else {
+            Domain::IPV6
+        };
+
+        let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+        socket.connect_timeout(&address.into(), Duration::from_secs(3))?;
+        if let Some(server_name) = server_name {
+            let sess = rustls::ClientConnection::new(tls_config, server_name)
+                .map_err(|err| TlsError::Handshake(self.host.clone(), err))?;
+            let mut tls = rustls::StreamOwned::new(sess, socket);
+
+            // Drive the handshake now so certificate problems surface here
+            // rather than as a generic read error halfway through a response
+            tls.conn.complete_io(&mut tls.sock).map_err(|err| {
+                match err
+                    .get_ref()
+                    .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+                {
+                    Some(rustls::Error::InvalidCertificate(cert_err)) => {
+                        TlsError::Certificate(self.host.clone(), cert_err.clone()).into()
+                    }
+                    Some(tls_err) => TlsError::Handshake(self.host.clone(), tls_err.clone()).into(),
+                    None => BrowserError::Io(err),
+                }
+            })?;
+            Ok(Connection::Tls(Box::new(tls)))
+        } else {
+            Ok(Connection::Plain(socket))
+        }
+    }
+
+    /// Reads the rest of the response whose status line is `line`, returning
+    /// it along with whether the connection can be reused for another
+    /// request. Bodies longer than `max_body_size` are an error. Unless the
+    /// response is a redirect, its body is also sent to `progress` piece by
+    /// piece as it's read.
+    pub(crate) fn read_http_response<T: Read + Write>(
+        &self,
+        reader: &mut BufReader<T>,
+        mut line: String,
+        method: &str,
+        max_body_size: usize,
+        progress: Option<&Sender<Progress>>,
+    ) -> Result<(Response, bool), BrowserError> {
+        let mut head = line.clone();
+        // The reason phrase after the status code is optional
+        let mut statusline = line.split_whitespace();
+        let version = statusline.next().unwrap_or_default().to_owned();
+        let status: u16 = statusline
+            .next()
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| BrowserError::Network("Invalid status line".to_owned()))?;
+        line.clear();
+
+        let mut response_headers: HashMap<String, Vec<String>> = HashMap::new();
+        loop {
+            if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                break;
+            }
+            head.push_str(&line);
+            let temp_line = line.clone();
+            if let Some((header, value)) = temp_line.split_once(":") {
+                response_headers
+                    .entry(header.trim().to_lowercase())
+                    .or_default()
+                    .push(value.trim().to_owned());
+            }
+            line.clear();
+        }
+
+        let header = |name| last_header(&response_headers, name);
+        if let Some(encoding) = header("content-encoding") {
+            return Err(BrowserError::Encoding(format!(
+                "Unsupported Content-Encoding {}",
+                encoding
+            )));
+        }
+
+        // A redirect's body isn't the page, so only the final response is
+        // shown as it arrives
+        let redirect = (300..400).contains(&status) && response_headers.contains_key("location");
+        // Nor is a 304's, which is only the headers of a cached copy
+        let progress = progress.filter(|_| !redirect && status != 304);
+        if let Some(progress) = progress {
+            let _ = progress.send(Progress::Head(response_headers.clone()));
+        }
+        let mut report = |piece: &[u8]| {
+            if let Some(progress) = progress {
+                let _ = progress.send(Progress::Body(piece.to_vec()));
+            }
+        };
+
+        let mut keep_alive = version.trim() == "HTTP/1.1"
+            && header("connection").is_none_or(|value| !value.eq_ignore_ascii_case("close"));
+
+        let body =
+            if method == "HEAD" || (100..200).contains(&status) || status == 204 || status == 304 {
+                // These never have a body, whatever the headers say
+                Vec::new()
+            } else if header("transfer-encoding")
+                .is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+            {
+                read_chunked_body(reader, max_body_size, &mut report)?
+            } else if let Some(length) = header("content-length") {
+                let length: usize = length
+                    .parse()
+                    .map_err(|_| BrowserError::Network("Invalid Content-Length".to_owned()))?;
+                // Refuse before allocating anything for it
+                if length > max_body_size {
+                    return Err(too_large());
+                }
+                let mut body = vec![0; length];
+                let mut read = 0;
+                while read < length {
+                    let end = std::cmp::min(read + BODY_PIECE_SIZE, length);
+                    let n = reader.read(&mut body[read..end])?;
+                    if n == 0 {
+                        return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+                    }
+                    report(&body[read..read + n]);
+                    read += n;
+                }
+                body
+            } else {
+                // Without a length the body only ends when the server closes the
+                // connection, so it can't be reused
+                keep_alive = false;
+                let mut body = Vec::new();
+                let mut piece = vec![0; BODY_PIECE_SIZE];
+                loop {
+                    let n = reader.read(&mut piece)?;
+                    if n == 0 {
+                        break;
+                    }
+                    if body.len() + n > max_body_size {
+                        return Err(too_large());
+                    }
+                    body.extend_from_slice(&piece[..n]);
+                    report(&piece[..n]);
+                }
+                body
+            };
+
+        let response = Response {
+            status,
+            headers: response_headers,
+            body,
+            head,
+        };
+        Ok((response, keep_alive))
+    }
+
+    /// How to show the file, going by its extension. Files with an extension
+    /// that isn't HTML's are shown as plain text, and files without one are
+    /// taken to be HTML.
+    pub(crate) fn file_kind(&self) -> BodyKind {
+        let extension = std::path::Path::new(&self.path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            None | Some("html" | "htm" | "xhtml") => BodyKind::Html,
+            Some("json") => BodyKind::Json,
+            Some(_) => BodyKind::PlainText,
+        }
+    }
+
+    pub(crate) fn load_file(&self) -> Result<String, BrowserError> {
+        Ok(decode(&fs::read(&self.path)?, None))
+    }
+
+    /// Fetches a resource a page uses, like an image or stylesheet. A local
+    /// file comes back as a response without headers.
+    pub(crate) fn fetch(&self, client: &mut HttpClient) -> Result<Response, BrowserError> {
+        match self.scheme.as_ref() {
+            "http" | "https" => self.request_following(client, "GET", None, MAX_REDIRECTS),
+            "file" => Ok(Response::new(200, HashMap::new(), fs::read(&self.path)?)),
+            "about" => Err(BrowserError::Url(format!("Can't fetch {}", self))),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resolves a link found on this page, such as an <img> src, against it.
+    /// Returns `None` for links using a scheme we can't load.
+    pub(crate) fn resolve(&self, reference: &str) -> Option<URL> {
+        let reference = reference.split('#').next().unwrap_or_default().trim();
+        // Schemes without a //, like data: or javascript:, are never supported
+        if let Some(scheme) = scheme_of(reference) {
+            return reference[scheme.len()..]
+                .starts_with("://")
+                .then(|| URL::new(reference).ok())
+                .flatten();
+        }
+        // about: pages have no path for anything to be relative to
+        if self.scheme == "about" {
+            return None;
+        }
+        if let Some(rest) = reference.strip_prefix("//") {
+            return URL::new(&format!("{}://{}", self.scheme, rest)).ok();
+        }
+        let base = self.path.split('?').next().unwrap_or_default();
+        let path = if reference.is_empty() {
+            // Only a fragment, which is this same page
+            self.path.clone()
+        } else if reference.starts_with('?') {
+            // The same path with another query
+            format!("{}{}", base, reference)
+        } else if reference.starts_with('/') {
+            reference.to_owned()
+        } else {
+            let directory = &base[..base.rfind('/').map_or(0, |index| index + 1)];
+            format!("{}{}", directory, reference)
+        };
+
+        // Collapse "." and ".." segments so the server sees a clean path
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+            None => (path, None),
+        };
+        let mut segments: Vec<&str> = Vec::new();
+        let mut parts = path.split('/').skip(1).peekable();
+        while let Some(part) = parts.next() {
+            let last = parts.peek().is_none();
+            match part {
+                "." | ".." => {
+                    if part == ".." {
+                        segments.pop();
+                    }
+                    if last {
+                        segments.push("");
+                    }
+                }
+                _ => segments.push(part),
+            }
+        }
+        let mut path = format!("/{}", segments.join("/"));
+        if let Some(query) = query {
+            path = format!("{}?{}", path, query);
+        }
+
+        Some(URL {
+            scheme: self.scheme.clone(),
+            host: self.host.clone(),
+            path,
+            port: self.port.clone(),
+        })
+    }
+}
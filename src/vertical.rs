@@ -0,0 +1,136 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use ab_glyph::{Font, FontRef, ScaleFont};
+use rustybuzz::{Direction, Face, GlyphBuffer, UnicodeBuffer, shape};
+
+use crate::VSTEP;
+use crate::layout::{BLOCK_SPACING, DisplayItem, Layout, LineItem};
+
+/// Where vertical text (writing-mode: vertical-rl) is up to. Columns of text
+/// run top to bottom and are placed right to left. Once they reach the left
+/// of the text column, a new tier of columns starts below, as newspapers set
+/// vertical text, so the page still scrolls down rather than sideways.
+pub(crate) struct Vertical {
+    // The top of the current tier, and how long each column in it is
+    pub(crate) top: u32,
+    pub(crate) length: u32,
+}
+
+impl Vertical {
+    /// Tiers as tall as a window `height` px high, so each fills the window
+    pub(crate) fn new(height: u32) -> Self {
+        Self {
+            top: VSTEP,
+            length: height.saturating_sub(2 * VSTEP).max(1),
+        }
+    }
+}
+
+impl Layout {
+    /// Stacks a word's characters upright down the current column, moving on
+    /// to the next column when this one is full. As in CJK text, a word can
+    /// break between any two of its characters.
+    pub(crate) fn vertical_word(
+        &mut self,
+        (source, range): (Rc<str>, Range<usize>),
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+        space_font: &'static FontRef<'static>,
+    ) {
+        for (offset, c) in source[range.clone()].char_indices() {
+            let start = range.start + offset;
+            let piece = start..start + c.len_utf8();
+            let (glyphs, advance) = self.shape_vertical(&source[piece.clone()], font, face);
+            let bottom = self.vertical.as_ref().map_or(0, |v| v.top + v.length);
+            if !self.line.is_empty() && self.cursor_y + advance > bottom {
+                self.flush();
+            }
+
+            let mut item = self.text_item(glyphs, font, face, source.clone(), piece);
+            item.y = self.cursor_y;
+            // Columns are as wide as lines of the same text are tall, and
+            // centered once the widest thing in them is known
+            let scaled_font = font.as_scaled(item.scale());
+            let width = scaled_font.height() * self.line_height;
+            self.line.push(LineItem {
+                item: DisplayItem::Text(item),
+                ascent: width / 2.0,
+                descent: width / 2.0,
+                shift: 0,
+                hidden: self.style.hidden,
+            });
+            self.cursor_y += advance;
+        }
+        self.cursor_y += self.space_width(space_font) as u32;
+    }
+
+    /// Finishes the current column: centers everything in it on one axis,
+    /// then moves left to start the next column, or down to a new tier if
+    /// there's no room left for it
+    pub(crate) fn flush_column(&mut self) {
+        if self.line.is_empty() {
+            return;
+        }
+        let width = self
+            .line
+            .iter()
+            .map(|line_item| line_item.ascent + line_item.descent)
+            .fold(0.0, f32::max) as u32;
+
+        let (left, right) = (self.left(), self.right());
+        let Some(vertical) = self.vertical.as_mut() else {
+            return;
+        };
+        let mut shift = 0;
+        if self.cursor_x < left + width && self.cursor_x != right {
+            shift = vertical.length + BLOCK_SPACING;
+            vertical.top += shift;
+            self.cursor_x = right;
+        }
+
+        let axis = self.cursor_x.saturating_sub(width / 2);
+        for LineItem {
+            mut item, hidden, ..
+        } in self.line.drain(..)
+        {
+            if hidden {
+                continue;
+            }
+            if let DisplayItem::Text(text) = &mut item {
+                text.x = axis;
+                text.y += shift;
+            }
+            self.display_list.push(item);
+        }
+
+        self.cursor_x = self.cursor_x.saturating_sub(width);
+        self.cursor_y = vertical.top;
+    }
+
+    /// Shapes `text` top to bottom at the current font size, returning the
+    /// glyphs and how far down they advance in px
+    pub(crate) fn shape_vertical(
+        &self,
+        text: &str,
+        font: &'static FontRef<'static>,
+        face: &'static Face<'static>,
+    ) -> (GlyphBuffer, u32) {
+        // Font size should be set in pt, not px
+        let scale = font.pt_to_px_scale(self.font_size.0 as f32).unwrap();
+        let scale_factor = scale.y / font.height_unscaled();
+
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.set_direction(Direction::TopToBottom);
+        let glyph_buffer = shape(face, &[], buffer);
+
+        // Advances downwards are negative, as y goes up in font units
+        let advance: i32 = glyph_buffer
+            .glyph_positions()
+            .iter()
+            .map(|pos| -pos.y_advance)
+            .sum();
+        (glyph_buffer, (advance as f32 * scale_factor) as u32)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::browser::Browser;
+
+// The zoom levels stepped through from the keyboard, in percent
+pub(crate) const ZOOM_LEVELS: &[u32] =
+    &[50, 67, 75, 80, 90, 100, 110, 125, 150, 175, 200, 250, 300];
+pub(crate) const DEFAULT_ZOOM: u32 = 100;
+
+impl Browser {
+    /// How far the page is zoomed, in percent
+    pub fn zoom(&self) -> u32 {
+        self.zoom
+    }
+
+    /// Zooms the page to `percent` of its usual text size, and remembers it
+    /// for the page's host, so other pages there and later visits start out
+    /// zoomed the same
+    pub fn set_zoom(&mut self, percent: u32) {
+        let percent = percent.clamp(ZOOM_LEVELS[0], ZOOM_LEVELS[ZOOM_LEVELS.len() - 1]);
+        if let Some(host) = self.zoom_host() {
+            let mut site_zoom = self.site_zoom.borrow_mut();
+            if percent == DEFAULT_ZOOM {
+                site_zoom.remove(&host);
+            } else {
+                site_zoom.insert(host, percent);
+            }
+        }
+        if percent != self.zoom {
+            self.zoom = percent;
+            self.relayout();
+        }
+    }
+
+    /// Zooms in to the next of the preset levels
+    pub fn zoom_in(&mut self) {
+        if let Some(&next) = ZOOM_LEVELS.iter().find(|&&level| level > self.zoom) {
+            self.set_zoom(next);
+        }
+    }
+
+    /// Zooms out to the previous of the preset levels
+    pub fn zoom_out(&mut self) {
+        if let Some(&previous) = ZOOM_LEVELS.iter().rev().find(|&&level| level < self.zoom) {
+            self.set_zoom(previous);
+        }
+    }
+
+    pub fn reset_zoom(&mut self) {
+        self.set_zoom(DEFAULT_ZOOM);
+    }
+
+    /// Goes back to the zoom last chosen for the current page's host, or the
+    /// default for hosts never zoomed, ready for the page to be laid out
+    pub(crate) fn restore_zoom(&mut self) {
+        self.zoom = self
+            .zoom_host()
+            .and_then(|host| self.site_zoom.borrow().get(&host).copied())
+            .unwrap_or(DEFAULT_ZOOM);
+    }
+
+    /// What zoom is remembered by for the current page. Local files and
+    /// pages without a URL share no host, so aren't remembered.
+    fn zoom_host(&self) -> Option<String> {
+        let url = self.current_url.as_ref()?;
+        (!url.host.is_empty()).then(|| url.host.to_ascii_lowercase())
+    }
+}
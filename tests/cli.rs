@@ -0,0 +1,76 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn reads_a_page_piped_in_on_stdin() {
+    let mut browser = Command::new(env!("CARGO_BIN_EXE_Browser"))
+        .args(["--dump-layout", "--no-system-fonts", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    browser
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"<p>Piped <b>in</b></p>")
+        .unwrap();
+    let output = browser.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let layout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = layout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(r#"text="Piped""#));
+    assert!(lines[1].contains("style=bold") && lines[1].ends_with(r#"text="in""#));
+}
+
+#[test]
+fn prints_a_page_as_wrapped_text() {
+    let mut browser = Command::new(env!("CARGO_BIN_EXE_Browser"))
+        .args(["--text", "--columns", "20", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    browser
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"<h1>A title</h1><p>The first paragraph runs on <b>long enough</b> to wrap.\
+              <p>Second<ul><li>One item that wraps too<li>Two</ul>",
+        )
+        .unwrap();
+    let output = browser.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# A title\n\
+         \n\
+         The first paragraph\n\
+         runs on **long\n\
+         enough** to wrap.\n\
+         \n\
+         Second\n\
+         \n\
+         - One item that\n  \
+           wraps too\n\
+         - Two\n"
+    );
+}
+
+#[test]
+fn reports_invalid_headers() {
+    let output = Command::new(env!("CARGO_BIN_EXE_Browser"))
+        .args(["--header", "no colon here", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Invalid header \"no colon here\"\n"
+    );
+}
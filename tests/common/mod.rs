@@ -0,0 +1,164 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// What a `TestServer` has seen so far
+#[derive(Default)]
+pub struct Seen {
+    // The request line and headers of each request, in order, followed by a
+    // blank line and the body if it has one
+    pub requests: Vec<String>,
+    pub connections: usize,
+}
+
+/// A local HTTP server that answers each request it receives with the next of
+/// a list of canned responses, then stops once they've all been sent
+pub struct TestServer {
+    pub port: u16,
+    seen: Arc<Mutex<Seen>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl TestServer {
+    pub fn new(responses: &[&str]) -> Self {
+        let responses: Vec<&[u8]> = responses.iter().map(|r| r.as_bytes()).collect();
+        Self::with_bytes(&responses)
+    }
+
+    /// Like `new`, for responses whose bodies aren't UTF-8
+    pub fn with_bytes(responses: &[&[u8]]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let mut responses: Vec<Vec<u8>> = responses.iter().rev().map(|r| r.to_vec()).collect();
+
+        let server_seen = seen.clone();
+        let handle = thread::spawn(move || {
+            while !responses.is_empty() {
+                let (stream, _) = listener.accept().unwrap();
+                server_seen.lock().unwrap().connections += 1;
+                let mut reader = BufReader::new(stream);
+
+                // Keep answering on this connection until the client hangs up
+                // or a response that can't be followed by another is sent
+                while let Some(response) = responses.last() {
+                    let Some(request) = read_request(&mut reader) else {
+                        break;
+                    };
+                    server_seen.lock().unwrap().requests.push(request);
+
+                    reader.get_mut().write_all(response).unwrap();
+                    let response = String::from_utf8_lossy(response);
+                    let reusable = !response.starts_with("HTTP/1.0")
+                        && (response.contains("Content-Length")
+                            || response.contains("Transfer-Encoding: chunked"))
+                        && !response.contains("Connection: close");
+                    responses.pop();
+                    if !reusable {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            port,
+            seen,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Waits for every response to have been sent, then returns what the
+    /// server saw
+    pub fn finish(mut self) -> Seen {
+        self.handle.take().unwrap().join().unwrap();
+        std::mem::take(&mut self.seen.lock().unwrap())
+    }
+}
+
+/// A local HTTP server that answers every request with the same response
+/// after a delay, serving each connection on a thread of its own, so that
+/// requests made at once are answered at once. It runs until the test ends.
+// Only some of the test files sharing this module use it
+#[allow(dead_code)]
+pub struct SlowServer {
+    pub port: u16,
+    // How many requests are being answered right now, and the most there
+    // have been at any one time
+    load: Arc<Mutex<(usize, usize)>>,
+}
+
+#[allow(dead_code)]
+impl SlowServer {
+    pub fn new(response: Vec<u8>, delay: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let load = Arc::new(Mutex::new((0, 0)));
+
+        let server_load = load.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (response, load) = (response.clone(), server_load.clone());
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.unwrap());
+                    while read_request(&mut reader).is_some() {
+                        {
+                            let mut load = load.lock().unwrap();
+                            load.0 += 1;
+                            load.1 = load.1.max(load.0);
+                        }
+                        thread::sleep(delay);
+                        load.lock().unwrap().0 -= 1;
+                        if reader.get_mut().write_all(&response).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        Self { port, load }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// The most requests that were ever being answered at once
+    pub fn peak(&self) -> usize {
+        self.load.lock().unwrap().1
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<String> {
+    let mut request = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        if line == "\r\n" {
+            break;
+        }
+        request.push_str(&line);
+    }
+
+    // A body follows the blank line after the headers
+    let length = request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse::<usize>().ok())?
+    });
+    if let Some(length) = length {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+        request.push_str("\r\n");
+        request.push_str(&String::from_utf8_lossy(&body));
+    }
+    Some(request)
+}
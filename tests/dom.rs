@@ -0,0 +1,223 @@
+use browser::{Element, Node, Style, Tag, body, head, lex, parse};
+
+fn text(text: &str) -> Node {
+    Node::Text(text.into())
+}
+
+fn element(tag: &str, children: Vec<Node>) -> Node {
+    Node::Element(Element {
+        tag: Tag::parse(tag),
+        children,
+        style: Style::default(),
+    })
+}
+
+/// The body of `html`, parsed
+fn tree(html: &str) -> Vec<Node> {
+    body(&parse(lex(html))).to_vec()
+}
+
+#[test]
+fn nests_elements() {
+    assert_eq!(
+        tree("<div>Hello <b>bold <i>both</i></b>!</div>"),
+        [element(
+            "div",
+            vec![
+                text("Hello "),
+                element("b", vec![text("bold "), element("i", vec![text("both")])]),
+                text("!"),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn closes_a_paragraph_at_the_next_block() {
+    assert_eq!(
+        tree("<p>One<p>Two<div>Three</div>"),
+        [
+            element("p", vec![text("One")]),
+            element("p", vec![text("Two")]),
+            element("div", vec![text("Three")]),
+        ]
+    );
+}
+
+#[test]
+fn closes_a_list_item_at_the_next_one() {
+    assert_eq!(
+        tree("<ul><li>One<li>Two<ul><li>Inner</ul><li>Three</ul>"),
+        [element(
+            "ul",
+            vec![
+                element("li", vec![text("One")]),
+                element(
+                    "li",
+                    vec![
+                        text("Two"),
+                        element("ul", vec![element("li", vec![text("Inner")])]),
+                    ]
+                ),
+                element("li", vec![text("Three")]),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn closes_elements_left_open_at_the_end() {
+    assert_eq!(
+        tree("<div><p>Unclosed <b>bold"),
+        [element(
+            "div",
+            vec![element(
+                "p",
+                vec![text("Unclosed "), element("b", vec![text("bold")])]
+            )]
+        )]
+    );
+}
+
+#[test]
+fn closes_what_is_open_inside_a_closed_element() {
+    assert_eq!(
+        tree("<div><span>Inside</div>Outside"),
+        [
+            element("div", vec![element("span", vec![text("Inside")])]),
+            text("Outside"),
+        ]
+    );
+}
+
+#[test]
+fn carries_misnested_formatting_on() {
+    assert_eq!(
+        tree("<b>bold <i>both</b> italic</i>"),
+        [
+            element("b", vec![text("bold "), element("i", vec![text("both")])]),
+            element("i", vec![text(" italic")]),
+        ]
+    );
+}
+
+#[test]
+fn puts_nothing_inside_void_elements() {
+    assert_eq!(
+        tree("<p>Line<br>Next <img src=a.png alt=A> after</p>"),
+        [element(
+            "p",
+            vec![
+                text("Line"),
+                element("br", vec![]),
+                text("Next "),
+                element("img src=a.png alt=A", vec![]),
+                text(" after"),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn closes_table_rows_and_cells() {
+    assert_eq!(
+        tree("<table><tr><td>A<td><b>B<tr><td>C</table>After"),
+        [
+            element(
+                "table",
+                vec![
+                    element(
+                        "tr",
+                        vec![
+                            element("td", vec![text("A")]),
+                            element("td", vec![element("b", vec![text("B")])]),
+                        ]
+                    ),
+                    element("tr", vec![element("td", vec![text("C")])]),
+                ]
+            ),
+            text("After"),
+        ]
+    );
+}
+
+#[test]
+fn drops_stray_closing_tags() {
+    assert_eq!(
+        tree("<div>One</span> two</div></div>"),
+        [element("div", vec![text("One"), text(" two")])]
+    );
+}
+
+#[test]
+fn closes_options_and_table_sections() {
+    assert_eq!(
+        tree("<select><option>A<optgroup><option>B<option>C</select>"),
+        [element(
+            "select",
+            vec![
+                element("option", vec![text("A")]),
+                element(
+                    "optgroup",
+                    vec![
+                        element("option", vec![text("B")]),
+                        element("option", vec![text("C")]),
+                    ]
+                ),
+            ]
+        )]
+    );
+    assert_eq!(
+        tree("<table><thead><tr><th>H<tbody><tr><td>D</table>"),
+        [element(
+            "table",
+            vec![
+                element(
+                    "thead",
+                    vec![element("tr", vec![element("th", vec![text("H")])])]
+                ),
+                element(
+                    "tbody",
+                    vec![element("tr", vec![element("td", vec![text("D")])])]
+                ),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn splits_the_head_from_the_body() {
+    let document = parse(lex(
+        "<html lang=en><head><title>Title</title>stray</head><body><p>Shown</body></html>",
+    ));
+    assert_eq!(
+        head(&document),
+        [element("title", vec![text("Title")]), text("stray")]
+    );
+    assert_eq!(body(&document), [element("p", vec![text("Shown")])]);
+    assert!(
+        matches!(&document[..], [Node::Element(html)] if html.tag == Tag::parse("html lang=en"))
+    );
+}
+
+#[test]
+fn fills_in_a_missing_head_and_body() {
+    let document = parse(lex("\n<title>Title</title><meta charset=utf-8><p>Shown"));
+    assert_eq!(
+        head(&document),
+        [
+            element("title", vec![text("Title")]),
+            element("meta charset=utf-8", vec![]),
+        ]
+    );
+    assert_eq!(body(&document), [element("p", vec![text("Shown")])]);
+
+    // Text after metadata starts the body, unless it's inside a <head>
+    let document = parse(lex("<title>Title</title>Shown"));
+    assert_eq!(head(&document), [element("title", vec![text("Title")])]);
+    assert_eq!(body(&document), [text("Shown")]);
+
+    let document = parse(lex("Just text"));
+    assert_eq!(head(&document), []);
+    assert_eq!(body(&document), [text("Just text")]);
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use browser::{Browser, HttpClient, URL};
+use common::TestServer;
+
+fn ok(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// The top left corner of the input named `name`, from the layout dump
+fn input_at(browser: &Browser, name: &str) -> (f32, f32) {
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let line = out
+        .lines()
+        .find(|line| line.contains(" input=") && line.contains(&format!("name={:?}", name)))
+        .unwrap_or_else(|| panic!("no input {:?} in {}", name, out));
+    let field = |field: &str| -> f32 {
+        let start = line.find(&format!("{}=", field)).unwrap() + field.len() + 1;
+        line[start..].split(' ').next().unwrap().parse().unwrap()
+    };
+    (field("x"), field("y"))
+}
+
+/// The request path of each request the server saw
+fn paths(server: TestServer) -> Vec<String> {
+    server
+        .finish()
+        .requests
+        .iter()
+        .map(|request| request.split(' ').nth(1).unwrap().to_owned())
+        .collect()
+}
+
+const FORM: &str = "<form action=\"search\">\
+    <input name=q value=\"rust lang\"> <input name=\"x y\" value=\"a&amp;b=c/é+\">\
+    <input value=unnamed> <input type=hidden name=h value=hidden>\
+    <button name=go value=1>Go</button> <input type=submit name=other value=Other>\
+    </form>";
+
+#[test]
+fn submits_named_inputs_in_the_query_string() {
+    let server = TestServer::new(&[&ok(FORM), &ok("results")]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/forms/page?old=1")).unwrap())
+        .unwrap();
+
+    let (x, y) = input_at(&browser, "go");
+    assert!(browser.click(x + 2.0, y + 2.0).unwrap());
+
+    assert_eq!(
+        paths(server),
+        [
+            "/forms/page?old=1",
+            // Only the button that was clicked is sent
+            "/forms/search?q=rust+lang&x+y=a%26b%3Dc%2F%C3%A9%2B&go=1",
+        ]
+    );
+}
+
+#[test]
+fn submits_what_was_typed_on_enter() {
+    let server = TestServer::new(&[&ok(FORM), &ok("results")]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/page")).unwrap())
+        .unwrap();
+
+    let (x, y) = input_at(&browser, "q");
+    assert!(browser.click(x + 2.0, y + 2.0).unwrap());
+    assert!(browser.is_editing());
+    browser.input_pop();
+    browser.input_pop();
+    browser.input_pop();
+    browser.input_pop();
+    browser.input_push("ust!");
+    browser.submit_input().unwrap();
+
+    assert_eq!(
+        paths(server),
+        ["/page", "/search?q=rust+ust%21&x+y=a%26b%3Dc%2F%C3%A9%2B"]
+    );
+}
+
+#[test]
+fn password_inputs_show_a_mask_but_submit_what_was_typed() {
+    let form = "<form action=login method=post>\
+        <input name=user value=andy> <input type=password name=pass value=ab>\
+        </form>";
+    let server = TestServer::new(&[&ok(form), &ok("welcome")]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new(&server.url("/")).unwrap()).unwrap();
+
+    let (x, y) = input_at(&browser, "pass");
+    assert!(browser.click(x + 2.0, y + 2.0).unwrap());
+    browser.input_push("c d&");
+
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.contains(
+            "type=password name=\"pass\" value=\"\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\""
+        ),
+        "{}",
+        out
+    );
+    assert!(!out.contains("c d&"));
+
+    browser.submit_input().unwrap();
+    let seen = server.finish();
+    assert!(
+        seen.requests[1].ends_with("\r\n\r\nuser=andy&pass=abc+d%26"),
+        "{}",
+        seen.requests[1]
+    );
+}
+
+#[test]
+fn clicks_elsewhere_stop_editing() {
+    let server = TestServer::new(&[&ok(&format!("text {}", FORM))]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new(&server.url("/")).unwrap()).unwrap();
+
+    let (x, y) = input_at(&browser, "q");
+    assert!(browser.click(x + 2.0, y + 2.0).unwrap());
+    assert!(!browser.click(x - 20.0, y + 2.0).unwrap());
+    assert!(!browser.is_editing());
+    server.finish();
+}
+
+#[test]
+fn posts_forms_with_a_urlencoded_body() {
+    let page = "<form action=/submit method=POST><input name=q value=\"a b&c\"><button>Send</button></form>";
+    let server = TestServer::new(&[&ok(page), &ok("sent")]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/form")).unwrap())
+        .unwrap();
+
+    let (x, y) = input_at(&browser, "q");
+    browser.click(x + 2.0, y + 2.0).unwrap();
+    browser.submit_input().unwrap();
+
+    let seen = server.finish();
+    let (head, body) = seen.requests[1].split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("POST /submit HTTP/1.1\r\n"));
+    assert!(head.contains("\r\nContent-Type: application/x-www-form-urlencoded"));
+    assert_eq!(body, "q=a+b%26c");
+}
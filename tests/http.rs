@@ -0,0 +1,1429 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use browser::{
+    Blocklist, Browser, BrowserError, HttpClient, RequestInterceptor, Response, Sni, TlsError, URL,
+};
+use image::RgbaImage;
+
+use common::{SlowServer, TestServer};
+
+fn get(url: &str) -> Result<String, BrowserError> {
+    URL::new(url).unwrap().request(&mut HttpClient::new(false))
+}
+
+#[test]
+fn reads_a_content_length_body() {
+    let server = TestServer::new(&["HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"]);
+    assert_eq!(get(&server.url("/index.html")).unwrap(), "hello");
+
+    let seen = server.finish();
+    assert!(seen.requests[0].starts_with("GET /index.html HTTP/1.1\r\n"));
+    assert!(seen.requests[0].contains("Host: 127.0.0.1\r\n"));
+}
+
+#[test]
+fn reads_to_close_without_a_length() {
+    let server = TestServer::new(&["HTTP/1.0 200 OK\r\n\r\nuntil the end"]);
+    assert_eq!(get(&server.url("/")).unwrap(), "until the end");
+    server.finish();
+}
+
+#[test]
+fn reads_a_chunked_body() {
+    let server = TestServer::new(&[concat!(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+        "5\r\nhello\r\n",
+        "7;name=value\r\n, world\r\n",
+        "0\r\nTrailer: ignored\r\n\r\n",
+    )]);
+    assert_eq!(get(&server.url("/")).unwrap(), "hello, world");
+    server.finish();
+}
+
+#[test]
+fn rejects_a_bad_chunk_size() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\nzz\r\n",
+    ]);
+    let err = get(&server.url("/")).unwrap_err();
+    assert!(matches!(err, BrowserError::Network(_)), "{:?}", err);
+    server.finish();
+}
+
+#[test]
+fn returns_the_body_of_error_statuses() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found",
+        "HTTP/1.1 500\r\nContent-Length: 6\r\n\r\nbroken",
+    ]);
+    let mut client = HttpClient::new(false);
+    assert_eq!(
+        URL::new(&server.url("/missing"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "not found"
+    );
+    // A status line without a reason phrase is still valid
+    assert_eq!(
+        URL::new(&server.url("/error"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "broken"
+    );
+    server.finish();
+}
+
+#[test]
+fn skips_the_body_of_no_content_responses() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 204 No Content\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext",
+    ]);
+    let mut client = HttpClient::new(false);
+    assert_eq!(
+        URL::new(&server.url("/a"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        ""
+    );
+    assert_eq!(
+        URL::new(&server.url("/b"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "next"
+    );
+    server.finish();
+}
+
+#[test]
+fn reuses_keep_alive_connections() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nsecond\r\n0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nthird",
+    ]);
+    let mut client = HttpClient::new(false);
+    for (path, body) in [("/1", "first"), ("/2", "second"), ("/3", "third")] {
+        assert_eq!(
+            URL::new(&server.url(path))
+                .unwrap()
+                .request(&mut client)
+                .unwrap(),
+            body
+        );
+    }
+
+    let seen = server.finish();
+    assert_eq!(seen.requests.len(), 3);
+    assert_eq!(seen.connections, 1);
+}
+
+#[test]
+fn resends_only_safe_requests_when_an_idle_connection_was_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        // Each connection answers one request as if it would take another,
+        // then closes
+        let mut requests = Vec::new();
+        for response in [
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+            "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond",
+            "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\npart",
+        ] {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if line.contains("HTTP/1.1") {
+                    requests.push(line.clone());
+                }
+            }
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        }
+        (listener, requests)
+    });
+
+    let url = |path: &str| URL::new(&format!("http://127.0.0.1:{port}{path}")).unwrap();
+    let mut client = HttpClient::new(false);
+    assert_eq!(url("/1").request(&mut client).unwrap(), "first");
+    // The GET finds its connection closed and goes again on a new one
+    assert_eq!(url("/2").request(&mut client).unwrap(), "second");
+    // A POST is never sent twice
+    assert!(url("/3").post(&mut client, "text/plain", b"once").is_err());
+    // Nor is a GET whose response had started arriving
+    assert!(url("/4").request(&mut client).is_err());
+
+    let (listener, requests) = server.join().unwrap();
+    assert_eq!(
+        requests,
+        [
+            "GET /1 HTTP/1.1\r\n",
+            "GET /2 HTTP/1.1\r\n",
+            "GET /4 HTTP/1.1\r\n"
+        ]
+    );
+    listener.set_nonblocking(true).unwrap();
+    assert!(listener.accept().is_err());
+}
+
+#[test]
+fn requests_the_same_url_twice() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond",
+    ]);
+    let url = URL::new(&server.url("/same")).unwrap();
+    let mut client = HttpClient::new(false);
+    assert_eq!(url.request(&mut client).unwrap(), "first");
+    assert_eq!(url.request(&mut client).unwrap(), "second");
+    assert_eq!(server.finish().requests.len(), 2);
+}
+
+#[test]
+fn reconnects_after_connection_close() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nfirst",
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond",
+    ]);
+    let mut client = HttpClient::new(false);
+    assert_eq!(
+        URL::new(&server.url("/1"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "first"
+    );
+    assert_eq!(
+        URL::new(&server.url("/2"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "second"
+    );
+    assert_eq!(server.finish().connections, 2);
+}
+
+#[test]
+fn follows_redirects() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: /moved/here\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 302 Found\r\nLocation: there?page=2\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\narrived",
+    ]);
+    assert_eq!(get(&server.url("/start")).unwrap(), "arrived");
+
+    let seen = server.finish();
+    let paths: Vec<&str> = seen
+        .requests
+        .iter()
+        .map(|request| request.split(' ').nth(1).unwrap())
+        .collect();
+    assert_eq!(paths, ["/start", "/moved/here", "/moved/there?page=2"]);
+}
+
+#[test]
+fn follows_redirects_that_only_change_the_query() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 302 Found\r\nLocation: ?page=2\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\narrived",
+    ]);
+    assert_eq!(get(&server.url("/list/items?page=1")).unwrap(), "arrived");
+    assert!(server.finish().requests[1].starts_with("GET /list/items?page=2 "));
+}
+
+#[test]
+fn keeps_the_method_only_for_307_and_308_redirects() {
+    for (status, method, body) in [
+        ("303 See Other", "GET", false),
+        ("302 Found", "GET", false),
+        ("307 Temporary Redirect", "POST", true),
+        ("308 Permanent Redirect", "POST", true),
+    ] {
+        let redirect = format!("HTTP/1.1 {status}\r\nLocation: /done\r\nContent-Length: 0\r\n\r\n");
+        let server = TestServer::new(&[
+            &redirect,
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone",
+        ]);
+        let done = URL::new(&server.url("/form"))
+            .unwrap()
+            .post(
+                &mut HttpClient::new(false),
+                "application/x-www-form-urlencoded",
+                b"a=1",
+            )
+            .unwrap();
+        assert_eq!(done, "done");
+
+        let seen = server.finish();
+        let followed = &seen.requests[1];
+        assert!(
+            followed.starts_with(&format!("{method} /done ")),
+            "{status}: {followed}"
+        );
+        assert_eq!(followed.contains("a=1"), body, "{status}: {followed}");
+    }
+}
+
+#[test]
+fn follows_redirects_to_absolute_urls() {
+    let target = TestServer::new(&["HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfound"]);
+    let redirect = format!(
+        "HTTP/1.1 307 Temporary Redirect\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+        target.url("/elsewhere")
+    );
+    let server = TestServer::new(&[&redirect]);
+    assert_eq!(get(&server.url("/")).unwrap(), "found");
+    server.finish();
+    target.finish();
+}
+
+#[test]
+fn gives_up_on_redirect_loops() {
+    let redirect = "HTTP/1.1 302 Found\r\nLocation: /again\r\nContent-Length: 0\r\n\r\n";
+    let server = TestServer::new(&[redirect; 11]);
+    assert!(get(&server.url("/")).is_err());
+    assert_eq!(server.finish().requests.len(), 11);
+}
+
+#[test]
+fn does_not_redirect_to_local_files() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 302 Found\r\nLocation: file:///etc/passwd\r\nContent-Length: 0\r\n\r\n",
+    ]);
+    let err = get(&server.url("/")).unwrap_err();
+    assert!(matches!(err, BrowserError::Network(_)), "{:?}", err);
+    server.finish();
+}
+
+#[test]
+fn reloading_fetches_the_page_again() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond",
+    ]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/page")).unwrap())
+        .unwrap();
+    browser.reload().unwrap();
+
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("text=\"second\""), "{}", out);
+    assert!(!out.contains("first"));
+
+    let seen = server.finish();
+    assert_eq!(seen.requests.len(), 2);
+    assert!(seen.requests[1].starts_with("GET /page "));
+}
+
+#[test]
+fn revalidates_cached_pages_and_shows_them_when_not_modified() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Tue, 13 Oct 2026 08:00:00 GMT\r\nContent-Length: 6\r\n\r\ncached",
+        "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n",
+    ]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/page")).unwrap())
+        .unwrap();
+    browser.reload().unwrap();
+
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("text=\"cached\""), "{}", out);
+
+    let seen = server.finish();
+    assert_eq!(seen.requests.len(), 2);
+    assert!(!seen.requests[0].contains("If-None-Match"));
+    assert!(seen.requests[1].contains("If-None-Match: \"v1\"\r\n"));
+    assert!(seen.requests[1].contains("If-Modified-Since: Tue, 13 Oct 2026 08:00:00 GMT\r\n"));
+}
+
+#[test]
+fn rejects_invalid_tls_server_names() {
+    for host in ["bad_host!", "a..b", "-"] {
+        let err = get(&format!("https://{}/", host)).unwrap_err();
+        assert!(
+            matches!(&err, BrowserError::Tls(TlsError::InvalidName(name)) if name == host),
+            "{}: {:?}",
+            host,
+            err
+        );
+    }
+    // IP literals are fine as names, so they get as far as connecting
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    let mut client = HttpClient::new(false);
+    client.connect_retries = 0;
+    let err = URL::new(&format!("https://127.0.0.1:{}/", port))
+        .unwrap()
+        .request(&mut client)
+        .unwrap_err();
+    assert!(!matches!(err, BrowserError::Tls(_)), "{:?}", err);
+}
+
+#[test]
+fn shows_secure_connection_failures_as_text() {
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new("https://a<i>b/").unwrap()).unwrap();
+    assert_eq!(
+        browser.extract_text(),
+        "Secure connection failed \"a<i>b\" is not a valid server name"
+    );
+}
+
+#[test]
+fn follows_a_meta_refresh() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 59\r\n\r\n\
+         <meta http-equiv=Refresh content='0; URL=/next'>redirecting",
+        "HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\narrived",
+    ]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new(&server.url("/")).unwrap()).unwrap();
+    assert_eq!(browser.extract_text(), "redirecting");
+
+    // A refresh with no delay is due straight away
+    let due = browser.next_wakeup().unwrap();
+    assert!(due <= Instant::now());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    browser.tick(Instant::now());
+    while browser.is_loading() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+        browser.tick(Instant::now());
+    }
+    assert_eq!(browser.extract_text(), "arrived");
+    assert_eq!(browser.next_wakeup(), None);
+
+    let seen = server.finish();
+    assert!(seen.requests[1].starts_with("GET /next "));
+}
+
+#[test]
+fn ignores_a_meta_refresh_from_the_web_to_a_local_file() {
+    let server = TestServer::new(&["HTTP/1.1 200 OK\r\nContent-Length: 63\r\n\r\n\
+         <meta http-equiv=refresh content='0;url=file:///etc/passwd'>web"]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new(&server.url("/")).unwrap()).unwrap();
+    assert_eq!(browser.next_wakeup(), None);
+    browser.tick(Instant::now());
+    assert_eq!(browser.extract_text(), "web");
+    server.finish();
+}
+
+#[test]
+fn ignores_a_click_on_a_frame_from_the_web_to_a_local_file() {
+    let server = TestServer::new(&["HTTP/1.1 200 OK\r\nContent-Length: 66\r\n\r\n\
+         <iframe src='file:///etc/passwd' width=700 height=500></iframe>web"]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new(&server.url("/")).unwrap()).unwrap();
+    assert!(browser.click(300.0, 300.0).unwrap());
+    assert_eq!(browser.extract_text(), "web");
+    server.finish();
+}
+
+#[test]
+fn waits_for_a_delayed_meta_refresh() {
+    let dir = std::env::temp_dir().join(format!("browser-refresh-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("page.html"),
+        "<meta http-equiv=refresh content=5>waiting",
+    )
+    .unwrap();
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    let loaded = Instant::now();
+    browser
+        .load(URL::new(&format!("file://{}", dir.join("page.html").display())).unwrap())
+        .unwrap();
+
+    // A refresh without a URL loads the page again once it's due
+    let due = browser.next_wakeup().unwrap();
+    assert!(due >= loaded + Duration::from_secs(5));
+    browser.tick(Instant::now());
+    assert_eq!(browser.next_wakeup(), Some(due));
+    fs::write(dir.join("page.html"), "refreshed").unwrap();
+    browser.tick(due);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(browser.extract_text(), "refreshed");
+    assert_eq!(browser.next_wakeup(), None);
+}
+
+#[test]
+fn ignores_a_meta_refresh_too_far_off_to_wait_for() {
+    let dir = std::env::temp_dir().join(format!("browser-refresh-far-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (name, delay) in [
+        ("huge", "100000000000000000000"),
+        ("overflow", &"9".repeat(40)),
+        ("near", "10000000000000000000"),
+    ] {
+        let path = dir.join(format!("{}.html", name));
+        fs::write(
+            &path,
+            format!("<meta http-equiv=refresh content={}>waiting", delay),
+        )
+        .unwrap();
+        let mut browser = Browser::new(800, 600, HttpClient::new(false));
+        browser
+            .load(URL::new(&format!("file://{}", path.display())).unwrap())
+            .unwrap();
+        assert_eq!(browser.next_wakeup(), None, "{}", name);
+        browser.tick(Instant::now());
+        assert_eq!(browser.extract_text(), "waiting");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fetches_the_icon_a_page_links_to() {
+    let mut png = Vec::new();
+    image::RgbaImage::new(16, 16)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let page = "<link rel='shortcut icon' href=icons/site.png>Page";
+    let mut icon = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        png.len()
+    )
+    .into_bytes();
+    icon.extend(png);
+    let server = TestServer::with_bytes(&[
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{page}",
+            page.len()
+        )
+        .as_bytes(),
+        &icon,
+        b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nNo icon",
+        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+    ]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser
+        .load(URL::new(&server.url("/dir/page.html")).unwrap())
+        .unwrap();
+    // It's fetched in the background, and only once per page
+    assert!(browser.favicon().is_none());
+    let favicon = wait_for_favicon(&mut browser).unwrap();
+    assert_eq!(favicon.dimensions(), (16, 16));
+    assert!(browser.favicon().is_some());
+
+    // Without an icon link, the server's /favicon.ico is tried instead
+    browser
+        .load(URL::new(&server.url("/other.html")).unwrap())
+        .unwrap();
+    assert!(wait_for_favicon(&mut browser).is_none());
+
+    let seen = server.finish();
+    assert!(seen.requests[1].starts_with("GET /dir/icons/site.png "));
+    assert!(seen.requests[3].starts_with("GET /favicon.ico "));
+}
+
+/// The browser's favicon once it's done fetching it
+fn wait_for_favicon(browser: &mut Browser) -> Option<Rc<RgbaImage>> {
+    browser.favicon();
+    while browser.next_wakeup().is_some() {
+        thread::sleep(Duration::from_millis(10));
+        browser.tick(Instant::now());
+    }
+    browser.favicon()
+}
+
+#[test]
+fn keeps_decoded_images_for_the_next_page() {
+    let mut png = Vec::new();
+    image::RgbaImage::new(4, 4)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        png.len()
+    )
+    .into_bytes();
+    response.extend(png);
+    let server = TestServer::with_bytes(&[&response, &response]);
+
+    let path =
+        std::env::temp_dir().join(format!("browser-http-{}-cached.html", std::process::id()));
+    fs::write(&path, format!("<img src={:?}>", server.url("/a.png"))).unwrap();
+    let page = URL::new(&format!("file://{}", path.display())).unwrap();
+    let loaded = |browser: &Browser| {
+        let mut out = Vec::new();
+        browser.dump_layout(&mut out).unwrap();
+        String::from_utf8(out).unwrap().contains("loaded=true")
+    };
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.load(page.clone()).unwrap();
+    assert!(loaded(&browser));
+    browser.load(page.clone()).unwrap();
+    assert!(loaded(&browser));
+
+    // Without room for it, it's fetched again
+    browser.set_image_cache_size(0);
+    browser.load(page).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(loaded(&browser));
+    assert_eq!(server.finish().requests.len(), 2);
+}
+
+#[test]
+fn reloading_fetches_the_images_again() {
+    let mut png = Vec::new();
+    image::RgbaImage::new(4, 4)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        png.len()
+    )
+    .into_bytes();
+    response.extend(png);
+    let server = TestServer::with_bytes(&[&response, &response]);
+
+    let path = std::env::temp_dir().join(format!(
+        "browser-http-{}-reload-img.html",
+        std::process::id()
+    ));
+    fs::write(&path, format!("<img src={:?}>", server.url("/a.png"))).unwrap();
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    browser.reload().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("loaded=true"));
+    let seen = server.finish();
+    assert_eq!(seen.requests.len(), 2);
+    assert!(seen.requests[1].starts_with("GET /a.png "));
+}
+
+#[test]
+fn fetches_images_concurrently() {
+    let mut png = Vec::new();
+    image::RgbaImage::new(4, 4)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        png.len()
+    )
+    .into_bytes();
+    response.extend(png);
+    let delay = Duration::from_millis(200);
+    let server = SlowServer::new(response, delay);
+
+    let images = 8;
+    let html: String = (0..images)
+        .map(|n| format!("<img src={:?}>", server.url(&format!("/{}.png", n))))
+        .collect();
+    let path =
+        std::env::temp_dir().join(format!("browser-http-{}-images.html", std::process::id()));
+    fs::write(&path, html).unwrap();
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    let start = Instant::now();
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    let elapsed = start.elapsed();
+    fs::remove_file(&path).unwrap();
+
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.matches("loaded=true").count(), images, "{}", out);
+    // One at a time they'd take `delay` each, but at most six go at once
+    assert!(elapsed < delay * images as u32 / 2, "took {:?}", elapsed);
+    assert!(server.peak() > 1 && server.peak() <= 6, "{}", server.peak());
+}
+
+#[test]
+fn shows_a_page_as_it_arrives() {
+    // The server sends the first half of the page, then waits to be told to
+    // send the rest
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (first, rest) = ("<p>first half</p><p cla", "ss=x>second half</p>");
+    let (send_rest, rest_wanted) = mpsc::channel::<()>();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let stream = reader.get_mut();
+        let length = first.len() + rest.len();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {length}\r\n\r\n{first}"
+        )
+        .unwrap();
+        rest_wanted.recv().unwrap();
+        stream.write_all(rest.as_bytes()).unwrap();
+    });
+
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    let url = format!("http://127.0.0.1:{}/", port);
+    browser.start_load(URL::new(&url).unwrap()).unwrap();
+    let tick_until = |browser: &mut Browser, done: &dyn Fn(&Browser) -> bool| {
+        let start = Instant::now();
+        while !done(browser) {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(10));
+            browser.tick(Instant::now());
+        }
+    };
+
+    tick_until(&mut browser, &|browser| !browser.extract_text().is_empty());
+    assert!(browser.is_loading());
+    // The tag cut off partway isn't shown as text
+    assert_eq!(browser.extract_text(), "first half");
+
+    send_rest.send(()).unwrap();
+    tick_until(&mut browser, &|browser| !browser.is_loading());
+    assert_eq!(browser.extract_text(), "first half\nsecond half");
+    server.join().unwrap();
+}
+
+#[test]
+fn shows_plain_text_as_it_is() {
+    let server = TestServer::new(&[concat!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\n",
+        "Content-Length: 46\r\n\r\n<b>not bold</b>\nnext\n\nafter gap\n    after  gap",
+    )]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/notes")).unwrap())
+        .unwrap();
+
+    assert_eq!(
+        browser.extract_text(),
+        "<b>not bold</b>\nnext\nafter gap\nafter gap"
+    );
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains("style=bold"), "{}", out);
+    let field = |line: usize, name: &str| -> u32 {
+        let line = out.lines().nth(line).unwrap();
+        let start = line.find(&format!("{}=", name)).unwrap() + name.len() + 1;
+        line[start..].split(' ').next().unwrap().parse().unwrap()
+    };
+    let (x, y) = (|line| field(line, "x"), |line| field(line, "y"));
+    // The blank line is kept as a gap between the lines around it
+    assert!(y(3) - y(2) > y(2) - y(0));
+    // And so are the indent and the run of spaces
+    assert!(x(5) > x(3), "{}", out);
+    assert!(x(6) - x(5) > x(4) - x(3), "{}", out);
+    server.finish();
+}
+
+#[test]
+fn pretty_prints_json() {
+    let json = r#"{"b":[1,2],"a":"x"}"#;
+    let server = TestServer::new(&[
+        &format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            json.len(),
+            json
+        ),
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 9\r\n\r\n{\"a\": <b>",
+    ]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&server.url("/data")).unwrap())
+        .unwrap();
+
+    // A line per value, keeping the order the keys were written in
+    assert_eq!(
+        browser.extract_text(),
+        "{\n\"b\": [\n1,\n2\n],\n\"a\": \"x\"\n}"
+    );
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.lines().all(|line| line.contains("font=\"monospace\"")));
+    // Nested values are indented further
+    let x = |text: &str| -> u32 {
+        let text = format!("text={:?}", text);
+        let line = out.lines().find(|line| line.ends_with(&text)).unwrap();
+        line[2..].split(' ').next().unwrap().parse().unwrap()
+    };
+    assert!(x("{") < x(r#""b":"#));
+    assert!(x(r#""b":"#) < x("1,"));
+
+    // JSON that doesn't parse is shown as it is
+    browser
+        .load(URL::new(&server.url("/broken")).unwrap())
+        .unwrap();
+    assert_eq!(browser.extract_text(), "{\"a\": <b>");
+    server.finish();
+}
+
+#[test]
+fn rejects_bodies_over_the_size_limit() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello world",
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n",
+        "HTTP/1.0 200 OK\r\n\r\nhello world",
+        "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\njust right",
+    ]);
+    let mut client = HttpClient::new(false);
+    client.max_body_size = 10;
+    for path in ["/length", "/chunked", "/to-close"] {
+        let err = URL::new(&server.url(path))
+            .unwrap()
+            .request(&mut client)
+            .unwrap_err();
+        assert!(matches!(err, BrowserError::Network(_)), "{}", path);
+        assert_eq!(err.to_string(), "Response too large");
+    }
+    assert_eq!(
+        URL::new(&server.url("/fits"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "just right"
+    );
+    server.finish();
+}
+
+#[test]
+fn keeps_every_value_of_repeated_headers() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nSet-Cookie: a=1; Path=/\r\nContent-Type: text/plain\r\n\
+         set-cookie: b=2\r\nContent-Length: 2\r\n\r\nok",
+    ]);
+    let response = URL::new(&server.url("/"))
+        .unwrap()
+        .request_method(&mut HttpClient::new(false), "GET")
+        .unwrap();
+    assert_eq!(response.header_values("set-cookie"), ["a=1; Path=/", "b=2"]);
+    assert_eq!(response.header("set-cookie"), Some("b=2"));
+    assert_eq!(response.header_values("content-type"), ["text/plain"]);
+    assert!(response.header_values("vary").is_empty());
+}
+
+#[test]
+fn head_requests_return_headers_without_a_body() {
+    // The length describes the body a GET would get, which isn't sent
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 1234\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext",
+    ]);
+    let mut client = HttpClient::new(false);
+    let response = URL::new(&server.url("/page"))
+        .unwrap()
+        .request_method(&mut client, "HEAD")
+        .unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-type"), Some("text/html"));
+    assert_eq!(response.header("content-length"), Some("1234"));
+    assert!(response.body.is_empty());
+    // The connection is still good for another request
+    assert_eq!(
+        URL::new(&server.url("/next"))
+            .unwrap()
+            .request(&mut client)
+            .unwrap(),
+        "next"
+    );
+
+    let seen = server.finish();
+    assert!(seen.requests[0].starts_with("HEAD /page HTTP/1.1\r\n"));
+    assert_eq!(seen.connections, 1);
+}
+
+#[test]
+fn decodes_with_the_charset_a_meta_tag_declares() {
+    // "Привет" in Windows-1251, which isn't valid UTF-8
+    let hello = b"\xCF\xF0\xE8\xE2\xE5\xF2";
+    let pages: Vec<Vec<u8>> = [
+        &b"<head><META Charset=\"windows-1251\"></head>"[..],
+        b"<meta http-equiv=Content-Type content=\"text/html; charset=windows-1251\">",
+    ]
+    .iter()
+    .map(|meta| {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n",
+            meta.len() + hello.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(meta);
+        response.extend_from_slice(hello);
+        response
+    })
+    .collect();
+    let server = TestServer::with_bytes(&pages.iter().map(Vec::as_slice).collect::<Vec<_>>());
+    let mut client = HttpClient::new(false);
+    for path in ["/charset", "/http-equiv"] {
+        let page = URL::new(&server.url(path))
+            .unwrap()
+            .request(&mut client)
+            .unwrap();
+        assert!(page.ends_with(">Привет"), "{:?}", page);
+    }
+    server.finish();
+}
+
+#[test]
+fn prefers_the_charset_in_the_headers() {
+    // "été" in ISO-8859-1, on a page that claims otherwise
+    let body = b"<meta charset=windows-1251>\xE9t\xE9";
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=ISO-8859-1\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    let server = TestServer::with_bytes(&[&response]);
+    let page = get(&server.url("/")).unwrap();
+    assert!(page.ends_with(">été"), "{:?}", page);
+    server.finish();
+}
+
+#[test]
+fn decodes_stylesheets_with_the_charset_in_their_headers() {
+    // A selector for class="café", in ISO-8859-1
+    let css = b"p.caf\xE9 { color: #00f }";
+    let mut stylesheet = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/css; charset=iso-8859-1\r\nContent-Length: {}\r\n\r\n",
+        css.len()
+    )
+    .into_bytes();
+    stylesheet.extend_from_slice(css);
+    let page = "<link rel=stylesheet href=style.css><p class=caf\u{e9}>blue";
+    let page = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+        page.len(),
+        page
+    );
+    let server = TestServer::with_bytes(&[page.as_bytes(), &stylesheet]);
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.load(URL::new(&server.url("/")).unwrap()).unwrap();
+    server.finish();
+
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let line = out
+        .lines()
+        .find(|line| line.ends_with(" text=\"blue\""))
+        .unwrap();
+    assert!(line.contains("color=#0000ff"), "{}", line);
+}
+
+#[test]
+fn a_byte_order_mark_beats_any_declared_charset() {
+    // "été" in UTF-8 after its BOM, on a page whose header and <meta> both
+    // claim something else
+    let body = b"\xEF\xBB\xBF<meta charset=windows-1251>\xC3\xA9t\xC3\xA9";
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=ISO-8859-1\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    let server = TestServer::with_bytes(&[&response]);
+    assert_eq!(
+        get(&server.url("/")).unwrap(),
+        "<meta charset=windows-1251>été"
+    );
+    server.finish();
+}
+
+#[test]
+fn falls_back_to_meta_when_the_header_charset_is_unknown() {
+    let pages: Vec<Vec<u8>> = [
+        // "Привет" in Windows-1251
+        (&b"<meta charset=windows-1251>"[..], &b"\xCF\xF0\xE8\xE2\xE5\xF2"[..]),
+        // A page can't really be UTF-16 if its <meta> could be read as ASCII
+        (b"<meta charset=utf-16>", "Привет".as_bytes()),
+    ]
+    .iter()
+    .map(|(meta, text)| {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=bogus\r\nContent-Length: {}\r\n\r\n",
+            meta.len() + text.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(meta);
+        response.extend_from_slice(text);
+        response
+    })
+    .collect();
+    let server = TestServer::with_bytes(&pages.iter().map(Vec::as_slice).collect::<Vec<_>>());
+    let mut client = HttpClient::new(false);
+    for path in ["/windows-1251", "/utf-16"] {
+        let page = URL::new(&server.url(path))
+            .unwrap()
+            .request(&mut client)
+            .unwrap();
+        assert!(page.ends_with(">Привет"), "{}: {:?}", path, page);
+    }
+    server.finish();
+}
+
+#[test]
+fn defaults_to_utf8() {
+    let server = TestServer::new(&["HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nPrivét"]);
+    assert_eq!(get(&server.url("/")).unwrap(), "Privét");
+    server.finish();
+}
+
+#[test]
+fn posts_a_body_with_its_headers() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 303 See Other\r\nLocation: /done\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nthanks",
+    ]);
+    let body = "name=Jo+Bloggs&note=a%26b";
+    assert_eq!(
+        URL::new(&server.url("/submit"))
+            .unwrap()
+            .post(
+                &mut HttpClient::new(false),
+                "application/x-www-form-urlencoded",
+                body.as_bytes()
+            )
+            .unwrap(),
+        "thanks"
+    );
+
+    let seen = server.finish();
+    let (head, sent) = seen.requests[0].split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("POST /submit HTTP/1.1\r\n"));
+    assert!(head.contains("\r\nContent-Type: application/x-www-form-urlencoded"));
+    assert!(head.contains(&format!("\r\nContent-Length: {}", body.len())));
+    assert_eq!(sent, body);
+    // A 303 is followed with a GET and no body
+    assert!(seen.requests[1].starts_with("GET /done HTTP/1.1\r\n"));
+    assert!(!seen.requests[1].contains("Content-Length"));
+}
+
+#[test]
+fn repeats_the_post_on_a_307() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 307 Temporary Redirect\r\nLocation: /again\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+    ]);
+    URL::new(&server.url("/"))
+        .unwrap()
+        .post(&mut HttpClient::new(false), "text/plain", b"data")
+        .unwrap();
+    let seen = server.finish();
+    assert!(seen.requests[1].starts_with("POST /again HTTP/1.1\r\n"));
+    assert!(seen.requests[1].ends_with("\r\n\r\ndata"));
+}
+
+#[test]
+fn rejects_malformed_urls() {
+    for url in ["example.org/page", "ftp://example.org/", "http://[::1/"] {
+        let err = URL::new(url).unwrap_err();
+        assert!(matches!(err, BrowserError::Url(_)), "{}: {:?}", url, err);
+    }
+}
+
+#[test]
+fn splits_the_port_from_the_host() {
+    for (url, shown) in [
+        ("http://[::1]/", "http://[::1]/"),
+        ("http://[::1]:9000/page", "http://[::1]:9000/page"),
+        ("http://example.org:80/", "http://example.org/"),
+        ("http://example.org:8080", "http://example.org:8080/"),
+    ] {
+        assert_eq!(URL::new(url).unwrap().to_string(), shown);
+    }
+}
+
+#[test]
+fn connects_to_ipv6_literals() {
+    let listener = TcpListener::bind("[::1]:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        while reader.read_line(&mut request).unwrap() > 2 {}
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nv6")
+            .unwrap();
+        request
+    });
+
+    assert_eq!(get(&format!("http://[::1]:{}/", port)).unwrap(), "v6");
+    let request = server.join().unwrap();
+    assert!(request.contains("Host: [::1]\r\n"), "{}", request);
+}
+
+#[test]
+fn rejects_unsupported_schemes() {
+    for (url, scheme) in [
+        ("ftp://example.org/file.txt", "ftp://"),
+        ("gopher://example.org/", "gopher://"),
+        ("mailto:someone@example.org", "mailto:"),
+        ("tel:+15555550100", "tel:"),
+        ("javascript:alert(1)", "javascript:"),
+        ("data:text/html,<p>hi</p>", "data:"),
+    ] {
+        let err = URL::new(url).unwrap_err();
+        assert!(matches!(err, BrowserError::Url(_)), "{}: {:?}", url, err);
+        assert_eq!(
+            err.to_string(),
+            format!("Unsupported URL scheme {}", scheme)
+        );
+    }
+    // A host and port isn't mistaken for a scheme
+    let err = URL::new("localhost:8000/page").unwrap_err();
+    assert!(err.to_string().contains("has no scheme"), "{}", err);
+}
+
+#[test]
+fn shows_an_error_page_for_what_cant_be_loaded() {
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    let address = "mailto:<someone@example.org>";
+    let err = URL::new(address).unwrap_err();
+    browser.show_error(address, &err);
+    assert_eq!(
+        browser.extract_text(),
+        "Couldn't load mailto:<someone@example.org>\nUnsupported URL scheme mailto:"
+    );
+}
+
+#[test]
+fn about_pages_are_made_up_in_process() {
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.load(URL::new("about:version").unwrap()).unwrap();
+    assert!(browser.extract_text().contains(env!("CARGO_PKG_VERSION")));
+
+    // about:blank has no host to connect to, so it can only come from the
+    // browser itself
+    browser.load(URL::new("about:blank").unwrap()).unwrap();
+    let mut layout = Vec::new();
+    browser.dump_layout(&mut layout).unwrap();
+    assert!(layout.is_empty());
+
+    let err = browser
+        .load(URL::new("about:nothing").unwrap())
+        .unwrap_err();
+    assert!(matches!(err, BrowserError::Url(_)), "{:?}", err);
+}
+
+#[test]
+fn rejects_compressed_responses() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 4\r\n\r\nzzzz",
+    ]);
+    let err = get(&server.url("/")).unwrap_err();
+    assert!(matches!(err, BrowserError::Encoding(_)), "{:?}", err);
+    assert_eq!(err.to_string(), "Unsupported Content-Encoding gzip");
+    server.finish();
+}
+
+#[test]
+fn retries_refused_connections_with_backoff() {
+    // Nothing is listening on the port to begin with
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let url = URL::new(&format!("http://127.0.0.1:{port}/")).unwrap();
+    let mut client = HttpClient::new(false);
+    client.connect_retries = 0;
+    let start = Instant::now();
+    assert!(url.request(&mut client).is_err());
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    // Two retries, 50ms and then 100ms later
+    client.connect_retries = 2;
+    client.retry_delay = Duration::from_millis(50);
+    let start = Instant::now();
+    assert!(url.request(&mut client).is_err());
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(150) && elapsed < Duration::from_secs(1),
+        "took {:?}",
+        elapsed
+    );
+
+    // A server that starts listening between attempts is reached
+    let server = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello")
+            .unwrap();
+    });
+    assert_eq!(url.request(&mut client).unwrap(), "hello");
+    server.join().unwrap();
+}
+
+/// Answers requests for /canned itself, and adds a header to the rest
+struct Canned;
+
+impl RequestInterceptor for Canned {
+    fn intercept(
+        &self,
+        method: &str,
+        url: &mut URL,
+        headers: &mut Vec<(String, String)>,
+    ) -> Option<Response> {
+        if url.to_string().ends_with("/canned") {
+            let body = format!("<p>{method} answered offline").into_bytes();
+            return Some(Response::new(200, HashMap::new(), body));
+        }
+        headers.push(("X-Intercepted".to_owned(), "yes".to_owned()));
+        None
+    }
+}
+
+#[test]
+fn interceptors_can_answer_or_change_requests() {
+    // Nothing listens on port 1, so this only loads if nothing is sent
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_interceptor(Canned);
+    browser
+        .load(URL::new("http://127.0.0.1:1/canned").unwrap())
+        .unwrap();
+    assert_eq!(browser.extract_text(), "GET answered offline");
+
+    let server = TestServer::new(&["HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nreal"]);
+    browser
+        .load(URL::new(&server.url("/real")).unwrap())
+        .unwrap();
+    assert_eq!(browser.extract_text(), "real");
+    assert!(
+        server.finish().requests[0].contains("\r\nX-Intercepted: yes\r\n"),
+        "header not sent"
+    );
+}
+
+#[test]
+fn blocked_hosts_are_never_connected_to() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let blocklist = Blocklist::parse("# Trackers\n\n127.0.0.1\nexample.com/ads/\n");
+    let url = |url: &str| URL::new(url).unwrap();
+    assert!(blocklist.blocks(&url("http://cdn.127.0.0.1/")));
+    assert!(blocklist.blocks(&url("https://example.com/ads/banner.png")));
+    assert!(!blocklist.blocks(&url("https://example.com/news/")));
+    assert!(!blocklist.blocks(&url("https://notexample.com/127.0.0.1")));
+
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_interceptor(blocklist);
+    browser
+        .load(url(&format!("http://127.0.0.1:{}/<i>page</i>", port)))
+        .unwrap();
+    // The placeholder shows the URL as it is, markup and all
+    assert_eq!(
+        browser.extract_text(),
+        format!(
+            "Blocked http://127.0.0.1:{}/<i>page</i> is on the blocklist",
+            port
+        )
+    );
+    assert_eq!(
+        listener.accept().unwrap_err().kind(),
+        std::io::ErrorKind::WouldBlock
+    );
+}
+
+/// The start of the TLS handshake a client configured with `sni` sends for
+/// https://localhost/, which names the server in plain text if at all
+fn client_hello(sni: Sni) -> Vec<u8> {
+    // Listen where the client will connect, whether that's IPv4 or IPv6
+    let address = ("localhost", 0).to_socket_addrs().unwrap().next().unwrap();
+    let listener = TcpListener::bind(address).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut hello = vec![0; 4096];
+        let read = std::io::Read::read(&mut stream, &mut hello).unwrap();
+        hello.truncate(read);
+        hello
+    });
+
+    let mut client = HttpClient::new(false);
+    client.connect_retries = 0;
+    client.sni = sni;
+    // The server hangs up without answering
+    assert!(
+        URL::new(&format!("https://localhost:{}/", port))
+            .unwrap()
+            .request(&mut client)
+            .is_err()
+    );
+    server.join().unwrap()
+}
+
+#[test]
+fn names_the_configured_server_when_connecting_over_tls() {
+    let contains = |hello: &[u8], name: &str| {
+        hello
+            .windows(name.len())
+            .any(|part| part == name.as_bytes())
+    };
+
+    let hello = client_hello(Sni::Host);
+    assert!(contains(&hello, "localhost"));
+
+    let hello = client_hello(Sni::Name("cdn.example.test".to_owned()));
+    assert!(contains(&hello, "cdn.example.test"));
+    assert!(!contains(&hello, "localhost"));
+
+    let hello = client_hello(Sni::Off);
+    assert!(!hello.is_empty());
+    assert!(!contains(&hello, "localhost"));
+}
+
+/// A server on localhost with a self-signed certificate, answering each of
+/// `connections` connections over TLS if the client trusts it
+fn self_signed_server(connections: usize) -> (u16, thread::JoinHandle<()>) {
+    let cert = rustls::pki_types::CertificateDer::from(
+        include_bytes!("common/localhost.crt.der").to_vec(),
+    );
+    let key = rustls::pki_types::PrivateKeyDer::try_from(
+        include_bytes!("common/localhost.key.der").to_vec(),
+    )
+    .unwrap();
+    let config = std::sync::Arc::new(
+        rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap(),
+    );
+    let address = ("localhost", 0).to_socket_addrs().unwrap().next().unwrap();
+    let listener = TcpListener::bind(address).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        for _ in 0..connections {
+            let (stream, _) = listener.accept().unwrap();
+            let conn = rustls::ServerConnection::new(config.clone()).unwrap();
+            let mut reader = BufReader::new(rustls::StreamOwned::new(conn, stream));
+            // A client that rejects the certificate hangs up mid-handshake
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                line.clear();
+            }
+            let _ = reader.get_mut().write_all(
+                b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 7\r\n\r\ntrusted",
+            );
+            let _ = reader.get_mut().flush();
+        }
+    });
+    (port, server)
+}
+
+#[test]
+fn accepts_self_signed_certificates_only_when_insecure() {
+    let (port, server) = self_signed_server(2);
+    let url = URL::new(&format!("https://localhost:{}/", port)).unwrap();
+    let err = url.request(&mut HttpClient::new(false)).unwrap_err();
+    assert!(
+        matches!(err, BrowserError::Tls(TlsError::Certificate(..))),
+        "{:?}",
+        err
+    );
+    assert_eq!(url.request(&mut HttpClient::new(true)).unwrap(), "trusted");
+    server.join().unwrap();
+}
+
+#[test]
+fn sends_extra_headers_with_every_request() {
+    let server = TestServer::new(&[
+        "HTTP/1.1 301 Moved\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+    ]);
+    let mut client = HttpClient::new(false);
+    client.add_header("Authorization: Bearer abc:123").unwrap();
+    client.add_header("user-agent:  test-agent/1.0 ").unwrap();
+    client.add_header("Content-Type: text/plain").unwrap();
+    let page = URL::new(&server.url("/"))
+        .unwrap()
+        .post(&mut client, "application/x-www-form-urlencoded", b"a=1")
+        .unwrap();
+    assert_eq!(page, "ok");
+
+    // Redirects carry them too
+    let requests = server.finish().requests;
+    for request in &requests {
+        assert!(request.contains("\r\nAuthorization: Bearer abc:123\r\n"));
+        assert!(request.contains("\r\nuser-agent: test-agent/1.0\r\n"));
+        assert_eq!(request.matches("Host:").count(), 1, "{}", request);
+    }
+    // A body is only ever described by its own headers
+    assert_eq!(requests[0].matches("Content-Type:").count(), 1);
+    assert!(requests[0].contains("\r\nContent-Type: application/x-www-form-urlencoded\r\n"));
+    assert!(requests[1].contains("\r\nContent-Type: text/plain\r\n"));
+
+    // Nor can the headers framing a request or managing its connection be
+    // added
+    for reserved in [
+        "Content-Length: 0",
+        "transfer-encoding: chunked",
+        "Connection: close",
+        "Host: example.test",
+    ] {
+        assert!(
+            matches!(client.add_header(reserved), Err(BrowserError::Request(_))),
+            "{:?}",
+            reserved
+        );
+    }
+
+    for malformed in [
+        "No colon",
+        ": no name",
+        "Bad name: x",
+        "X-Split: a\r\nInjected: b",
+    ] {
+        assert!(
+            matches!(client.add_header(malformed), Err(BrowserError::Request(_))),
+            "{:?}",
+            malformed
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::rc::Rc;
+
+use browser::ImageCache;
+use image::RgbaImage;
+
+/// A blank image taking up `kb` kilobytes once decoded
+fn image(kb: u32) -> Rc<RgbaImage> {
+    Rc::new(RgbaImage::new(16, 16 * kb))
+}
+
+#[test]
+fn drops_the_least_recently_drawn_image_past_the_budget() {
+    let mut cache = ImageCache::new(3 * 1024);
+    cache.insert("a.png".to_owned(), image(1));
+    cache.insert("b.png".to_owned(), image(1));
+    cache.insert("c.png".to_owned(), image(1));
+    assert_eq!(cache.size(), 3 * 1024);
+
+    // Drawing "a" again leaves "b" as the oldest
+    cache.touch("a.png");
+    cache.insert("d.png".to_owned(), image(1));
+    assert!(!cache.contains("b.png"));
+    assert!(
+        ["a.png", "c.png", "d.png"]
+            .iter()
+            .all(|url| cache.contains(url))
+    );
+    assert_eq!(cache.size(), 3 * 1024);
+
+    // As many as it takes go to fit a bigger one, oldest first
+    cache.insert("e.png".to_owned(), image(2));
+    assert!(!cache.contains("c.png") && !cache.contains("a.png"));
+    assert!(cache.contains("d.png") && cache.contains("e.png"));
+    assert_eq!(cache.size(), 3 * 1024);
+}
+
+#[test]
+fn keeps_nothing_bigger_than_the_budget() {
+    let mut cache = ImageCache::new(1024);
+    cache.insert("small.png".to_owned(), image(1));
+    cache.insert("huge.png".to_owned(), image(2));
+    assert!(!cache.contains("huge.png"));
+    assert!(cache.contains("small.png"));
+    assert!(cache.get("huge.png").is_none());
+}
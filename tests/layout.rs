@@ -0,0 +1,1179 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use browser::{Browser, HttpClient, RequestInterceptor, Response, URL};
+use font_kit::family_name::FamilyName;
+use font_kit::source::{Source, SystemSource};
+
+/// A laid out word, parsed back out of `Browser::dump_layout`
+#[derive(Debug)]
+struct Word {
+    x: u32,
+    y: u32,
+    size: u32,
+    style: String,
+    color: String,
+    text: String,
+}
+
+/// Lays out `html` in the bundled font, so positions don't depend on the
+/// fonts installed on the machine
+fn layout(name: &str, html: &str) -> Vec<Word> {
+    layout_with_width(name, html, None)
+}
+
+fn layout_with_width(name: &str, html: &str, max_content_width: Option<u32>) -> Vec<Word> {
+    layout_with(name, html, |browser| {
+        browser.max_content_width = max_content_width
+    })
+}
+
+/// Lays out `html` in the bundled font, after `configure` sets up the browser
+fn layout_with(name: &str, html: &str, configure: impl FnOnce(&mut Browser)) -> Vec<Word> {
+    words(&load(name, html, configure))
+}
+
+/// A browser showing `html` in the bundled font
+fn load(name: &str, html: &str, configure: impl FnOnce(&mut Browser)) -> Browser {
+    let path = std::env::temp_dir().join(format!(
+        "browser-layout-{}-{}.html",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, html).unwrap();
+
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    configure(&mut browser);
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    browser
+}
+
+/// The words laid out on the page currently in `browser`
+fn words(browser: &Browser) -> Vec<Word> {
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains(" text="))
+        .map(|line| {
+            let field = |name: &str| {
+                let start = line.find(&format!("{}=", name)).unwrap() + name.len() + 1;
+                let end = line[start..]
+                    .find(' ')
+                    .map_or(line.len(), |end| start + end);
+                line[start..end].to_owned()
+            };
+            let text = &line[line.find(" text=").unwrap() + 6..];
+            Word {
+                x: field("x").parse().unwrap(),
+                y: field("y").parse().unwrap(),
+                size: field("size").parse().unwrap(),
+                style: field("style"),
+                color: field("color"),
+                text: text.trim_matches('"').to_owned(),
+            }
+        })
+        .collect()
+}
+
+fn word<'a>(words: &'a [Word], text: &str) -> &'a Word {
+    words
+        .iter()
+        .find(|word| word.text == text)
+        .unwrap_or_else(|| panic!("no word {:?} in {:?}", text, words))
+}
+
+#[test]
+fn subscripts_sit_below_the_baseline_in_a_smaller_size() {
+    let words = layout("sub", "H<sub>two</sub>O");
+    let (h, two, o) = (word(&words, "H"), word(&words, "two"), word(&words, "O"));
+    assert!(two.y > h.y);
+    assert!(two.size < h.size);
+    assert!(two.x > h.x && o.x > two.x);
+    // The text after it goes back to normal
+    assert_eq!((o.y, o.size), (h.y, h.size));
+}
+
+#[test]
+fn superscripts_sit_above_the_baseline_in_a_smaller_size() {
+    let words = layout("sup", "x<sup>two</sup> after");
+    let (x, two, after) = (
+        word(&words, "x"),
+        word(&words, "two"),
+        word(&words, "after"),
+    );
+    assert!(two.y < x.y);
+    assert!(two.size < x.size);
+    assert_eq!((after.y, after.size), (x.y, x.size));
+}
+
+#[test]
+fn lines_make_room_for_subscripts() {
+    // <nobr> keeps the first line together while the narrow column wraps the
+    // last word onto a line of its own
+    let plain = layout_with_width("plain-lines", "<nobr>first low</nobr> next", Some(80));
+    let with_sub = layout_with_width(
+        "sub-lines",
+        "<nobr>first <sub>low</sub></nobr> next",
+        Some(80),
+    );
+    let plain_step = word(&plain, "next").y - word(&plain, "first").y;
+    let sub_step = word(&with_sub, "next").y - word(&with_sub, "first").y;
+    assert!(sub_step > plain_step);
+}
+
+#[test]
+fn small_text_stays_at_the_minimum_font_size() {
+    let html = "normal <small>fine <small>print</small></small> x<sub>low</sub>";
+    let words = layout("small", html);
+    let (normal, fine, print) = (
+        word(&words, "normal"),
+        word(&words, "fine"),
+        word(&words, "print"),
+    );
+    assert!(fine.size < normal.size && print.size < fine.size);
+
+    let words = layout_with("small-min", html, |browser| {
+        browser.min_font_size = Some(14)
+    });
+    assert!(words.iter().all(|word| word.size >= 14), "{:?}", words);
+    assert_eq!(word(&words, "normal").size, normal.size);
+    assert_eq!(word(&words, "print").size, 14);
+
+    // Lines of clamped text are spaced like lines at the minimum size
+    let gap = |html: &str| {
+        let words = layout_with("small-lines", html, |browser| {
+            browser.max_content_width = Some(80);
+            browser.min_font_size = Some(16);
+        });
+        word(&words, "second").y - word(&words, "first").y
+    };
+    assert_eq!(gap("<small>first second</small>"), gap("first second"));
+}
+
+#[test]
+fn headings_and_big_text_are_set_larger() {
+    let html = "<h1>one <small>aside</small> title</h1><h2>two</h2><h3>three</h3>\
+                <h6>six</h6><p>body <big>big <big>bigger</big></big> after";
+    let words = layout("heading-sizes", html);
+    let size = |text: &str| word(&words, text).size;
+    assert_eq!(size("body"), 16);
+    assert_eq!((size("one"), size("two")), (32, 24));
+    assert!(size("three") > size("body") && size("six") < size("body"));
+    // Sizes nest, each going back to the one around it
+    assert!(size("aside") < size("one") && size("aside") > size("body"));
+    assert_eq!(size("title"), 32);
+    assert!(size("big") > size("body") && size("bigger") > size("big"));
+    assert_eq!(size("after"), 16);
+    // Larger text takes up taller lines
+    assert!(word(&words, "two").y - word(&words, "one").y > 32);
+}
+
+#[test]
+fn words_too_wide_for_a_line_are_broken_at_its_edge() {
+    let long = "0123456789abcdef".repeat(8);
+    let html = format!("before {} after", long);
+    let words = layout_with_width("overflow", &html, Some(200));
+    let pieces: Vec<&Word> = words
+        .iter()
+        .filter(|word| word.text != "before" && word.text != "after")
+        .collect();
+    assert!(pieces.len() > 2, "{:?}", words);
+    let text: String = pieces.iter().map(|word| word.text.as_str()).collect();
+    assert_eq!(text, long);
+
+    // Each piece starts a line of its own, after the line with "before"
+    let left = word(&words, "before").x;
+    let mut y = word(&words, "before").y;
+    for piece in &pieces {
+        assert_eq!(piece.x, left);
+        assert!(piece.y > y);
+        y = piece.y;
+    }
+    // Text carries on after the last piece, on its line if there's room
+    let (last, after) = (pieces[pieces.len() - 1], word(&words, "after"));
+    assert!(after.y == last.y && after.x > last.x || after.y > last.y && after.x == left);
+}
+
+#[test]
+fn breaks_a_very_long_word_across_many_lines() {
+    let long = "0123456789abcdef".repeat(5000);
+    let words = layout_with_width("very-long", &long, Some(200));
+    assert!(words.len() > 500);
+    let text: String = words.iter().map(|word| word.text.as_str()).collect();
+    assert_eq!(text, long);
+    assert!(words.windows(2).all(|pair| pair[1].y > pair[0].y));
+}
+
+#[test]
+fn font_family_lists_fall_through_to_a_family_that_exists() {
+    // Every glyph is as wide as any other in a monospace font
+    let after = |name: &str, family: &str, text: &str| {
+        let words = layout_with(name, &format!("{} x", text), |browser| {
+            browser.set_font(Some(family), true)
+        });
+        word(&words, "x").x
+    };
+    // Only where the system has a monospace font to fall through to
+    let monospace = SystemSource::new()
+        .select_family_by_generic_name(&FamilyName::Monospace)
+        .is_ok();
+    let family = "No Such Family, monospace";
+    if monospace {
+        assert_eq!(
+            after("mono-narrow", family, "iiiiii"),
+            after("mono-wide", family, "MMMMMM")
+        );
+    }
+    let family = "No Such Family, sans-serif";
+    assert_ne!(
+        after("sans-narrow", family, "iiiiii"),
+        after("sans-wide", family, "MMMMMM")
+    );
+}
+
+#[test]
+fn matches_tags_case_insensitively() {
+    let words = layout("case", "x<SUB >low</SUB> after");
+    let (x, low, after) = (
+        word(&words, "x"),
+        word(&words, "low"),
+        word(&words, "after"),
+    );
+    assert!(low.y > x.y);
+    assert_eq!(after.y, x.y);
+}
+
+#[test]
+fn styles_emphasis_and_underlines() {
+    let words = layout(
+        "emphasis",
+        "plain <em>em</em> <strong>strong</strong> <u>under</u> <s>struck</s> <i><b>both</b></i>",
+    );
+    for (text, style) in [
+        ("plain", "normal"),
+        ("em", "italic"),
+        ("strong", "bold"),
+        ("under", "underline"),
+        ("struck", "strikethrough"),
+        ("both", "bold,italic"),
+    ] {
+        assert_eq!(word(&words, text).style, style, "{:?}", text);
+    }
+}
+
+#[test]
+fn nested_styles_end_with_their_own_element() {
+    let words = layout(
+        "nested",
+        "<b>a <strong>b</strong> c</b> d <em>e <u>f</i> g</u> h",
+    );
+    for (text, style) in [
+        ("a", "bold"),
+        ("b", "bold"),
+        // Still inside the <b>
+        ("c", "bold"),
+        ("d", "normal"),
+        ("e", "italic"),
+        ("f", "italic,underline"),
+        // </i> doesn't close the <em>
+        ("g", "italic,underline"),
+        ("h", "italic"),
+    ] {
+        assert_eq!(word(&words, text).style, style, "{:?}", text);
+    }
+}
+
+#[test]
+fn inherits_color_from_the_body() {
+    let words = layout(
+        "inherit",
+        "<style>body { color: #c00 }</style><p>in <span>nested <b>deep</b></span></p>",
+    );
+    for text in ["in", "nested", "deep"] {
+        assert_eq!(word(&words, text).color, "#cc0000", "{:?}", text);
+    }
+    assert_eq!(word(&words, "deep").style, "bold");
+}
+
+#[test]
+fn cascades_rules_by_specificity() {
+    let words = layout(
+        "cascade",
+        "<style>
+            #id { color: blue }
+            .class { color: green; font-style: italic }
+            p { color: red }
+            p { font-weight: bold }
+            div span { text-decoration: underline }
+            b { font-weight: normal }
+         </style>
+         <p>tag</p>
+         <p class=class>class</p>
+         <p class=class id=id>id</p>
+         <p id=id style='color: rgb(0, 128, 128)'>inline</p>
+         <div><p><span>descendant</span></p></div>
+         <span>outside</span> <b>unbold</b>",
+    );
+    for (text, color, style) in [
+        ("tag", "#ff0000", "bold"),
+        ("class", "#008000", "bold,italic"),
+        ("id", "#0000ff", "bold,italic"),
+        ("inline", "#008080", "bold"),
+        ("descendant", "#ff0000", "bold,underline"),
+        ("outside", "#000000", "normal"),
+        // The page's rules override the browser's own
+        ("unbold", "#000000", "normal"),
+    ] {
+        let word = word(&words, text);
+        assert_eq!(
+            (word.color.as_str(), word.style.as_str()),
+            (color, style),
+            "{:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn leaves_out_elements_that_are_not_displayed() {
+    let plain = layout("display-plain", "<p>one before after</p><p>three</p>");
+    let hidden = layout(
+        "display-none",
+        "<style>.ad { display: none }</style>\
+         <p>one before <span style='display:none'>gone <b>too</b></span>after</p>\
+         <p class=ad>two <span style='display: inline'>inner</span></p><p>three</p>",
+    );
+    let texts: Vec<&str> = hidden.iter().map(|word| word.text.as_str()).collect();
+    assert_eq!(texts, ["one", "before", "after", "three"]);
+    for text in ["after", "three"] {
+        let (plain, hidden) = (word(&plain, text), word(&hidden, text));
+        assert_eq!((hidden.x, hidden.y), (plain.x, plain.y), "{:?}", text);
+    }
+}
+
+#[test]
+fn leaves_a_gap_for_hidden_elements() {
+    let plain = layout("visibility-plain", "before after");
+    let hidden = layout(
+        "visibility-hidden",
+        "before <span style='visibility: hidden'>gap <b style='visibility: visible'>shown</b></span> after",
+    );
+    let texts: Vec<&str> = hidden.iter().map(|word| word.text.as_str()).collect();
+    assert_eq!(texts, ["before", "shown", "after"]);
+    assert_eq!(word(&hidden, "before").x, word(&plain, "before").x);
+    assert!(word(&hidden, "shown").x > word(&plain, "after").x);
+    assert!(word(&hidden, "after").x > word(&hidden, "shown").x);
+}
+
+#[test]
+fn applies_linked_stylesheets() {
+    let dir = std::env::temp_dir().join(format!("browser-css-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("style.css"),
+        "/* linked */ em { color: #00f; font-style: normal }",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("page.html"),
+        "<link rel=stylesheet href=style.css><em>styled</em>",
+    )
+    .unwrap();
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&format!("file://{}", dir.join("page.html").display())).unwrap())
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let words = words(&browser);
+    let styled = word(&words, "styled");
+    assert_eq!(
+        (styled.color.as_str(), styled.style.as_str()),
+        ("#0000ff", "normal")
+    );
+}
+
+#[test]
+fn decodes_stylesheets_with_the_charset_they_declare() {
+    let dir = std::env::temp_dir().join(format!("browser-css-charset-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // A selector for class="café", in windows-1252
+    fs::write(
+        dir.join("style.css"),
+        b"@charset \"windows-1252\"; p.caf\xE9 { color: #00f }",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("page.html"),
+        "<link rel=stylesheet href=style.css><p class=caf\u{e9}>blue",
+    )
+    .unwrap();
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&format!("file://{}", dir.join("page.html").display())).unwrap())
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(word(&words(&browser), "blue").color, "#0000ff");
+}
+
+#[test]
+fn bold_words_are_spaced_like_plain_ones() {
+    let plain = layout("plain-space", "bold after");
+    let bold = layout("bold-space", "<b>bold</b> after");
+    assert_eq!(word(&bold, "bold").style, "bold");
+    // The gap is a space in the regular face, not the bold one, so the next
+    // word starts where it would after a plain word
+    assert_eq!(word(&bold, "after").x, word(&plain, "after").x);
+    assert!(word(&bold, "after").x > word(&bold, "bold").x);
+}
+
+#[test]
+fn a_word_that_exactly_fits_stays_on_the_line() {
+    // How far apart two of the word are is its width plus a space, which is
+    // how much room it takes after the image's own trailing space
+    let words = layout("fit-measure", "word word");
+    let room = words[1].x - words[0].x;
+    let html = "<img width=\"100\" height=\"10\"> word";
+    let fits = layout_with_width("fit-exact", html, Some(100 + room));
+    let wraps = layout_with_width("fit-over", html, Some(100 + room - 1));
+    assert_eq!(fits[0].y, layout("fit-wide", html)[0].y);
+    assert!(wraps[0].y > fits[0].y);
+}
+
+#[test]
+fn caps_and_centers_the_text_column_on_wide_windows() {
+    let html = "word ".repeat(100);
+    let wide = layout_with("uncapped", &html, |browser| {
+        browser.resize_browser(1600, 600)
+    });
+    let capped = layout_with("capped", &html, |browser| {
+        browser.resize_browser(1600, 600);
+        browser.max_content_width = Some(700);
+    });
+
+    // Lines start where a 700px column centered in the window does
+    let left = (1600 - 700) / 2;
+    assert!(
+        capped
+            .iter()
+            .all(|word| word.x >= left && word.x < left + 700)
+    );
+    assert_eq!(capped[0].x, left);
+    let per_line = |words: &[Word]| words.iter().filter(|word| word.y == words[0].y).count();
+    assert!(per_line(&capped) > 1);
+    assert!(per_line(&capped) < per_line(&wide));
+    assert!(capped.last().unwrap().y > wide.last().unwrap().y);
+}
+
+#[test]
+fn nobr_and_nbsp_keep_words_on_one_line() {
+    let words = layout_with_width("nobr", "<nobr>aaa bbb ccc ddd</nobr> eee fff ggg", Some(80));
+    let kept: Vec<u32> = ["aaa", "bbb", "ccc", "ddd"]
+        .iter()
+        .map(|text| word(&words, text).y)
+        .collect();
+    assert!(kept.iter().all(|&y| y == kept[0]), "{:?}", words);
+    // Past the </nobr>, the narrow column wraps as usual
+    assert!(word(&words, "ggg").y > word(&words, "eee").y);
+
+    // Words joined by &nbsp; move to the next line together
+    let words = layout_with_width("nbsp", "xx aaa&nbsp;bbb", Some(80));
+    let (xx, joined) = (word(&words, "xx"), word(&words, r"aaa\u{a0}bbb"));
+    assert_eq!(joined.x, xx.x);
+    assert!(joined.y > xx.y);
+    let words = layout_with_width("no-nbsp", "xx aaa bbb", Some(80));
+    assert_eq!(word(&words, "aaa").y, word(&words, "xx").y);
+}
+
+#[test]
+fn tabs_widen_to_tab_size_spaces() {
+    // Preformatted text keeps every space, so it shows how wide four are
+    let words = layout("tabs", "a\tb<br>a\t\tb<br>\tc<pre>a    b\na        b</pre>");
+    let lines: Vec<(u32, u32)> = words
+        .iter()
+        .filter(|word| word.text == "b")
+        .map(|word| (word.y, word.x))
+        .collect();
+    let gap = |line: usize| {
+        let a = words.iter().find(|word| word.y == lines[line].0).unwrap();
+        lines[line].1 - a.x
+    };
+    // Give or take the rounding of each gap to whole pixels
+    assert_eq!(lines.len(), 4, "{:?}", words);
+    assert!(gap(0).abs_diff(gap(2)) <= 2, "{:?}", words);
+    assert!(gap(1).abs_diff(gap(3)) <= 2, "{:?}", words);
+    assert!(gap(1) > gap(0));
+    // A tab at the start of a line collapses like any other whitespace
+    assert_eq!(word(&words, "c").x, word(&words, "a").x);
+}
+
+#[test]
+fn punctuation_stays_with_the_word_it_touches() {
+    let words = layout("punctuation", "(word). a -- b");
+    let texts: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+    assert_eq!(texts, ["(word).", "a", "--", "b"]);
+}
+
+#[test]
+fn long_words_break_at_wbr() {
+    let html = "example.com/a/long<wbr>/path/to/somewhere";
+    let wide = layout("wbr-wide", html);
+    assert_eq!(wide.len(), 1);
+    // Kept as an invisible break within the one word
+    assert_eq!(
+        wide[0].text,
+        r"example.com/a/long\u{200b}/path/to/somewhere"
+    );
+
+    let narrow = layout_with_width("wbr-narrow", html, Some(200));
+    let (first, rest) = (
+        word(&narrow, "example.com/a/long"),
+        word(&narrow, "/path/to/somewhere"),
+    );
+    assert_eq!(first.x, rest.x);
+    assert!(rest.y > first.y);
+}
+
+#[test]
+fn indents_blockquotes_from_both_sides() {
+    let words = layout_with_width(
+        "blockquote",
+        "before <blockquote>one two three four five six seven eight nine ten \
+         <blockquote>nested</blockquote></blockquote> after",
+        Some(300),
+    );
+    let (before, after, nested) = (
+        word(&words, "before"),
+        word(&words, "after"),
+        word(&words, "nested"),
+    );
+    let quoted: Vec<&Word> = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ]
+    .iter()
+    .map(|text| word(&words, text))
+    .collect();
+
+    // The column is 300px wide in an 800px window, so runs from 250 to 550
+    assert_eq!(before.x, 250);
+    let left = quoted[0].x;
+    assert!(left > before.x);
+    assert!(quoted[0].y > before.y);
+    // Wrapped lines start at the same indent, and stop short of the right
+    // side by as much
+    assert!(quoted.iter().any(|word| word.y > quoted[0].y));
+    for word in &quoted {
+        assert!(word.x >= left);
+        assert!(word.x < 550 - (left - 250), "{:?}", word);
+    }
+    assert!(
+        quoted
+            .iter()
+            .filter(|word| word.y > quoted[0].y)
+            .any(|word| word.x == left)
+    );
+
+    assert!(nested.x > left);
+    assert_eq!(after.x, before.x);
+    assert!(after.y > nested.y);
+}
+
+#[test]
+fn aligns_table_cells_in_columns() {
+    let words = layout(
+        "table",
+        "before <table><tr><td>a</td><td>b</td></tr>\
+         <tr><td>longer cell</td><td>d</td></tr></table> after",
+    );
+    let [before, a, b, longer, cell, d, after] =
+        ["before", "a", "b", "longer", "cell", "d", "after"].map(|text| word(&words, text));
+
+    // Columns line up, the second starting after the widest of the first
+    assert_eq!(a.x, before.x);
+    assert_eq!(longer.x, a.x);
+    assert_eq!(d.x, b.x);
+    assert!(b.x > cell.x);
+    // Rows line up, each below the one before
+    assert!(a.y > before.y);
+    assert_eq!((a.y, cell.y), (b.y, longer.y));
+    assert_eq!(d.y, longer.y);
+    assert!(longer.y > a.y);
+    assert!(after.y > d.y && after.x == before.x);
+}
+
+#[test]
+fn rows_are_as_tall_as_their_tallest_cell() {
+    let words = layout_with_width(
+        "table-wrap",
+        "<table><tr><td>short</td><td>one two three four five six seven eight \
+         nine ten eleven twelve</td></tr><tr><td>next</td></tr></table>",
+        Some(300),
+    );
+    let (short, one, twelve, next) = (
+        word(&words, "short"),
+        word(&words, "one"),
+        word(&words, "twelve"),
+        word(&words, "next"),
+    );
+    // The long cell wraps within its column rather than the page
+    assert!(twelve.y > one.y);
+    assert!(
+        words
+            .iter()
+            .filter(|word| word.y > one.y && word.y < next.y)
+            .all(|word| word.x >= one.x)
+    );
+    assert!(next.y > twelve.y && next.x == short.x);
+}
+
+#[test]
+fn pages_can_restyle_links_and_turn_off_decorations() {
+    let words = layout(
+        "link-style",
+        "<style>
+            a { text-decoration: none; color: #c00 }
+            .plain { text-decoration: none }
+         </style>
+         <a href=elsewhere.html>link</a> <a name=anchor>anchor</a>
+         <s>struck <span class=plain>through</span></s>",
+    );
+    for (text, color, style) in [
+        ("link", "#cc0000", "normal"),
+        ("anchor", "#cc0000", "normal"),
+        // Turning off its own decoration leaves its parent's drawn through it
+        ("through", "#000000", "strikethrough"),
+    ] {
+        let word = word(&words, text);
+        assert_eq!(
+            (word.color.as_str(), word.style.as_str()),
+            (color, style),
+            "{:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn colors_visited_links_differently() {
+    let dir = std::env::temp_dir().join(format!("browser-links-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("seen.html"), "already seen").unwrap();
+    fs::write(
+        dir.join("index.html"),
+        "<a href=seen.html>seen</a> <a href=./new.html>new</a> plain",
+    )
+    .unwrap();
+
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    for page in ["seen.html", "index.html"] {
+        browser
+            .load(URL::new(&format!("file://{}", dir.join(page).display())).unwrap())
+            .unwrap();
+    }
+    fs::remove_dir_all(&dir).unwrap();
+
+    let words = words(&browser);
+    let (seen, new, plain) = (
+        word(&words, "seen"),
+        word(&words, "new"),
+        word(&words, "plain"),
+    );
+    assert_eq!(seen.color, "#551a8b");
+    assert_eq!(new.color, "#0000ee");
+    assert_eq!(plain.color, "#000000");
+    // Links are underlined whether visited or not
+    assert_eq!(
+        (seen.style.as_str(), new.style.as_str()),
+        ("underline", "underline")
+    );
+    assert_eq!(plain.style, "normal");
+}
+
+#[test]
+fn finds_the_link_under_a_point() {
+    let dir = std::env::temp_dir().join(format!("browser-hover-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let page = dir.join("index.html");
+    fs::write(
+        &page,
+        "before <a href=\"docs/page.html\">the link</a> after",
+    )
+    .unwrap();
+
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&format!("file://{}", page.display())).unwrap())
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let words = words(&browser);
+    let (link, after) = (word(&words, "link"), word(&words, "after"));
+    // Dumped positions are of the baseline, so look just above it
+    let href = browser.link_at(link.x as f32 + 2.0, link.y as f32 - 2.0);
+    assert_eq!(
+        href,
+        Some(format!("file://{}/docs/page.html", dir.display()).as_str())
+    );
+    assert_eq!(
+        browser.link_at(after.x as f32 + 2.0, after.y as f32 - 2.0),
+        None
+    );
+}
+
+#[test]
+fn resolves_links_against_the_first_base() {
+    let dir = std::env::temp_dir().join(format!("browser-base-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let page = dir.join("index.html");
+    fs::write(
+        &page,
+        "<head><base href=\"assets/\"><base href=\"/ignored/\"></head>\
+         <a href=\"page.html\">relative</a> <a href=\"/root.html\">absolute</a>",
+    )
+    .unwrap();
+
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser
+        .load(URL::new(&format!("file://{}", page.display())).unwrap())
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let words = words(&browser);
+    let link_at = |text: &str| {
+        let word = word(&words, text);
+        browser
+            .link_at(word.x as f32 + 2.0, word.y as f32 - 2.0)
+            .map(str::to_owned)
+    };
+    assert_eq!(
+        link_at("relative"),
+        Some(format!("file://{}/assets/page.html", dir.display()))
+    );
+    assert_eq!(link_at("absolute"), Some("file:///root.html".to_owned()));
+}
+
+#[test]
+fn resolves_links_with_urls_in_their_query() {
+    let browser = load(
+        "query-url",
+        "<a href=\"/go?to=https://x.com\">redirect</a> \
+         <a href=\"next?at=10:30\">time</a>",
+        |_| {},
+    );
+
+    let words = words(&browser);
+    let link_at = |text: &str| {
+        let word = word(&words, text);
+        browser
+            .link_at(word.x as f32 + 2.0, word.y as f32 - 2.0)
+            .map(str::to_owned)
+    };
+    assert_eq!(
+        link_at("redirect"),
+        Some("file:///go?to=https://x.com".to_owned())
+    );
+    assert_eq!(
+        link_at("time"),
+        Some(format!(
+            "file://{}/next?at=10:30",
+            std::env::temp_dir().display()
+        ))
+    );
+}
+
+#[test]
+fn divs_start_new_lines_without_a_gap() {
+    let words = layout(
+        "div",
+        "<div>one</div><div>two</div><div>three <div>four</div> five</div>",
+    );
+    let lines: Vec<&Word> = ["one", "two", "three", "four", "five"]
+        .iter()
+        .map(|text| word(&words, text))
+        .collect();
+    let (xs, ys): (Vec<u32>, Vec<u32>) = lines.iter().map(|word| (word.x, word.y)).unzip();
+    assert!(xs.iter().all(|&x| x == xs[0]), "{:?}", xs);
+    // Every line is one line height below the last, nested or not
+    let gap = ys[1] - ys[0];
+    assert!(gap > 0);
+    assert!(
+        ys.windows(2).all(|pair| pair[1] - pair[0] == gap),
+        "{:?}",
+        ys
+    );
+}
+
+#[test]
+fn paragraphs_end_without_their_end_tags() {
+    let closed = layout("p-closed", "<p>a</p><p>b</p>");
+    let open = layout("p-open", "<p>a<p>b");
+    let line = layout("p-line", "<p>a<br>b");
+    let gap = |words: &[Word]| word(words, "b").y - word(words, "a").y;
+    // Two paragraph blocks, spaced apart further than two lines
+    assert_eq!(gap(&open), gap(&closed));
+    assert!(gap(&open) > gap(&line));
+    assert_eq!(word(&open, "b").x, word(&open, "a").x);
+}
+
+#[test]
+fn leaves_the_head_out() {
+    let html = "<html><head><title>Title</title><style>p {}</style>stray</head>\
+                <body><p>Shown</p><script>hidden()</script></body></html>";
+    let browser = load("head", html, |_| {});
+    let words = words(&browser);
+    assert_eq!(
+        words
+            .iter()
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>(),
+        ["Shown"]
+    );
+    assert_eq!(browser.title().as_deref(), Some("Title"));
+}
+
+#[test]
+fn line_height_sets_the_gap_between_wrapped_lines() {
+    let gap = |line_height: f32| {
+        let words = layout_with("line-height", "first second", |browser| {
+            browser.max_content_width = Some(80);
+            browser.line_height = line_height;
+        });
+        word(&words, "second").y - word(&words, "first").y
+    };
+    let (tight, normal, double) = (gap(1.0), gap(1.2), gap(2.0));
+    assert!(
+        tight < normal && normal < double,
+        "{tight} {normal} {double}"
+    );
+    // Lines twice as tall are twice as far apart
+    assert!(double.abs_diff(2 * tight) <= 1, "{tight} {double}");
+}
+
+#[test]
+fn jumps_between_headings() {
+    let path = std::env::temp_dir().join(format!("browser-headings-{}.html", std::process::id()));
+    let filler = "words ".repeat(400);
+    fs::write(
+        &path,
+        format!("{filler}<h2>First</h2>{filler}<h3>Second</h3>{filler}"),
+    )
+    .unwrap();
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.smooth_scroll = false;
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let words = words(&browser);
+    let (first, second) = (word(&words, "First").y, word(&words, "Second").y);
+    // Each jump brings the heading's line to the top of the window
+    let at_top = |browser: &Browser, y: u32| {
+        let scroll = browser.scroll_offset();
+        scroll < y && y - scroll < 40
+    };
+
+    browser.next_heading();
+    assert!(
+        at_top(&browser, first),
+        "{} {}",
+        browser.scroll_offset(),
+        first
+    );
+    browser.next_heading();
+    assert!(
+        at_top(&browser, second),
+        "{} {}",
+        browser.scroll_offset(),
+        second
+    );
+    browser.previous_heading();
+    assert!(
+        at_top(&browser, first),
+        "{} {}",
+        browser.scroll_offset(),
+        first
+    );
+    // There's no heading before the first, so it stays put
+    browser.previous_heading();
+    assert!(at_top(&browser, first));
+}
+
+#[test]
+fn extracts_text_a_block_per_line() {
+    // Narrow enough to break the soft hyphenated word across lines
+    let browser = load(
+        "extract",
+        "<h1>The  title</h1><p>First\n   paragraph, <b>bold</b> and hy\u{ad}phen\u{ad}ated.</p>\
+         <p>Second</p><div>In a div</div><table><tr><td>one<td>two</table>after",
+        |browser| browser.max_content_width = Some(70),
+    );
+    assert_eq!(
+        browser.extract_text(),
+        "The title\nFirst paragraph, bold and hyphenated.\nSecond\nIn a div\none\ntwo\nafter"
+    );
+}
+
+#[test]
+fn reader_mode_shows_only_the_article() {
+    let mut browser = load(
+        "reader",
+        "<nav>Home About</nav><main>Main <article>Story <div><article>inner</article></div> \
+         end</article> Comments</main><footer>Footer</footer>",
+        |_| {},
+    );
+    let normal = word(&words(&browser), "Story").size;
+
+    browser.toggle_reader_mode();
+    assert!(browser.is_reader_mode());
+    assert_eq!(browser.extract_text(), "Story\ninner\nend");
+    assert!(word(&words(&browser), "Story").size > normal);
+
+    browser.toggle_reader_mode();
+    assert!(browser.extract_text().starts_with("Home About Main Story"));
+}
+
+#[test]
+fn vertical_text_stacks_glyphs_in_columns_right_to_left() {
+    let browser = load("vertical", "<p>ab cd</p><p>ef</p>", |browser| {
+        browser.vertical_text = true
+    });
+    let words = words(&browser);
+    let texts: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+    assert_eq!(texts, ["a", "b", "c", "d", "e", "f"]);
+
+    // Each character sits below the one before, all down one column
+    let (a, b, c, d) = (&words[0], &words[1], &words[2], &words[3]);
+    assert!([b.x, c.x, d.x].iter().all(|&x| x == a.x));
+    assert!(a.y < b.y && b.y < c.y && c.y < d.y);
+    // The next paragraph is a column further left, starting back at the top
+    let e = &words[4];
+    assert!(e.x < a.x);
+    assert_eq!(e.y, a.y);
+    // Characters are still read as the words they came from
+    assert_eq!(browser.extract_text(), "ab cd\nef");
+}
+
+#[test]
+fn lays_out_again_once_resizing_settles() {
+    let html = "<p>one two three four five six seven eight nine ten</p>";
+    let mut browser = load("resize", html, |_| {});
+    let lines = |browser: &Browser| {
+        let mut ys: Vec<u32> = words(browser).iter().map(|word| word.y).collect();
+        ys.dedup();
+        ys.len()
+    };
+    assert_eq!(lines(&browser), 1);
+
+    let start = Instant::now();
+    for (step, width) in [600, 400, 200, 150].into_iter().enumerate() {
+        browser.resize(width, 600, start + Duration::from_millis(20 * step as u64));
+    }
+    let last = start + Duration::from_millis(60);
+    let settle = Duration::from_millis(100);
+    // Still laid out for the old width while the window is being dragged
+    browser.tick(last + Duration::from_millis(50));
+    assert_eq!(lines(&browser), 1);
+    assert_eq!(browser.next_wakeup(), Some(last + settle));
+
+    browser.tick(last + settle);
+    assert!(lines(&browser) > 1);
+    assert_eq!(browser.next_wakeup(), None);
+}
+
+#[test]
+fn scrolls_by_the_configured_step() {
+    let html = "words ".repeat(2000);
+    let scrolled = |configure: fn(&mut Browser), scroll: fn(&mut Browser)| {
+        let mut browser = load("scroll-step", &html, |browser| {
+            browser.smooth_scroll = false;
+            configure(browser);
+        });
+        scroll(&mut browser);
+        browser.scroll_offset()
+    };
+    assert_eq!(scrolled(|_| {}, Browser::scrolldown), 20);
+    assert_eq!(
+        scrolled(|browser| browser.scroll_step = 50, Browser::scrolldown),
+        50
+    );
+    // Turning the wheel towards you scrolls down, unless it's reversed
+    assert_eq!(
+        scrolled(
+            |browser| browser.scroll_step = 50,
+            |browser| { browser.scroll_wheel(-3.0) }
+        ),
+        150
+    );
+    assert_eq!(
+        scrolled(
+            |browser| {
+                browser.scroll_step = 50;
+                browser.reverse_scroll = true;
+            },
+            |browser| browser.scroll_wheel(3.0)
+        ),
+        150
+    );
+    assert_eq!(scrolled(|_| {}, |browser| browser.scroll_wheel(3.0)), 0);
+}
+
+/// Serves the same page for every URL, without a network
+struct EveryPage;
+
+impl RequestInterceptor for EveryPage {
+    fn intercept(
+        &self,
+        _method: &str,
+        _url: &mut URL,
+        _headers: &mut Vec<(String, String)>,
+    ) -> Option<Response> {
+        Some(Response::new(200, HashMap::new(), b"<p>Zoomed".to_vec()))
+    }
+}
+
+#[test]
+fn remembers_the_zoom_for_each_site() {
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    browser.set_interceptor(EveryPage);
+    // The zoom on arriving at `url`, and the size the page's text is shown at
+    let visit = |browser: &mut Browser, url: &str| {
+        browser.load(URL::new(url).unwrap()).unwrap();
+        (browser.zoom(), word(&words(browser), "Zoomed").size)
+    };
+    let (zoom, size) = visit(&mut browser, "http://a.example/");
+    assert_eq!(zoom, 100);
+
+    browser.zoom_in();
+    browser.zoom_in();
+    assert_eq!(browser.zoom(), 125);
+    let zoomed = word(&words(&browser), "Zoomed").size;
+    assert!(zoomed > size, "{} {}", zoomed, size);
+
+    assert_eq!(visit(&mut browser, "http://b.example/"), (100, size));
+    assert_eq!(visit(&mut browser, "http://a.example/other"), (125, zoomed));
+}
+
+#[test]
+fn images_that_are_not_loaded_are_placeholder_boxes() {
+    let browser = load(
+        "img-placeholder",
+        "<img alt=\"logo\" width=\"100\" height=\"50\"> after<p><img src=\"missing.png\">",
+        |_| {},
+    );
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let boxes: Vec<&str> = out
+        .lines()
+        .filter(|line| line.contains(" image="))
+        .collect();
+    assert_eq!(boxes.len(), 2, "{}", out);
+    assert_eq!(
+        boxes[0],
+        "x=40 y=40 image=100x50 loaded=false alt=Some(\"logo\")"
+    );
+    // Without a size or alt text it's an empty box of the default size
+    assert!(
+        boxes[1].ends_with("image=100x100 loaded=false alt=None"),
+        "{}",
+        boxes[1]
+    );
+
+    // The text after the box carries on past it, on its baseline
+    let words = words(&browser);
+    let after = word(&words, "after");
+    assert!(after.x > 40 + 100);
+    assert_eq!(after.y, 40 + 50);
+}
+
+#[test]
+fn frames_are_placeholders_opening_their_source() {
+    let target = std::env::temp_dir().join(format!("browser-frame-{}.html", std::process::id()));
+    fs::write(&target, "<title>Framed</title>").unwrap();
+    let mut browser = load(
+        "iframe",
+        &format!(
+            "<p><iframe src=\"https://example.com/embed\" width=200 height=100>fallback</iframe>\
+             <iframe src=\"file://{}\"></iframe></p>",
+            target.display()
+        ),
+        |_| {},
+    );
+    let mut out = Vec::new();
+    browser.dump_layout(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let boxes: Vec<&str> = out
+        .lines()
+        .filter(|line| line.contains(" image="))
+        .collect();
+    assert_eq!(boxes.len(), 2, "{}", out);
+    assert!(
+        boxes[0].contains("image=200x100 loaded=false"),
+        "{}",
+        boxes[0]
+    );
+    assert!(boxes[0].ends_with("alt=Some(\"frame: https://example.com/embed\")"));
+    // Without width and height a frame is the usual 300x150
+    assert!(boxes[1].contains("image=300x150"), "{}", boxes[1]);
+    // What's inside a frame is only shown where frames aren't
+    assert!(!out.contains("fallback"));
+
+    // Clicking anywhere in the box opens the frame's source
+    let field = |name: &str| -> f32 {
+        let start = boxes[1].find(&format!("{}=", name)).unwrap() + name.len() + 1;
+        let end = start + boxes[1][start..].find(' ').unwrap();
+        boxes[1][start..end].parse().unwrap()
+    };
+    let (x, y) = (field("x"), field("y"));
+    assert!(browser.click(x + 150.0, y + 100.0).unwrap());
+    fs::remove_file(&target).unwrap();
+    assert_eq!(browser.title().as_deref(), Some("Framed"));
+}
+
+#[test]
+fn keeps_the_first_visible_word_in_view_when_resized() {
+    let html: String = (0..3000).map(|n| format!("w{} ", n)).collect();
+    let mut browser = load("resize-anchor", &html, |browser| {
+        browser.smooth_scroll = false;
+    });
+    for _ in 0..100 {
+        browser.scrolldown();
+    }
+    let scroll = browser.scroll_offset();
+    assert!(scroll > 0);
+    let first = words(&browser)
+        .into_iter()
+        .find(|word| word.y > scroll)
+        .unwrap();
+
+    let now = Instant::now();
+    browser.resize(400, 600, now);
+    browser.tick(now + Duration::from_secs(1));
+    let words = words(&browser);
+    let after = word(&words, &first.text);
+    assert_ne!(after.y, first.y);
+    let scroll = browser.scroll_offset();
+    assert!(
+        (scroll..scroll + 600).contains(&after.y),
+        "{} at {} is out of view at {}",
+        after.text,
+        after.y,
+        scroll
+    );
+}
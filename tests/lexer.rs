@@ -0,0 +1,161 @@
+use browser::{Tag, Token, lex};
+
+fn text(text: &str) -> Token {
+    Token::Text(text.into())
+}
+
+fn tag(text: &str) -> Token {
+    Token::Tag(Tag::parse(text))
+}
+
+#[test]
+fn interleaves_tags_and_text() {
+    assert_eq!(
+        lex("Hello <b>bold</b> and <i>italic</i>!"),
+        [
+            text("Hello "),
+            tag("b"),
+            text("bold"),
+            tag("/b"),
+            text(" and "),
+            tag("i"),
+            text("italic"),
+            tag("/i"),
+            text("!"),
+        ]
+    );
+}
+
+#[test]
+fn keeps_adjacent_tags_without_empty_text() {
+    assert_eq!(
+        lex("<b><i>x</i></b>"),
+        [tag("b"), tag("i"), text("x"), tag("/i"), tag("/b")]
+    );
+}
+
+#[test]
+fn leaves_unclosed_elements_open() {
+    assert_eq!(
+        lex("<b>bold to the end"),
+        [tag("b"), text("bold to the end")]
+    );
+}
+
+#[test]
+fn parses_tag_attributes() {
+    let tokens = lex(r#"<img src="a.png" alt='An &amp; B' width=10>"#);
+    let [Token::Tag(img)] = tokens.as_slice() else {
+        panic!("expected a single tag, got {:?}", tokens);
+    };
+    assert_eq!(img.name, "img");
+    assert_eq!(img.attributes["src"], "a.png");
+    assert_eq!(img.attributes["alt"], "An & B");
+    assert_eq!(img.attributes["width"], "10");
+}
+
+#[test]
+fn decodes_entities_in_text() {
+    assert_eq!(
+        lex("a&lt;b&gt; &amp; &quot;c&quot;&nbsp;d&unknown; &amp"),
+        [text("a<b> & \"c\"\u{a0}d&unknown; &amp")]
+    );
+}
+
+#[test]
+fn drops_comments() {
+    assert_eq!(
+        lex("before<!-- a comment -->after"),
+        [text("before"), text("after")]
+    );
+}
+
+#[test]
+fn comments_can_contain_angle_brackets() {
+    assert_eq!(
+        lex("a<!-- <b>not bold</b> -> still going -->b"),
+        [text("a"), text("b")]
+    );
+    assert_eq!(lex("<!---->x"), [text("x")]);
+}
+
+#[test]
+fn keeps_an_unterminated_tag_at_the_end_as_text() {
+    assert_eq!(
+        lex("before <b>bold</b> <i"),
+        [
+            text("before "),
+            tag("b"),
+            text("bold"),
+            tag("/b"),
+            text(" <i"),
+        ]
+    );
+    assert_eq!(lex("trailing <"), [text("trailing <")]);
+}
+
+#[test]
+fn treats_a_doubled_angle_bracket_as_text() {
+    assert_eq!(lex("a<<b>c"), [text("a<"), tag("b"), text("c")]);
+    assert_eq!(
+        lex("x <y <i>z</i>"),
+        [text("x <y "), tag("i"), text("z"), tag("/i"),]
+    );
+}
+
+#[test]
+fn treats_an_angle_bracket_before_whitespace_as_text() {
+    assert_eq!(lex("a < b"), [text("a < b")]);
+    assert_eq!(lex("a <\tb <b>c"), [text("a <\tb "), tag("b"), text("c")]);
+}
+
+#[test]
+fn treats_a_stray_closing_angle_bracket_as_text() {
+    assert_eq!(lex("a > b"), [text("a > b")]);
+}
+
+#[test]
+fn drops_an_unterminated_comment() {
+    assert_eq!(lex("a<!-- never closed <b>"), [text("a")]);
+}
+
+#[test]
+fn only_starts_tags_at_tag_openers() {
+    assert_eq!(lex("1 < 2"), [text("1 < 2")]);
+    assert_eq!(lex("1<2 and x <= y"), [text("1<2 and x <= y")]);
+    assert_eq!(lex("I <3 it"), [text("I <3 it")]);
+    assert_eq!(lex("a<b>c"), [text("a"), tag("b"), text("c")]);
+    assert_eq!(lex("a</b>c"), [text("a"), tag("/b"), text("c")]);
+    assert_eq!(lex("<!doctype html>x"), [tag("!doctype html"), text("x")]);
+}
+
+#[test]
+fn normalizes_tag_names() {
+    for (text, name, closing) in [
+        ("B", "b", false),
+        ("I ", "i", false),
+        ("/B", "b", true),
+        ("/ i ", "i", true),
+        ("br/", "br", false),
+        ("IMG SRC=a.png", "img", false),
+    ] {
+        let tag = Tag::parse(text);
+        assert_eq!(
+            (tag.name.as_str(), tag.closing),
+            (name, closing),
+            "{:?}",
+            text
+        );
+    }
+    assert_eq!(Tag::parse("IMG SRC=a.png").attributes["src"], "a.png");
+}
+
+#[test]
+fn decodes_numeric_character_references() {
+    assert_eq!(
+        lex("&#233;&#xE9;&#X1F600;&#128512; &#0; &#xD800; &#x110000; &#; &#xZZ; &#65"),
+        [text(
+            "\u{e9}\u{e9}\u{1F600}\u{1F600} \u{fffd} \u{fffd} \u{fffd} &#; &#xZZ; &#65"
+        )]
+    );
+}
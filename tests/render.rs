@@ -0,0 +1,322 @@
+use std::fs;
+use std::ops::RangeInclusive;
+
+use browser::{Browser, HttpClient, URL};
+
+const WIDTH: u32 = 400;
+const HEIGHT: u32 = 120;
+
+/// Draws `html` into a buffer and returns, for each row, the longest run of
+/// dark pixels on it
+fn dark_runs(name: &str, html: &str) -> Vec<u32> {
+    let path = std::env::temp_dir().join(format!(
+        "browser-render-{}-{}.html",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, html).unwrap();
+    let frame =
+        Browser::render_to_buffer(&format!("file://{}", path.display()), WIDTH, HEIGHT).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    frame
+        .chunks(WIDTH as usize * 4)
+        .map(|row| {
+            let (mut longest, mut run) = (0, 0);
+            for pixel in row.chunks(4) {
+                run = if pixel[..3].iter().all(|&c| c < 128) {
+                    run + 1
+                } else {
+                    0
+                };
+                longest = longest.max(run);
+            }
+            longest
+        })
+        .collect()
+}
+
+/// The first and last rows with any dark pixels on them
+fn inked_rows(runs: &[u32]) -> RangeInclusive<usize> {
+    let first = runs.iter().position(|&run| run > 0).unwrap();
+    let last = runs.iter().rposition(|&run| run > 0).unwrap();
+    first..=last
+}
+
+/// The rows with a dark line at least `width` pixels long
+fn line_rows(runs: &[u32], width: u32) -> Vec<usize> {
+    (0..runs.len()).filter(|&row| runs[row] >= width).collect()
+}
+
+/// Draws `html` into a buffer, after `configure` sets up the browser
+fn draw(name: &str, html: &str, configure: impl FnOnce(&mut Browser)) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!(
+        "browser-render-{}-{}.html",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, html).unwrap();
+    let mut browser = Browser::new(WIDTH, HEIGHT, HttpClient::new(false));
+    configure(&mut browser);
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    let mut frame = vec![255; (WIDTH * HEIGHT * 4) as usize];
+    browser.draw(&mut frame);
+    frame
+}
+
+#[test]
+fn underlines_run_below_the_glyphs() {
+    // No descenders, so the plain glyphs all end at the baseline
+    let plain = dark_runs("plain-u", "mmmmmm");
+    let underlined = dark_runs("underlined", "<u>mmmmmm</u>");
+    let glyphs = inked_rows(&plain);
+    let widest = *plain.iter().max().unwrap();
+
+    let lines = line_rows(&underlined, widest * 3);
+    assert!(!lines.is_empty(), "no underline in {:?}", underlined);
+    assert!(lines.iter().all(|row| row > glyphs.end()));
+}
+
+#[test]
+fn strikethroughs_run_through_the_glyphs() {
+    let plain = dark_runs("plain-s", "xxxxxx");
+    let glyphs = inked_rows(&plain);
+    let widest = *plain.iter().max().unwrap();
+    for (name, html) in [("s", "<s>xxxxxx</s>"), ("del", "<del>xxxxxx</del>")] {
+        let struck = dark_runs(name, html);
+        let lines = line_rows(&struck, widest * 3);
+        assert!(!lines.is_empty(), "no line through {:?}", html);
+        assert!(lines.iter().all(|row| glyphs.contains(row)), "{:?}", html);
+    }
+}
+
+#[test]
+fn lines_cut_off_at_the_top_are_still_drawn() {
+    let path =
+        std::env::temp_dir().join(format!("browser-render-{}-scroll.html", std::process::id()));
+    fs::write(&path, "MMMMMM ".repeat(200)).unwrap();
+    let mut browser = Browser::new(WIDTH, HEIGHT, HttpClient::new(false));
+    browser.smooth_scroll = false;
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let draw = |browser: &Browser| {
+        let mut frame = vec![255; (WIDTH * HEIGHT * 4) as usize];
+        browser.draw(&mut frame);
+        frame
+    };
+    let row = WIDTH as usize * 4;
+    let unscrolled = draw(&browser);
+    let mut cut_lines = 0;
+    // Scrolling moves the page up a few pixels at a time, so this lands
+    // partway through lines as well as between them
+    for scroll in (20..HEIGHT as usize).step_by(20) {
+        browser.scrolldown();
+        let scrolled = draw(&browser);
+        assert_eq!(
+            scrolled[..row * (HEIGHT as usize - scroll)],
+            unscrolled[row * scroll..],
+            "scrolled by {}",
+            scroll
+        );
+        cut_lines += scrolled[..row].iter().any(|&c| c < 128) as usize;
+    }
+    assert!(cut_lines > 0, "no scroll position cut through a line");
+}
+
+#[test]
+fn wide_preformatted_text_scrolls_sideways() {
+    let path =
+        std::env::temp_dir().join(format!("browser-render-{}-wide.html", std::process::id()));
+    fs::write(&path, format!("<pre>{}</pre>", "MMMMMM ".repeat(20))).unwrap();
+    let mut browser = Browser::new(WIDTH, HEIGHT, HttpClient::new(false));
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let draw = |browser: &Browser| {
+        let mut frame = vec![255; (WIDTH * HEIGHT * 4) as usize];
+        browser.draw(&mut frame);
+        frame
+    };
+    let unscrolled = draw(&browser);
+    browser.scroll_right();
+    assert_eq!(browser.scroll_x_offset(), 20);
+    let scrolled = draw(&browser);
+    // Above the scrollbar, everything has moved left by the scroll
+    let row = WIDTH as usize * 4;
+    let shift = 20 * 4;
+    for y in 0..HEIGHT as usize - 20 {
+        let (before, after) = (&unscrolled[y * row..][..row], &scrolled[y * row..][..row]);
+        assert_eq!(after[..row - shift], before[shift..], "row {}", y);
+    }
+
+    // Scrolling stops once the end of the line is in view
+    for _ in 0..1000 {
+        browser.scroll_right();
+    }
+    let end = browser.scroll_x_offset();
+    assert!(end > 20 && end < 1000 * 20);
+    browser.scroll_left();
+    assert_eq!(browser.scroll_x_offset(), end - 20);
+}
+
+#[test]
+fn selected_text_is_drawn_over_its_highlight() {
+    let path =
+        std::env::temp_dir().join(format!("browser-render-{}-select.html", std::process::id()));
+    fs::write(&path, "MMMMMM <u>MMMMMM</u>").unwrap();
+    let mut browser = Browser::new(WIDTH, HEIGHT, HttpClient::new(false));
+    browser
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // Solid black pixels are where glyphs and lines fully cover the page
+    let black_and_selected = |browser: &Browser| {
+        let mut frame = vec![255; (WIDTH * HEIGHT * 4) as usize];
+        browser.draw(&mut frame);
+        let count = |color: [u8; 3]| frame.chunks(4).filter(|p| p[..3] == color).count();
+        (count([0, 0, 0]), count([180, 210, 255]))
+    };
+    let (black, selected) = black_and_selected(&browser);
+    assert!(black > 0);
+    assert_eq!(selected, 0);
+
+    browser.start_selection(0.0, 0.0);
+    browser.extend_selection(WIDTH as f32, HEIGHT as f32);
+    assert_eq!(browser.selected_text(), "MMMMMM MMMMMM");
+    let (black_selected, selected) = black_and_selected(&browser);
+    assert!(selected > 0);
+    assert_eq!(black_selected, black);
+}
+
+#[test]
+fn draws_characters_beyond_the_basic_plane() {
+    // Whether or not the font has a glyph for it, the character takes up
+    // room of its own, the same when written out or as a reference
+    let referenced = dark_runs("astral-ref", "a&#x1F600;b &#128512;");
+    let literal = dark_runs("astral-literal", "a\u{1F600}b \u{1F600}");
+    let without = dark_runs("astral-none", "ab");
+    assert_eq!(referenced, literal);
+    assert_ne!(referenced, without);
+}
+
+#[test]
+fn text_without_antialiasing_has_hard_edges() {
+    let path = std::env::temp_dir().join(format!("browser-render-{}-aa.html", std::process::id()));
+    fs::write(&path, "Anti-aliasing off").unwrap();
+    let draw = |configure: fn(&mut Browser)| {
+        let mut browser = Browser::new(WIDTH, HEIGHT, HttpClient::new(false));
+        configure(&mut browser);
+        browser
+            .load(URL::new(&format!("file://{}", path.display())).unwrap())
+            .unwrap();
+        let mut frame = vec![255; (WIDTH * HEIGHT * 4) as usize];
+        browser.draw(&mut frame);
+        frame
+    };
+    // The shade of each pixel, all drawn black on white
+    let shades = |frame: &[u8]| -> Vec<u8> { frame.chunks(4).map(|pixel| pixel[0]).collect() };
+    let smooth = shades(&draw(|_| {}));
+    let hard = shades(&draw(|browser| browser.antialias = false));
+    let heavy = shades(&draw(|browser| browser.gamma = 2.2));
+    fs::remove_file(&path).unwrap();
+
+    assert!(smooth.iter().any(|&shade| shade != 0 && shade != 255));
+    assert!(hard.iter().all(|&shade| shade == 0 || shade == 255));
+    assert!(hard.contains(&0));
+    // A higher gamma inks the same pixels, only darker
+    let ink = |shades: &[u8]| -> u32 { shades.iter().map(|&shade| 255 - shade as u32).sum() };
+    assert!(ink(&heavy) > ink(&smooth));
+}
+
+#[test]
+fn soft_hyphens_only_show_where_a_line_breaks() {
+    let draw = |name: &str, html: &str, max_content_width: Option<u32>| {
+        draw(name, html, |browser| {
+            browser.set_font(None, false);
+            browser.max_content_width = max_content_width;
+        })
+    };
+
+    // Mid-line a soft hyphen takes up no room and draws nothing
+    assert!(draw("shy-line", "hy&shy;phen after", None) == draw("no-shy", "hyphen after", None));
+    // Where the line breaks at one, it's drawn as a hyphen
+    let broken = draw("shy-break", "hyphen&shy;ation", Some(80));
+    assert!(broken == draw("hyphen-br", "hyphen-<br>ation", Some(80)));
+    assert!(broken != draw("no-hyphen-br", "hyphen<br>ation", Some(80)));
+}
+
+#[test]
+fn falls_back_to_the_default_font_for_unknown_families() {
+    let fallback = draw("unknown-family", "Fallback", |browser| {
+        browser.set_font(Some("No Such Family"), true)
+    });
+    assert!(fallback.iter().any(|&c| c < 128));
+    assert!(fallback == draw("default-family", "Fallback", |_| {}));
+}
+
+#[test]
+fn renders_in_the_bundled_font_without_system_fonts() {
+    let bundled = draw("bundled", "Bundled", |browser| {
+        browser.set_font(None, false)
+    });
+    assert!(bundled.iter().any(|&c| c < 128));
+    // Whichever family is asked for, only the bundled font is used
+    for (name, family) in [("bundled-serif", "serif"), ("bundled-mono", "monospace")] {
+        let drawn = draw(name, "Bundled", |browser| {
+            browser.set_font(Some(family), false)
+        });
+        assert!(drawn == bundled, "{}", family);
+    }
+    let drawn = draw("bundled-pre", "<tt>Bundled</tt>", |browser| {
+        browser.set_font(None, false)
+    });
+    assert!(drawn == bundled);
+}
+
+/// How far from the left the ink starts on each row that has any
+fn left_edges(frame: &[u8]) -> Vec<usize> {
+    frame
+        .chunks(WIDTH as usize * 4)
+        .filter_map(|row| {
+            row.chunks(4)
+                .position(|pixel| pixel[..3].iter().all(|&c| c < 128))
+        })
+        .collect()
+}
+
+#[test]
+fn slants_italics_the_font_has_no_face_for() {
+    // The bundled font has only an upright face
+    let bundled = |browser: &mut Browser| browser.set_font(None, false);
+    let upright = left_edges(&draw("upright", "<h1>l</h1>", bundled));
+    let slanted = left_edges(&draw("slanted", "<h1><i>l</i></h1>", bundled));
+    let lean = |edges: &[usize]| edges[0] as i64 - edges[edges.len() - 1] as i64;
+    assert!(lean(&upright).abs() <= 1, "{:?}", upright);
+    // The top of the stroke leans right of its foot
+    assert!(lean(&slanted) > 2, "{:?}", slanted);
+}
+
+#[test]
+fn emboldens_bold_text_the_font_has_no_face_for() {
+    // The bundled font has only a regular weight
+    let bundled = |browser: &mut Browser| browser.set_font(None, false);
+    let ink = |frame: Vec<u8>| {
+        frame
+            .chunks(4)
+            .filter(|pixel| pixel[..3].iter().all(|&c| c < 128))
+            .count()
+    };
+    let regular = ink(draw("regular", "<h1>l</h1>", bundled));
+    let bold = ink(draw("emboldened", "<h1><b>l</b></h1>", bundled));
+    // The stroke is drawn again a pixel over, thickening it
+    assert!(bold > regular, "{} vs {}", bold, regular);
+}
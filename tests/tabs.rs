@@ -0,0 +1,78 @@
+use std::fs;
+
+use browser::{Browser, HttpClient, Tabs, URL};
+
+/// The text laid out in the current tab
+fn text(tabs: &Tabs) -> Vec<String> {
+    let mut out = Vec::new();
+    tabs.active().dump_layout(&mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .filter_map(|line| {
+            Some(
+                line[line.find(" text=")? + 6..]
+                    .trim_matches('"')
+                    .to_owned(),
+            )
+        })
+        .collect()
+}
+
+fn load(tabs: &mut Tabs, name: &str, html: &str) {
+    let path =
+        std::env::temp_dir().join(format!("browser-tabs-{}-{}.html", std::process::id(), name));
+    fs::write(&path, html).unwrap();
+    tabs.active_mut()
+        .load(URL::new(&format!("file://{}", path.display())).unwrap())
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+fn tabs() -> Tabs {
+    let mut browser = Browser::new(800, 600, HttpClient::new(false));
+    browser.set_font(None, false);
+    Tabs::new(browser)
+}
+
+#[test]
+fn each_tab_keeps_its_own_page() {
+    let mut tabs = tabs();
+    load(&mut tabs, "first", "first page");
+
+    tabs.open();
+    assert_eq!((tabs.len(), tabs.active_index()), (2, 1));
+    assert!(text(&tabs).is_empty());
+    load(&mut tabs, "second", "second page");
+
+    // Switching wraps around from the last tab to the first
+    tabs.switch(1);
+    assert_eq!(tabs.active_index(), 0);
+    assert_eq!(text(&tabs), ["first", "page"]);
+    tabs.switch(-1);
+    assert_eq!(text(&tabs), ["second", "page"]);
+
+    tabs.switch(1);
+    assert!(tabs.close());
+    assert_eq!(tabs.len(), 1);
+    assert_eq!(text(&tabs), ["second", "page"]);
+    // The last tab stays open
+    assert!(!tabs.close());
+}
+
+#[test]
+fn clicks_on_the_strip_switch_tabs() {
+    let mut tabs = tabs();
+    tabs.open();
+    tabs.open();
+    assert_eq!(tabs.active_index(), 2);
+
+    assert!(tabs.click(10.0, 5.0));
+    assert_eq!(tabs.active_index(), 0);
+    // Clicks below the strip are for the page, in the page's coordinates
+    assert!(!tabs.click(10.0, 100.0));
+    let (x, y) = tabs.page_position(10.0, 100.0).unwrap();
+    assert_eq!(x, 10.0);
+    assert!(y < 100.0);
+    assert_eq!(tabs.page_position(10.0, 5.0), None);
+}